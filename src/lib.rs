@@ -0,0 +1,63 @@
+//! Weave, embeddable.
+//!
+//! ```
+//! let mut weave = weaver::Weave::new();
+//! weave.run("answer = 40 + 2").unwrap();
+//! assert_eq!(weave.get_global("answer"), Some(weaver::Value::Integer(42)));
+//! ```
+
+pub mod weave;
+
+use crate::weave::compiler::Compiler;
+use crate::weave::vm::vm::VM;
+use crate::weave::Chunk;
+
+pub use crate::weave::vm::types::{NanBoxedValue, Value};
+pub use crate::weave::vm::arena::ArenaStats;
+pub use crate::weave::vm::vm::{MemoryReport, VMError, VMResult};
+
+/// An embedded Weave interpreter. Globals persist between calls to `run`.
+pub struct Weave {
+    vm: VM,
+}
+
+impl Weave {
+    pub fn new() -> Weave {
+        Weave { vm: VM::new(false) }
+    }
+
+    /// Runs `source`, returning the value of its last expression
+    pub fn run(&mut self, source: &str) -> Result<Value, VMError> {
+        let result = self.vm.interpret(source)?;
+        Ok(self.vm.export_value(result))
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.vm.get_global(name).map(|value| self.vm.export_value(value))
+    }
+}
+
+impl Default for Weave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Evaluates `source` in a fresh interpreter
+pub fn eval(source: &str) -> Result<Value, VMError> {
+    Weave::new().run(source)
+}
+
+/// Compiles `source` without running it, returning the script's top-level chunk.
+/// See `Chunk::serialize` for saving it to disk and `VM::interpret_chunk` for running it.
+pub fn compile(source: &str) -> Result<Chunk, VMError> {
+    let script = Compiler::new(source, false).compile()?;
+    Ok(script.chunk)
+}
+
+/// Compiles `source` without running it, returning the bytecode listing for the script
+/// and every function defined in it.
+pub fn disassemble(source: &str) -> Result<String, VMError> {
+    let script = Compiler::new(source, false).compile()?;
+    Ok(script.disassemble_all())
+}
@@ -1,21 +1,26 @@
-use crate::weave::vm::vm::VM;
-use crate::weave::shell::repl::repl;
-use crate::weave::logging::{LoggingConfig, LogLevel, LogFormat};
-
-mod weave;
+use weaver::weave::vm::vm::{VM, VMError, VMResult, DEFAULT_MAX_STACK_SIZE};
+use weaver::weave::Chunk;
+use weaver::weave::shell::repl::{repl, ReplConfig};
+use weaver::weave::logging::{LoggingConfig, LogLevel, LogFormat, RotationPolicy};
+use weaver::log_error;
 use clap::Parser;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 #[derive(Parser)]
 #[command(name = "weaver")]
 #[command(about = "Weaver programming language interpreter")]
 #[command(version)]
+#[command(group(clap::ArgGroup::new("program").args(["file", "eval"])))]
 struct Cli {
     /// Script file to execute (if not provided, starts REPL)
     #[arg(value_name = "FILE")]
     file: Option<PathBuf>,
 
+    /// Run CODE instead of a file, e.g. `weaver -e "puts 2 + 3"`
+    #[arg(short = 'e', long, value_name = "CODE")]
+    eval: Option<String>,
+
     /// Set the logging level
     #[arg(long, value_enum, default_value = "info")]
     log_level: LogLevel,
@@ -31,47 +36,251 @@ struct Cli {
     /// Log output format
     #[arg(long, value_enum, default_value = "text")]
     log_format: LogFormat,
+
+    /// When to rotate the log file
+    #[arg(long, value_enum, default_value = "size")]
+    log_rotation: RotationPolicy,
+
+    /// Maximum number of values the VM stack may hold while running FILE
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_STACK_SIZE)]
+    max_stack_size: usize,
+
+    /// Print `puts` and `echo` output without color, even to a terminal
+    #[arg(long)]
+    no_color: bool,
+
+    /// Time each opcode while running FILE or CODE and print a profile to stderr
+    #[arg(long, requires = "program")]
+    profile: bool,
+
+    /// Compile FILE to bytecode, saved alongside it as a .weavec file, without running it
+    #[arg(long, requires = "file", conflicts_with = "dump_bytecode")]
+    compile: bool,
+
+    /// Compile FILE or CODE, or verify a .weavec FILE, and report any errors without running it
+    #[arg(long, requires = "program", conflicts_with_all = ["compile", "dump_bytecode", "profile"])]
+    check: bool,
+
+    /// Print the compiled bytecode for FILE and exit without running it
+    #[arg(long, requires = "file")]
+    dump_bytecode: bool,
+
+    /// How to print errors from FILE to stderr: as text, or as one JSON object for tooling
+    #[arg(long, value_enum, default_value = "text")]
+    error_format: ErrorFormat,
+
+    /// Arguments passed on to FILE, available to it through `args()`
+    #[arg(last = true, value_name = "ARGS")]
+    script_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
 }
 
 fn main() {
     let cli = Cli::parse();
 
+    // Debug logging also turns on the VM's bytecode and stack tracing
+    let debug_mode = cli.log_level == LogLevel::Debug;
+
     // Create logging configuration from CLI arguments
     let logging_config = LoggingConfig {
         level: cli.log_level,
         console_output: cli.log_console,
         file_path: cli.log_file.map(|p| p.to_string_lossy().to_string()),
         format: cli.log_format,
+        rotation: cli.log_rotation,
     };
 
     // Initialize logging system with config
-    if let Err(e) = crate::weave::logging::init_logging(logging_config) {
+    if let Err(e) = weaver::weave::logging::init_logging(logging_config) {
         eprintln!("FATAL: Failed to initialize logging system: {}", e);
         std::process::exit(1);
     }
     
     // Test log to verify logging is working
-    crate::log_info!("Weaver interpreter starting", version = env!("CARGO_PKG_VERSION"));
+    weaver::log_info!("Weaver interpreter starting", version = env!("CARGO_PKG_VERSION"));
 
-    // Execute file or start REPL based on arguments
-    if let Some(file_path) = cli.file {
-        run_file(&file_path.to_string_lossy());
+    let options = RunOptions {
+        debug_mode,
+        max_stack_size: cli.max_stack_size,
+        no_color: cli.no_color,
+        profile: cli.profile,
+        error_format: cli.error_format,
+        script_args: cli.script_args,
+    };
+
+    // Execute a one-liner, a file, or start REPL based on arguments
+    if let Some(code) = cli.eval {
+        if cli.check {
+            check("<eval>", &code, cli.error_format);
+            return;
+        }
+        eval(&code, options);
+    } else if let Some(file_path) = cli.file {
+        if cli.check {
+            check_file(&file_path.to_string_lossy(), cli.error_format);
+            return;
+        }
+        if cli.dump_bytecode {
+            dump_bytecode(&file_path.to_string_lossy(), cli.error_format);
+            return;
+        }
+        if cli.compile {
+            compile_file(&file_path, cli.error_format);
+            return;
+        }
+        run_file(&file_path.to_string_lossy(), options);
     } else {
-        repl();
+        repl(ReplConfig::from_env());
     }
 }
 
-fn run_file(path: &str) {
-    let file_contents = std::fs::read_to_string(path).unwrap();
-    let mut vm = VM::new();
-    let res = vm.interpret(&file_contents);
+/// How to set up the VM for a script, from the command line
+struct RunOptions {
+    debug_mode: bool,
+    max_stack_size: usize,
+    no_color: bool,
+    profile: bool,
+    error_format: ErrorFormat,
+    script_args: Vec<String>,
+}
+
+fn run_file(path: &str, options: RunOptions) {
+    let error_format = options.error_format;
+    run(path, options, |vm| {
+        // Precompiled bytecode runs as-is; anything else is source
+        if is_bytecode(path) {
+            vm.interpret_chunk(load_chunk(path, error_format))
+        } else {
+            vm.interpret(&read_source(path))
+        }
+    });
+}
+
+fn is_bytecode(path: &str) -> bool {
+    path.ends_with(".weavec")
+}
+
+/// Reads the source file at `path`, exiting with a clean error if it can't be read as text
+fn read_source(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Error reading {}: {}", path, e);
+        exit(66) // EX_NOINPUT
+    })
+}
+
+/// Reads and verifies the compiled chunk at `path`, exiting with `InvalidChunk`'s status if it's malformed
+fn load_chunk(path: &str, error_format: ErrorFormat) -> Chunk {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Error reading {}: {}", path, e);
+        exit(66) // EX_NOINPUT
+    });
+    Chunk::deserialize(&bytes).unwrap_or_else(|msg| {
+        match error_format {
+            ErrorFormat::Text => eprintln!("Error loading {}: {}", path, msg),
+            ErrorFormat::Json => {
+                let mut json = VMError::InvalidChunk.to_json();
+                json["message"] = msg.into();
+                json["file"] = path.into();
+                eprintln!("{}", json);
+            }
+        }
+        exit(VMError::InvalidChunk.exit_code())
+    })
+}
+
+/// Runs source given on the command line, reporting errors against `<eval>`
+fn eval(code: &str, options: RunOptions) {
+    run("<eval>", options, |vm| vm.interpret(code));
+}
+
+/// Runs `program` on a fresh VM, exiting with the error's status if it fails
+fn run(name: &str, options: RunOptions, program: impl FnOnce(&mut VM) -> VMResult) {
+    let mut vm = VM::new(options.debug_mode);
+    vm.set_max_stack_size(options.max_stack_size);
+    vm.set_script_args(options.script_args);
+    if options.no_color {
+        vm.set_color_output(false);
+    }
+    if options.profile {
+        vm.set_profile(true);
+    }
+    let res = program(&mut vm);
+    if options.profile {
+        vm.print_profile();
+    }
     match res {
         Ok(_) => {},
+        // The script asked to stop, so it isn't an error; this is the only place that ends the process
+        Err(VMError::Exit(status)) => exit(status),
         Err(e) => { 
-            log_error!("File execution failed", error = format!("{:?}", e).as_str(), file = path);
-            eprintln!("Error executing {}: {:?}", path, e); 
-            exit(e.exit_code()) 
+            log_error!("File execution failed", error = format!("{:?}", e).as_str(), file = name);
+            report_error("executing", name, &e, options.error_format);
+            exit(e.exit_code())
+        },
+    }
+}
+
+fn compile_file(path: &Path, error_format: ErrorFormat) {
+    let file_contents = read_source(&path.to_string_lossy());
+    let bytes = weaver::compile(&file_contents)
+        .and_then(|chunk| chunk.serialize().map_err(|msg| VMError::CompilationError { line: None, column: None, msg }));
+    match bytes {
+        Ok(bytes) => {
+            let out_path = path.with_extension("weavec");
+            if let Err(e) = std::fs::write(&out_path, bytes) {
+                eprintln!("Error writing {}: {}", out_path.display(), e);
+                exit(74) // EX_IOERR
+            }
+        }
+        Err(e) => {
+            report_error("compiling", &path.to_string_lossy(), &e, error_format);
+            exit(e.exit_code())
         },
     }
 }
 
+/// Checks the file at `path` without running it. Bytecode is already compiled, so it is
+/// only loaded, which verifies it.
+fn check_file(path: &str, error_format: ErrorFormat) {
+    if is_bytecode(path) {
+        load_chunk(path, error_format);
+    } else {
+        check(path, &read_source(path), error_format);
+    }
+}
+
+/// Compiles `source` only, so editors and CI can catch syntax errors without running anything
+fn check(path: &str, source: &str, error_format: ErrorFormat) {
+    if let Err(e) = weaver::compile(source) {
+        report_error("compiling", path, &e, error_format);
+        exit(e.exit_code())
+    }
+}
+
+fn dump_bytecode(path: &str, error_format: ErrorFormat) {
+    let file_contents = read_source(path);
+    match weaver::disassemble(&file_contents) {
+        Ok(listing) => print!("{}", listing),
+        Err(e) => {
+            report_error("compiling", path, &e, error_format);
+            exit(e.exit_code())
+        },
+    }
+}
+
+/// Prints `error` to stderr, either as a line of text or as a JSON object naming `path`
+fn report_error(action: &str, path: &str, error: &VMError, error_format: ErrorFormat) {
+    match error_format {
+        ErrorFormat::Text => eprintln!("Error {} {}: {}", action, path, error),
+        ErrorFormat::Json => {
+            let mut json = error.to_json();
+            json["file"] = path.into();
+            eprintln!("{}", json);
+        }
+    }
+}
@@ -1,4 +1,10 @@
+use std::io::IsTerminal;
 
+/// Whether output to stdout should be colored by default: only when it's a terminal, and
+/// never if the `NO_COLOR` environment variable is set (see https://no-color.org)
+pub fn stdout_supports_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
 
 pub fn green(s: &str) -> String {
     format!("\x1b[32m{s}\x1b[0m")
@@ -1,30 +1,53 @@
 use std::cmp::PartialEq;
+use std::collections::HashSet;
+use std::rc::Rc;
 use crate::weave::compiler::parse_rule::ParseRule;
 use crate::weave::compiler::parser::Parser;
 use crate::weave::compiler::precedence::Precedence;
 use crate::weave::compiler::token::{Token, TokenType};
 use crate::weave::compiler::internal::Scope;
-use crate::weave::vm::types::{WeaveFn, FnClosure, Upvalue, NanBoxedValue, PointerTag};
+use crate::weave::vm::types::{WeaveFn, FnClosure, FnParam, Upvalue, NanBoxedValue, PointerTag};
 use crate::weave::{Chunk, Op};
 use crate::{log_debug, log_info, log_error};
 
-pub type CompileResult = Result<WeaveFn, String>;
- 
-enum FnType {
-    Script,
-    Function
+pub type CompileResult = Result<WeaveFn, CompileError>;
+
+/// The first error found in a script, and the line and column of the token it was found at
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub msg: String,
 }
 
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] {}", self.line, self.msg)
+    }
+}
+ 
 const MAX_UPVALS: usize = 255;
 
+/// Bookkeeping for the innermost loop being compiled, used by `break` and `continue`
+struct LoopContext {
+    start: usize,
+    break_jumps: Vec<usize>,
+    values: usize,  // Values the body has left on the stack so far, popped by break/continue
+}
+
 pub struct Compiler {
     line: usize,
     parser: Parser,
     had_error: bool,
+    first_error: Option<CompileError>,  // The first error reported, which compile() returns
     panic_mode: bool,
     function: WeaveFn,
-    function_type: FnType,
-    scope: Scope
+    scope: Scope,
+    loops: Vec<LoopContext>,
+    const_globals: HashSet<String>,  // Globals declared with `const`
+    can_assign: bool,
+    debug_mode: bool,
+    source: Rc<str>,
 }
 
 pub enum AssignMode {
@@ -43,46 +66,80 @@ impl PartialEq for AssignMode {
 }
 
 impl Compiler {
-    pub fn new(source: &str, _debug_mode: bool) -> Compiler {
+    pub fn new(source: &str, debug_mode: bool) -> Compiler {
+        let source: Rc<str> = source.into();
+        let mut function = WeaveFn::new(String::new(), vec![]);
+        function.source = Some(source.clone());
         Compiler {
             line: 1,
-            parser: Parser::new(source),
+            parser: Parser::new(&source),
             had_error: false,
+            first_error: None,
             panic_mode: false,
-            function: WeaveFn::new(String::new(), vec![]),
-            function_type: FnType::Script,
+            function,
             scope: Scope::new(),
+            loops: vec![],
+            const_globals: HashSet::new(),
+            can_assign: false,
+            debug_mode,
+            source,
         }
     }
     
+    /// Treats `names` as globals already declared `const`, e.g. by earlier scripts run on the same VM
+    pub fn with_const_globals(mut self, names: HashSet<String>) -> Compiler {
+        self.const_globals = names;
+        self
+    }
+
+    /// Every global declared `const`, including the ones passed to `with_const_globals`
+    pub fn const_globals(&self) -> &HashSet<String> {
+        &self.const_globals
+    }
+
     pub fn new_func_compiler(&mut self, name: String, scope: Scope) -> Compiler {
+        let mut function = WeaveFn::new(name, vec![]);
+        function.source = Some(self.source.clone());
         Compiler{
             line: self.line,
             parser: self.parser.clone(),
             had_error: false,
+            first_error: None,
             panic_mode: false,
-            function: WeaveFn::new(name, vec![]),
-            function_type: FnType::Function,
+            function,
             scope,
+            loops: vec![],
+            const_globals: self.const_globals.clone(),
+            can_assign: false,
+            debug_mode: self.debug_mode,
+            source: self.source.clone(),
         }
     }
 
     pub fn compile(&mut self) -> CompileResult {
         self.advance();
+        // Top-level variables are globals rather than stack slots, so the values each statement
+        // leaves behind can be dropped once the next one starts. Only the last statement's value
+        // survives to become the script's result.
+        let mut values = 0;
         while !self.parser.cur_is(TokenType::EOF) {
-            self.declaration();
+            self.pop_values(values);
+            values = self.declaration();
         }
         self.consume(TokenType::EOF, "Expected end of file");
         self.emit_basic_opcode(Op::RETURN);
 
         if self.had_error {
-            let _ = self.current_chunk().disassemble("Chunk Dump");
+            self.disassemble("Chunk Dump");
             self.report_err("Compilation error- see above");
-            return Err("Compilation error".to_string());
+            return Err(self.first_error.clone().unwrap_or_else(|| {
+                let token = self.parser.previous();
+                CompileError { line: token.line, column: token.column, msg: "Compilation error".to_string() }
+            }));
         }
         
         // Disassemble for debugging
-        let _ = self.current_chunk().disassemble("=== Script ===");
+        self.disassemble("=== Script ===");
 
         Ok(self.function.clone())
     }
@@ -93,7 +150,8 @@ impl Compiler {
                 match token.token_type {
                     TokenType::ERROR => self.report_err_at(&token, "Parsing error"),
                     _ => {
-                        self.line = token.line;
+                        // Code emitted from here on belongs to the token just consumed, not the lookahead
+                        self.line = self.parser.previous().line;
                         break;
                     }
                 }
@@ -109,6 +167,25 @@ impl Compiler {
         }
     }
 
+    /// Consumes the delimiter closing `what`, which was opened on `opened_at`. Running out of
+    /// source first is reported against the opener, since that's where the mistake usually is.
+    fn consume_closing(&mut self, token_type: TokenType, what: &str, opened_at: usize, message: &str) {
+        if self.parser.cur_is(TokenType::EOF) {
+            let eof = self.parser.peek();
+            self.report_err_at(&eof, &format!("Unterminated {} starting at line {}", what, opened_at));
+        } else {
+            self.consume(token_type, message);
+        }
+    }
+
+    fn disassemble(&self, name: &str) {
+        if self.debug_mode {
+            let mut listing = Vec::new();
+            let _ = self.function.disassemble_to(&mut listing);
+            log_debug!("Disassembly", chunk_name = name, listing = String::from_utf8_lossy(&listing).as_ref());
+        }
+    }
+
     fn current_chunk(&mut self) -> &mut Chunk {
         &mut self.function.chunk
     }
@@ -125,26 +202,33 @@ impl Compiler {
         log_error!("Compilation error", 
             message = message, 
             line = token.line, 
+            column = token.column,
             lexeme = format!("{}", token.lexeme).as_str()
         );
         self.had_error = true;
         self.panic_mode = true;
+        if self.first_error.is_none() {
+            self.first_error = Some(CompileError { line: token.line, column: token.column, msg: message.to_string() });
+        }
     }
 
     pub fn expression(&mut self) {
         log_debug!("Parsing expression", current_token = format!("{:?}", self.parser.peek_type()).as_str());
         self.print_progress();
         self.parse_precedence(Precedence::ASSIGNMENT);
-        self.check(TokenType::Semicolon);
     }
 
-    pub fn declaration(&mut self) {
+    /// Compiles one declaration, returning how many values it leaves on the stack
+    pub fn declaration(&mut self) -> usize {
         log_debug!("Parsing declaration", current_token = format!("{:?}", self.parser.peek_type()).as_str());
         self.print_progress();
 
         if self.panic_mode { self.synchronize(); }
 
-        self.statement();
+        let values = self.statement();
+        // Only a whole statement may end in `;`, so one nested inside it can't swallow it
+        self.check(TokenType::Semicolon);
+        values
     }
 
     pub fn variable(&mut self, assign_mode: AssignMode) {
@@ -157,7 +241,9 @@ impl Compiler {
         // The third case requires that there is _not_ an equal sign after the identifier.
         // So we have to consume the identifier... then see what comes next to know what
         // to emit!
-        if self.parser.peek_type() == TokenType::Equal && assign_mode == AssignMode::Yes {
+        let is_assignment = matches!(self.parser.peek_type(),
+            TokenType::Equal | TokenType::PlusEqual | TokenType::MinusEqual | TokenType::StarEqual | TokenType::SlashEqual);
+        if is_assignment && assign_mode == AssignMode::Yes {
             self.variable_set();
         } else {
             self.variable_get();
@@ -167,6 +253,10 @@ impl Compiler {
     fn variable_get(&mut self) {
         log_debug!("Compiling variable get", variable = format!("{}", self.parser.previous()).as_str(), line = self.line);
         let identifier = self.parser.previous().lexeme.lexeme().to_string();
+        self.get_named_variable(identifier);
+    }
+
+    fn get_named_variable(&mut self, identifier: String) {
         let idx = self.resolve_local(identifier.as_str());
         if idx.is_some() {
             log_debug!("Local variable found", identifier = identifier, index = idx, scope_depth = self.scope.depth);
@@ -178,10 +268,8 @@ impl Compiler {
                 log_debug!("Upvalue variable found", identifier = identifier.as_str(), upvalue_index = upval_ref.idx);
                 self.emit_opcode(Op::GetUpvalue, &vec![upval.unwrap().idx]);
             } else {
-                let line = self.line;
                 log_debug!("Using global variable lookup", identifier = identifier.as_str(), scope_depth = self.scope.depth);
-                self.current_chunk().emit_constant(NanBoxedValue::string(identifier.into()), line);
-                self.emit_basic_opcode(Op::GetGlobal);
+                self.emit_global(Op::GetGlobalByIndex, identifier);
             }
         }
     }
@@ -189,11 +277,26 @@ impl Compiler {
     fn variable_set(&mut self) {
         log_debug!("Compiling variable definition", variable = format!("{}", self.parser.previous()).as_str(), line = self.line);
 
-        let identifier = self.parser.previous();
-        self.consume(TokenType::Equal, "Expected assignment in declaration");
+        let identifier = self.parser.previous().lexeme.lexeme().to_string();
+        self.advance(); // consume the assignment operator
+        let operator = match self.parser.previous().token_type {
+            TokenType::PlusEqual => Some(Op::ADD),
+            TokenType::MinusEqual => Some(Op::SUB),
+            TokenType::StarEqual => Some(Op::MUL),
+            TokenType::SlashEqual => Some(Op::DIV),
+            _ => None,
+        };
+
+        // Compound assignment (x += y) desugars to x = x + y
+        if operator.is_some() {
+            self.get_named_variable(identifier.clone());
+        }
         self.expression(); // Compile the expression
+        if let Some(op) = operator {
+            self.emit_basic_opcode(op);
+        }
 
-        self.set_named_variable(identifier.lexeme.lexeme().to_string());
+        self.set_named_variable(identifier);
     }
 
     pub(crate) fn resolve_local(&self, identifier: &str) -> Option<isize> {
@@ -210,7 +313,23 @@ impl Compiler {
         self.scope.resolve_upvalue(identifier)
     }
 
+    fn is_constant(&self, identifier: &str) -> bool {
+        if self.scope.depth > 0 {
+            match self.resolve_local(identifier) {
+                Some(idx) => self.scope.is_const_local(idx as usize),
+                None => self.scope.is_const_upvalue(identifier),
+            }
+        } else {
+            self.const_globals.contains(identifier)
+        }
+    }
+
     fn set_named_variable(&mut self, identifier: String) {
+        if self.is_constant(identifier.as_str()) {
+            self.report_err(format!("Cannot reassign constant '{}'", identifier).as_str());
+            return;
+        }
+
         if self.scope.depth > 0 {
             let idx = self.resolve_local(identifier.as_str());
             if idx.is_some() {
@@ -223,21 +342,19 @@ impl Compiler {
                         self.emit_opcode(Op::SetUpvalue, &[idx].to_vec());
                     }
                     None => {
-                        let local_id = self.add_local(identifier);
+                        let local_id = self.add_local(identifier, false);
                         self.emit_opcode(Op::SetLocal, &[local_id as u8].to_vec());
                     }
                 }
             }
         } else {
-            let line = self.line;
-            self.current_chunk().emit_constant(NanBoxedValue::string(identifier), line);
-            self.emit_basic_opcode(Op::SetGlobal);
+            self.emit_global(Op::SetGlobalByIndex, identifier);
         }
     }
 
-    fn add_local(&mut self, identifier: String) -> usize {
+    fn add_local(&mut self, identifier: String, is_const: bool) -> usize {
         // Create new variable
-        let slot = self.scope.declare_local(identifier.clone());
+        let slot = self.scope.declare_local(identifier.clone(), is_const);
         log_info!("ADDED LOCAL VARIABLE", 
             identifier = identifier.as_str(), 
             assigned_slot = slot,
@@ -247,36 +364,84 @@ impl Compiler {
         slot
     }
 
-    pub fn statement(&mut self) {
+    /// Compiles one statement, returning how many values it leaves on the stack.
+    /// Expressions (assignments included) leave their value; loops and jumps leave nothing.
+    pub fn statement(&mut self) -> usize {
         log_debug!("Parsing statement", current_token = format!("{:?}", self.parser.peek_type()).as_str());
         self.print_progress();
 
         if self.check(TokenType::Puts) {
             self.puts_statement();
+            1
+        } else if self.check(TokenType::Echo) {
+            self.echo_statement();
+            1
         } else if self.check(TokenType::Return) {
             self.return_statement();
+            0
+        } else if self.check(TokenType::Const) {
+            self.const_statement();
+            1
         } else if self.check(TokenType::If) {
-            self.if_statement();
-        } else if self.check(TokenType::FN) {
+            self.if_statement()
+        } else if self.parser.cur_is(TokenType::FN) && self.parser.peek_second_type() == TokenType::Identifier {
+            // Without a name, `fn` starts an anonymous function expression instead
+            self.advance();
             self.function_statement();
+            1
         } else if self.check(TokenType::While) {
             self.while_statement();
+            0
+        } else if self.check(TokenType::For) {
+            self.for_statement();
+            0
+        } else if self.check(TokenType::Break) {
+            self.break_statement();
+            0
+        } else if self.check(TokenType::Continue) {
+            self.continue_statement();
+            0
         } else {
             self.expression_statement();
+            1
         }
     }
 
     fn return_statement(&mut self) {
-        match self.function_type{
-            FnType::Script => self.report_err("Can't return from script"),
-            FnType::Function => {
-                if self.check(TokenType::Semicolon) {
-                    self.emit_basic_opcode(Op::RETURN);
-                } else {
-                    self.expression();
-                    self.emit_basic_opcode(Op::RETURN);
-                }
-            }
+        // A return at script level ends the program with that value, just like the implicit
+        // RETURN at end of file. A bare `return` yields null rather than whatever is on the stack.
+        let bare = self.check(TokenType::Semicolon)
+            || self.parser.cur_is(TokenType::RightBrace)
+            || self.parser.cur_is(TokenType::EOF);
+        if bare {
+            self.emit_constant(NanBoxedValue::null());
+        } else {
+            self.expression();
+        }
+        self.emit_basic_opcode(Op::RETURN);
+    }
+
+    fn const_statement(&mut self) {
+        // const x = y
+        self.consume(TokenType::Identifier, "Expected constant name");
+        let identifier = self.parser.previous().lexeme.lexeme().to_string();
+        if self.is_constant(identifier.as_str()) {
+            self.report_err(format!("Cannot reassign constant '{}'", identifier).as_str());
+            return;
+        }
+        if self.scope.depth > 0 && self.resolve_local(identifier.as_str()).is_some() {
+            self.report_err(format!("Cannot redeclare '{}' as a constant", identifier).as_str());
+            return;
+        }
+        self.consume(TokenType::Equal, "Expected '=' after constant name");
+        self.expression();
+
+        if self.scope.depth > 0 {
+            let local_id = self.add_local(identifier, true);
+            self.emit_opcode(Op::SetLocal, &[local_id as u8].to_vec());
+        } else {
+            self.emit_global(Op::SetGlobalByIndex, identifier.clone());
+            self.const_globals.insert(identifier);
         }
     }
 
@@ -293,7 +458,8 @@ impl Compiler {
         
         // Use enter_function_scope() instead of enter_scope() to prevent scope state accumulation
         // between sequential function compilations while preserving upvalue resolution
-        let new_scope = self.scope.enter_function_scope();
+        let mut new_scope = self.scope.enter_function_scope();
+        new_scope.name_function(fn_name.lexeme.lexeme());
         log_info!("SCOPE STATE AFTER enter_function_scope", 
             depth = new_scope.depth, 
             stack_len = new_scope.debug_stack_len()
@@ -303,6 +469,8 @@ impl Compiler {
         func_compiler.function(); // compile function
 
         self.parser = func_compiler.parser;  // leap forward to the end of the function
+        self.had_error |= func_compiler.had_error;  // errors inside the body fail the whole compile
+        self.first_error = self.first_error.take().or(func_compiler.first_error);
 
         self.emit_closure(func_compiler.function, func_compiler.scope.depth as usize);
         self.set_named_variable(fn_name.lexeme.lexeme().to_string());
@@ -314,12 +482,35 @@ impl Compiler {
         );
     }
 
+    /// `fn` in value position: `fn(n) { n + 1 }` or `fn name(n) { ... }`, leaving the closure on
+    /// the stack. A name is only bound inside the function itself, so the function can call itself.
+    pub fn function_expression(&mut self, _assign_mode: AssignMode) {
+        log_debug!("Compiling function expression");
+        let mut new_scope = self.scope.enter_function_scope();
+        let name = if self.check(TokenType::Identifier) {
+            let name = self.parser.previous().lexeme.lexeme().to_string();
+            new_scope.name_function(name.as_str());
+            name
+        } else {
+            "<fn>".to_string()
+        };
+        let mut func_compiler = self.new_func_compiler(name, new_scope);
+        func_compiler.function();
+
+        self.parser = func_compiler.parser;  // leap forward to the end of the function
+        self.had_error |= func_compiler.had_error;  // errors inside the body fail the whole compile
+        self.first_error = self.first_error.take().or(func_compiler.first_error);
+
+        self.emit_closure(func_compiler.function, func_compiler.scope.depth as usize);
+        self.scope.exit_scope();
+    }
+
     fn function(&mut self) {
         log_debug!("Compiling function implementation", function_name = self.function.name.as_str());
-        self.begin_scope();
         self.consume(TokenType::LeftParen, "Expected '(' after function name");
         self.function_params();
         self.consume(TokenType::RightParen, "Expected ')' after function params");
+        self.function.start_body();
         
         self.consume(TokenType::LeftBrace, "Expected '{' before function body");
         self.block();
@@ -328,15 +519,16 @@ impl Compiler {
         self.emit_basic_opcode(Op::RETURN);
         
         log_info!("Function compilation complete", function_name = self.function.name.as_str());
-        let _ = self.function.chunk.disassemble(self.function.name.as_str());
+        let name = self.function.name.clone();
+        self.disassemble(name.as_str());
     }
 
     fn lambda_function(&mut self) {
         log_debug!("Compiling lambda function implementation");
-        self.begin_scope();
         self.consume(TokenType::LeftParen, "Expected '(' in lambda");
         self.function_params();
         self.consume(TokenType::RightParen, "Expected ')' after lambda params");
+        self.function.start_body();
         
         self.consume(TokenType::LeftBrace, "Expected '{' before lambda body");
         self.block();
@@ -345,15 +537,28 @@ impl Compiler {
         self.emit_basic_opcode(Op::RETURN);
         
         log_info!("Lambda compilation complete");
-        let _ = self.function.chunk.disassemble("<lambda>");
+        self.disassemble("<lambda>");
     }
 
     fn function_params(&mut self) {
         if !self.parser.cur_is(TokenType::RightParen) {
             loop {
                 self.consume(TokenType::Identifier, "Expected parameter name");
-                self.function.arity += 1;
-                self.add_local(self.parser.previous().lexeme.lexeme().to_string());
+                let name = self.parser.previous().lexeme.lexeme().to_string();
+                let default = if self.check(TokenType::Equal) {
+                    // Compiled inline as the prologue. Its value lands in this param's slot, and
+                    // the param isn't declared yet so the default can only see earlier params.
+                    let entry = self.current_chunk().code.len();
+                    self.expression();
+                    Some(entry)
+                } else {
+                    if self.function.required_arity() < self.function.arity {
+                        self.report_err("Parameters without defaults must come before those with defaults");
+                    }
+                    None
+                };
+                self.function.add_param(FnParam::new(name.clone(), default));
+                self.add_local(name, false);
                 if !self.check(TokenType::Comma) { break; }
             }
         }
@@ -374,7 +579,13 @@ impl Compiler {
         let closure_box = Box::new(closure);
         let closure_ptr = Box::into_raw(closure_box) as *const ();
         let closure_nan_boxed = NanBoxedValue::pointer(closure_ptr, PointerTag::Closure);
-        let closure_idx = self.current_chunk().add_constant_only(closure_nan_boxed);
+        let closure_idx = match self.current_chunk().add_constant_only(closure_nan_boxed) {
+            Ok(idx) => idx,
+            Err(msg) => {
+                self.report_err(msg);
+                return;
+            }
+        };
         
         // Emit the closure constant index as part of the Closure instruction
         self.emit_bytes((closure_idx as u16).to_be_bytes().to_vec());
@@ -394,6 +605,7 @@ impl Compiler {
     }
 
     fn arg_count(&mut self) -> u8 {
+        let opened_at = self.parser.previous().line;
         let mut arg_count = 0;
         if !self.parser.cur_is(TokenType::RightParen) {
             loop {
@@ -402,26 +614,131 @@ impl Compiler {
                 if !self.check(TokenType::Comma) { break; }
             }
         }
-        self.consume(TokenType::RightParen, "Expected ')' after arguments");
+        self.consume_closing(TokenType::RightParen, "argument list", opened_at, "Expected ')' after arguments");
         arg_count
     }
 
     fn while_statement(&mut self) {
-        let loop_start = self.current_chunk().code.len() - 1;
+        let loop_start = self.current_chunk().code.len();
         self.expression_statement(); // condition
         let exit_jump = self.emit_jump(Op::JumpIfFalse);
         // JumpIfFalse now pops the condition automatically
 
         self.consume(TokenType::LeftBrace, "Expected Block after condition");
-        self.block(); // Block now manages its own stack, returns last expression
-        self.emit_basic_opcode(Op::POP); // Pop the block result since while loop discards it
+        self.begin_loop(loop_start);
+        let body_values = self.block();
+        self.pop_values(body_values); // Every iteration must start from the same stack height
         self.emit_loop(loop_start);
 
         self.patch_jump(exit_jump);
         // No need to pop - JumpIfFalse already handled it
+        self.end_loop();
     }
 
-    fn if_statement(&mut self) {
+    fn for_statement(&mut self) {
+        // Weave scopes variables to the enclosing function, so the loop variable simply
+        // becomes a local (or global at the top level) rather than opening a scope of its own.
+        self.consume(TokenType::LeftParen, "Expected '(' after 'for'");
+
+        // Initializer
+        if !self.check(TokenType::Semicolon) {
+            self.expression();
+            self.emit_basic_opcode(Op::POP);
+            self.consume(TokenType::Semicolon, "Expected ';' after loop initializer");
+        }
+
+        let mut loop_start = self.current_chunk().code.len();
+
+        // Condition - an empty condition loops forever
+        let exit_jump = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expected ';' after loop condition");
+            Some(self.emit_jump(Op::JumpIfFalse))
+        };
+
+        // Increment - runs after the body, so jump over it on the way in
+        if !self.parser.cur_is(TokenType::RightParen) {
+            let body_jump = self.emit_jump(Op::Jump);
+            let increment_start = self.current_chunk().code.len();
+            self.parse_precedence(Precedence::ASSIGNMENT);
+            self.emit_basic_opcode(Op::POP);
+            self.emit_loop(loop_start);
+
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after for clauses");
+
+        self.consume(TokenType::LeftBrace, "Expected Block after for clauses");
+        self.begin_loop(loop_start);
+        let body_values = self.block();
+        self.pop_values(body_values); // Every iteration must start from the same stack height
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+        }
+        self.end_loop();
+    }
+
+    fn begin_loop(&mut self, start: usize) {
+        self.loops.push(LoopContext { start, break_jumps: vec![], values: 0 });
+    }
+
+    /// Values the innermost loop's body has left on the stack at this point
+    fn loop_values(&self) -> usize {
+        self.loops.last().map_or(0, |ctx| ctx.values)
+    }
+
+    fn set_loop_values(&mut self, values: usize) {
+        if let Some(ctx) = self.loops.last_mut() {
+            ctx.values = values;
+        }
+    }
+
+    fn pop_values(&mut self, count: usize) {
+        for _ in 0..count {
+            self.emit_basic_opcode(Op::POP);
+        }
+    }
+
+    fn end_loop(&mut self) {
+        // Every break lands just past the loop, alongside the condition's exit jump
+        if let Some(ctx) = self.loops.pop() {
+            for jump in ctx.break_jumps {
+                self.patch_jump(jump);
+            }
+        }
+    }
+
+    fn break_statement(&mut self) {
+        self.check(TokenType::Semicolon);
+        if self.loops.is_empty() {
+            self.report_err("break outside of loop");
+            return;
+        }
+        self.pop_values(self.loop_values());
+        let jump = self.emit_jump(Op::Jump);
+        self.loops.last_mut().unwrap().break_jumps.push(jump);
+    }
+
+    fn continue_statement(&mut self) {
+        self.check(TokenType::Semicolon);
+        match self.loops.last() {
+            Some(ctx) => {
+                let start = ctx.start;
+                self.pop_values(self.loop_values());
+                self.emit_loop(start);
+            }
+            None => self.report_err("continue outside of loop"),
+        }
+    }
+
+    /// Returns how many values the statement leaves on the stack, which is the same
+    /// whichever branch runs
+    fn if_statement(&mut self) -> usize {
         self.expression_statement();  // Condition
 
         // Set up the jump to evaluate the condition
@@ -430,24 +747,42 @@ impl Compiler {
 
         // Compile the 'then' block
         self.consume(TokenType::LeftBrace, "Expected Block after condition");
-        self.block();
+        let values_before = self.loop_values();
+        let then_values = self.block();
 
         // Skip the 'else' block when the condition is true
         let else_jump = self.emit_jump(Op::Jump);
         self.patch_jump(then_jump);
 
         // No need to pop - JumpIfFalse already handled it
-        if self.check(TokenType::Else) {
-            // Compile the 'else' block
-            self.consume(TokenType::LeftBrace, "Expected Block after 'else'");
-            self.block();
+        self.set_loop_values(values_before);
+        let else_values = if self.check(TokenType::Else) {
+            if self.check(TokenType::If) {
+                // 'else if' - the nested if patches its own jumps
+                self.if_statement()
+            } else {
+                // Compile the 'else' block
+                self.consume(TokenType::LeftBrace, "Expected Block after 'else'");
+                self.block()
+            }
+        } else {
+            0
+        };
+
+        // The else path has to match the then path, so code after the if knows the stack height
+        for _ in else_values..then_values {
+            self.emit_constant(NanBoxedValue::null());
         }
+        self.pop_values(else_values.saturating_sub(then_values));
         self.patch_jump(else_jump);
+
+        // Our caller accounts for the values we leave behind
+        self.set_loop_values(values_before);
+        then_values
     }
 
     fn expression_statement(&mut self) {
         self.expression();
-        self.check(TokenType::Semicolon);
     }
 
     fn check(&mut self, token: TokenType) -> bool {
@@ -461,31 +796,46 @@ impl Compiler {
     }
 
     fn puts_statement(&mut self) {
-        self.expression();
-        self.emit_basic_opcode(Op::PRINT);
+        match self.output_values() {
+            Some(1) => self.emit_basic_opcode(Op::PRINT),
+            Some(count) => self.emit_opcode(Op::PrintN, &vec![count]),
+            None => self.report_err("Too many values in puts"),
+        }
     }
 
-    fn begin_scope(&mut self) {
-        log_debug!("Beginning new scope", new_depth = self.scope.depth + 1);
-        self.scope.enter_scope();
+    /// Like `puts`, but without the trailing newline
+    fn echo_statement(&mut self) {
+        match self.output_values() {
+            Some(count) => self.emit_opcode(Op::WRITE, &vec![count]),
+            None => self.report_err("Too many values in echo"),
+        }
     }
 
-    fn end_scope(&mut self) {
-        log_debug!("Exiting scope", depth = self.scope.depth);
-        self.scope.exit_scope();
-        
-        self.emit_basic_opcode(Op::RETURN);  // Implicit return when exiting a scope
-        
-        for _ in 0..self.scope.locals_at(self.scope.depth) {
-            self.emit_basic_opcode(Op::POP);
+    /// Compiles the comma-separated values of a `puts` or `echo`, returning how many there
+    /// were, or None if there are too many to fit in the opcode's count byte
+    fn output_values(&mut self) -> Option<u8> {
+        self.expression();
+        let mut count = 1;
+        while self.check(TokenType::Comma) {
+            self.expression();
+            count += 1;
         }
-        self.scope.pop_scope();
+        u8::try_from(count).ok()
     }
 
-    fn block(&mut self) {
+    /// Compiles statements up to the closing brace, returning how many values they leave on the stack
+    fn block(&mut self) -> usize {
+        let opened_at = self.parser.previous().line;
         let mut _expression_count = 0;
+        let mut values = 0;
         while !self.parser.cur_is(TokenType::RightBrace) && !self.parser.cur_is(TokenType::EOF) {
-            self.declaration();
+            // The previous statement's values are discarded once another follows it, e.g. the
+            // result of a bare `f()`. Locals are unaffected: they live in their own slots, below
+            // the copy an assignment leaves on top of the stack.
+            self.pop_values(values);
+            self.set_loop_values(self.loop_values().saturating_sub(values));
+            values = self.declaration();
+            self.set_loop_values(self.loop_values() + values);
             _expression_count += 1;
         }
 
@@ -518,7 +868,8 @@ impl Compiler {
             // }
         }
 
-        self.consume(TokenType::RightBrace, "Expected '}' after block");
+        self.consume_closing(TokenType::RightBrace, "block", opened_at, "Expected '}' after block");
+        values
     }
 
     fn synchronize(&mut self) {
@@ -530,7 +881,7 @@ impl Compiler {
             }
 
             match self.parser.peek_type() {
-                TokenType::FN | TokenType::Puts | TokenType::If | TokenType::Return => return,
+                TokenType::FN | TokenType::Puts | TokenType::Echo | TokenType::If | TokenType::Return => return,
                 _ => (),
             }
 
@@ -555,8 +906,10 @@ impl Compiler {
             None => self.report_err(&format!("Expected prefix expression for token {}", self.parser.previous())),
         }
 
-        while precedence <= self.parser.peek().token_type.precedence() {
+        while precedence <= self.infix_precedence() {
             self.advance();
+            // Infix rules don't take an AssignMode, so let index assignment (a[i] = v) peek at it here
+            self.can_assign = precedence <= Precedence::ASSIGNMENT;
             match ParseRule::for_token(self.parser.previous().token_type).infix {
                 Some(infix) => infix(self),
                 None => self.report_err("Expected Infix expression"),
@@ -564,9 +917,24 @@ impl Compiler {
         }
     }
 
+    /// Precedence of the upcoming token as an infix operator.
+    /// Some operators can also begin an expression, so at the start of a line they begin a new
+    /// statement rather than continuing the last one: `^` starts a lambda, `(` a grouping,
+    /// `[` an array and `-` a negation.
+    fn infix_precedence(&self) -> Precedence {
+        let next = self.parser.peek();
+        let starts_expression = matches!(next.token_type,
+            TokenType::Caret | TokenType::LeftParen | TokenType::LeftBracket | TokenType::Minus);
+        if starts_expression && self.parser.peek_starts_line() {
+            return Precedence::NONE;
+        }
+        next.token_type.precedence()
+    }
+
     pub(crate) fn grouping(&mut self, _assign_mode: AssignMode) {
+        let opened_at = self.parser.previous().line;
         self.expression();
-        self.consume(TokenType::RightParen, "Expected ')' after expression");
+        self.consume_closing(TokenType::RightParen, "'('", opened_at, "Expected ')' after expression");
     }
 
     pub(crate) fn unary(&mut self, _assign_mode: AssignMode) {
@@ -575,7 +943,7 @@ impl Compiler {
         self.parse_precedence(Precedence::UNARY);
 
         match operator {
-            TokenType::Bang => self.emit_basic_opcode(Op::NOT),
+            TokenType::Bang | TokenType::Not => self.emit_basic_opcode(Op::NOT),
             TokenType::Minus => self.emit_basic_opcode(Op::NEGATE),
             _ => unreachable!("Not a unary operator"),
         }
@@ -589,20 +957,100 @@ impl Compiler {
             _ => unreachable!("Not a literal"),
         }
     }
+
+    pub fn null_literal(&mut self, _assign_mode: AssignMode) {
+        self.emit_constant(NanBoxedValue::null());
+    }
     
+    pub fn array(&mut self, _assign_mode: AssignMode) {
+        log_debug!("Compiling array literal");
+        let mut count: usize = 0;
+        while !self.parser.cur_is(TokenType::RightBracket) {
+            self.expression();
+            count += 1;
+            if !self.check(TokenType::Comma) { break; }
+        }
+        self.consume(TokenType::RightBracket, "Expected ']' after array elements");
+
+        if count > u16::MAX as usize {
+            self.report_err("Too many elements in array literal");
+            return;
+        }
+        self.emit_opcode(Op::BuildArray, &(count as u16).to_be_bytes().to_vec());
+    }
+
+    pub fn map(&mut self, _assign_mode: AssignMode) {
+        log_debug!("Compiling map literal");
+        let mut count: usize = 0;
+        while !self.parser.cur_is(TokenType::RightBrace) {
+            self.expression();
+            self.consume(TokenType::Colon, "Expected ':' after map key");
+            self.expression();
+            count += 1;
+            if !self.check(TokenType::Comma) { break; }
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after map entries");
+
+        if count > u16::MAX as usize {
+            self.report_err("Too many entries in map literal");
+            return;
+        }
+        self.emit_opcode(Op::BuildMap, &(count as u16).to_be_bytes().to_vec());
+    }
+
+    pub fn index(&mut self) {
+        log_debug!("Compiling index expression");
+        let can_assign = self.can_assign;
+        self.expression();
+        // `[a:b]` slices. The end may be left out, in which case it's passed as null.
+        // (A leading ':' is left free for symbol keys - see docs/syntax.md)
+        if self.check(TokenType::Colon) {
+            if self.parser.cur_is(TokenType::RightBracket) {
+                self.emit_constant(NanBoxedValue::null());
+            } else {
+                self.expression();
+            }
+            self.consume(TokenType::RightBracket, "Expected ']' after slice");
+            self.emit_basic_opcode(Op::Slice);
+            return;
+        }
+        self.consume(TokenType::RightBracket, "Expected ']' after index");
+
+        if can_assign && self.check(TokenType::Equal) {
+            self.expression();
+            self.emit_basic_opcode(Op::SetIndex);
+        } else {
+            self.emit_basic_opcode(Op::Index);
+        }
+    }
+
     pub fn log_and(&mut self) {
+        // A falsy left operand is the result, like `||`'s truthy one. JumpIfFalse pops what it
+        // tests, so it tests a copy; otherwise the left operand is dropped for the right one.
+        self.emit_basic_opcode(Op::DUP);
         let end_jump = self.emit_jump(Op::JumpIfFalse);
-        self.emit_basic_opcode(Op::POP);  // Pop the condition off the stack()
-        self.parse_precedence(Precedence::AND);
+        self.emit_basic_opcode(Op::POP);
+        self.parse_precedence(Precedence::AND.next());
         self.patch_jump(end_jump);
     }
     
     pub fn log_or(&mut self) {
+        // A truthy left operand is the result; otherwise it is dropped for the right one
+        let end_jump = self.emit_jump(Op::JumpIfTrue);
+        self.emit_basic_opcode(Op::POP);
+        self.parse_precedence(Precedence::OR.next());
+        self.patch_jump(end_jump);
+    }
+
+    pub fn ternary(&mut self) {
+        // JumpIfFalse pops the condition, so only the chosen branch's value is left behind
         let else_jump = self.emit_jump(Op::JumpIfFalse);
+        self.parse_precedence(Precedence::TERNARY);
+        self.consume(TokenType::Colon, "Expected ':' in conditional expression");
         let end_jump = self.emit_jump(Op::Jump);
         self.patch_jump(else_jump);
-        self.emit_basic_opcode(Op::POP);  // Pop the condition off the stack()
-        self.parse_precedence(Precedence::OR);
+        // Parsing the else branch at the same level lets `a ? b : c ? d : e` nest to the right
+        self.parse_precedence(Precedence::TERNARY);
         self.patch_jump(end_jump);
     }
 
@@ -610,25 +1058,32 @@ impl Compiler {
         log_debug!("Compiling binary expression", operator = format!("{:?}", self.parser.previous().token_type).as_str());
         let operator = self.parser.previous().token_type;
         let rule = ParseRule::for_token(operator);
+        if rule.precedence == Precedence::COMPARISON {
+            return self.comparison(operator);
+        }
 
         self.parse_precedence(rule.precedence.next());
+        self.binary_op(operator);
+    }
 
+    /// Emits the opcodes for a binary operator whose operands are already on the stack
+    fn binary_op(&mut self, operator: TokenType) {
         match operator {
             TokenType::Plus => self.emit_basic_opcode(Op::ADD),
             TokenType::Minus => self.emit_basic_opcode(Op::SUB),
             TokenType::Slash => self.emit_basic_opcode(Op::DIV),
             TokenType::Star => self.emit_basic_opcode(Op::MUL),
+            TokenType::Percent => self.emit_basic_opcode(Op::MOD),
+            TokenType::Ampersand => self.emit_basic_opcode(Op::BitAnd),
+            TokenType::Bar => self.emit_basic_opcode(Op::BitOr),
+            TokenType::Caret => self.emit_basic_opcode(Op::BitXor),
+            TokenType::LShift => self.emit_basic_opcode(Op::Shl),
+            TokenType::RShift => self.emit_basic_opcode(Op::Shr),
             TokenType::Greater => self.emit_basic_opcode(Op::GREATER),
             TokenType::Less => self.emit_basic_opcode(Op::LESS),
             TokenType::EqEqual => self.emit_basic_opcode(Op::EQUAL),
-            TokenType::GEqual => {
-                self.emit_basic_opcode(Op::LESS);
-                self.emit_basic_opcode(Op::NOT)
-            },
-            TokenType::LEqual => {
-                self.emit_basic_opcode(Op::GREATER);
-                self.emit_basic_opcode(Op::NOT)
-            },
+            TokenType::GEqual => self.emit_basic_opcode(Op::GEqual),
+            TokenType::LEqual => self.emit_basic_opcode(Op::LEqual),
             TokenType::NEqual => {
                 self.emit_basic_opcode(Op::EQUAL);
                 self.emit_basic_opcode(Op::NOT)
@@ -637,12 +1092,67 @@ impl Compiler {
         };
     }
 
+    /// Ordering comparisons chain like they do in maths: `a < b <= c` means `a < b && b <= c`,
+    /// with `b` evaluated only once. Each link keeps its right operand for the next one, and
+    /// the first false link skips the rest.
+    fn comparison(&mut self, mut operator: TokenType) {
+        let mut false_jumps = vec![];
+        loop {
+            self.parse_precedence(Precedence::COMPARISON.next());
+            if self.infix_precedence() != Precedence::COMPARISON {
+                self.binary_op(operator);
+                break;
+            }
+            // [a, b] -> [b, a < b]
+            self.emit_basic_opcode(Op::SWAP);
+            self.emit_basic_opcode(Op::OVER);
+            self.binary_op(operator);
+            false_jumps.push(self.emit_jump(Op::JumpIfFalse));
+            self.advance();
+            operator = self.parser.previous().token_type;
+        }
+
+        if !false_jumps.is_empty() {
+            let end_jump = self.emit_jump(Op::Jump);
+            for jump in false_jumps {
+                self.patch_jump(jump);
+            }
+            // Only the operand kept for the next link is left on the stack
+            self.emit_basic_opcode(Op::POP);
+            self.emit_basic_opcode(Op::FALSE);
+            self.patch_jump(end_jump);
+        }
+    }
+
     pub fn number(&mut self, _assign_mode: AssignMode) {
         log_debug!("Compiling number literal", value = format!("{}", self.parser.previous()).as_str());
-        let val = self.parser.previous().lexeme.lexeme().parse::<f64>();
+        // Digit separators are purely visual
+        let lexeme = self.parser.previous().lexeme.lexeme().replace('_', "");
+        let radix = match lexeme.get(..2) {
+            Some("0x") => Some(16),
+            Some("0b") => Some(2),
+            Some("0o") => Some(8),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            // The scanner has already checked the digits, so the only failure left is overflow
+            match i64::from_str_radix(&lexeme[2..], radix) {
+                Ok(v) => self.emit_constant(NanBoxedValue::integer(v)),
+                Err(_) => self.report_err(&format!("Number literal too large: {}", lexeme)),
+            }
+            return;
+        }
+        // Literals without a decimal point or exponent are integers
+        if !lexeme.contains(['.', 'e', 'E'])
+            && let Ok(v) = lexeme.parse::<i64>()
+        {
+            self.emit_constant(NanBoxedValue::integer(v));
+            return;
+        }
+        let val = lexeme.parse::<f64>();
         log_debug!("Parsed number value", parsed_value = format!("{:?}", val).as_str());
         match val {
-            Ok(v) => self.emit_number(v),
+            Ok(v) => self.emit_constant(NanBoxedValue::number(v)),
             Err(_) => self.report_err(&format!("Not a Number: {}", self.parser.previous())),
         }
     }
@@ -674,6 +1184,8 @@ impl Compiler {
         func_compiler.lambda_function(); // compile lambda
         
         self.parser = func_compiler.parser;  // leap forward to the end of the lambda
+        self.had_error |= func_compiler.had_error;  // errors inside the body fail the whole compile
+        self.first_error = self.first_error.take().or(func_compiler.first_error);
         
         self.emit_closure(func_compiler.function, func_compiler.scope.depth as usize);
         self.scope.exit_scope();
@@ -691,16 +1203,16 @@ impl Compiler {
 
     fn emit_string(&mut self, value: String) {
         log_debug!("Emitting string constant", constant_value = format!("{:?}", value).as_str(), line = self.line);
-        let line = self.line;
-        self.current_chunk().emit_constant(NanBoxedValue::string(value.into()), line);
+        self.emit_constant(NanBoxedValue::string(value.into()));
     }
 
-    fn emit_number(&mut self, value: f64) {
+    fn emit_constant(&mut self, value: NanBoxedValue) {
         let line = self.line;
         log_debug!("Emitting constant opcode", constant_value = format!("{:?}", value).as_str(), line = line, offset = self.current_chunk().code.len());
         
-        self.current_chunk()
-            .emit_constant(NanBoxedValue::number(value), line);
+        if let Err(msg) = self.current_chunk().emit_constant(value, line) {
+            self.report_err(msg);
+        }
     }
 
     fn emit_basic_opcode(&mut self, op: Op) {
@@ -718,6 +1230,15 @@ impl Compiler {
         log_debug!("Emitting CloseUpvalues", stack_slot = stack_slot, line = self.line);
     }
 
+    /// Emits a global get or set. The name goes in the constant table rather than on the stack,
+    /// so the VM can remember which slot it resolved to.
+    fn emit_global(&mut self, op: Op, identifier: String) {
+        match self.current_chunk().add_constant_only(NanBoxedValue::string(identifier)) {
+            Ok(idx) => self.emit_opcode(op, &(idx as u16).to_be_bytes().to_vec()),
+            Err(msg) => self.report_err(msg),
+        }
+    }
+
     fn emit_opcode(&mut self, op: Op, args: &Vec<u8>) {
         let line = self.line;
         log_debug!("Emitting opcode with args", opcode = format!("{:?}", op).as_str(), args = format!("{:?}", args).as_str(), line = line, offset = self.current_chunk().code.len());
@@ -737,7 +1258,8 @@ impl Compiler {
     }
     
     fn emit_loop(&mut self, loop_start: usize) {
-        let offset = self.current_chunk().code.len() - loop_start + 2;
+        // +3 to also jump back over the Loop instruction and its 2-byte operand
+        let offset = self.current_chunk().code.len() - loop_start + 3;
         let hi = (offset >> 8) as u8;
         let lo = (offset & 0xFF) as u8;
         self.emit_opcode(Op::Loop, &vec![hi, lo]);
@@ -780,6 +1302,152 @@ mod tests {
         assert!(result.is_ok(), "Failed to compile");
     }
 
+    #[test]
+    fn test_unterminated_delimiters_name_the_opener() {
+        let cases = [
+            ("x = 1\nif x > 0 {\n  puts x\n", "Unterminated block starting at line 2"),
+            ("fn f(a) {\n  a + 1\n\n", "Unterminated block starting at line 1"),
+            ("f = ^() {\n  if true { 1 }\n", "Unterminated block starting at line 1"),
+            ("x = (1 +\n  2\n", "Unterminated '(' starting at line 1"),
+            ("puts 1\nlen(1,\n  2\n", "Unterminated argument list starting at line 2"),
+        ];
+        for (code, expected) in cases {
+            let mut compiler = Compiler::new(code, false);
+            let err = compiler.compile().unwrap_err();
+            assert!(err.msg.ends_with(expected), "{:?}: {}", code, err);
+        }
+
+        // A closer that's merely wrong still gets the usual message
+        let mut compiler = Compiler::new("x = (1 + 2]", false);
+        let err = compiler.compile().unwrap_err();
+        assert!(err.msg.contains("Expected ')' after expression"), "{}", err);
+    }
+
+    #[test]
+    fn test_const_bindings() {
+        let mut compiler = Compiler::new("const PI = 3.14; PI * 2", true);
+        assert!(compiler.compile().is_ok(), "Reading a constant should compile");
+
+        let mut compiler = Compiler::new("const PI = 3.14; PI = 1", true);
+        assert!(compiler.compile().is_err(), "Reassigning a constant should not compile");
+
+        let mut compiler = Compiler::new("const PI = 3.14; PI += 1", true);
+        assert!(compiler.compile().is_err(), "Compound assignment to a constant should not compile");
+
+        let mut compiler = Compiler::new("const PI = 3.14; const PI = 3", true);
+        assert!(compiler.compile().is_err(), "Redeclaring a constant should not compile");
+    }
+
+    #[test]
+    fn test_const_locals() {
+        let mut compiler = Compiler::new("fn f() { const n = 1; n = 2 }", true);
+        assert!(compiler.compile().is_err(), "Reassigning a local constant should not compile");
+
+        let mut compiler = Compiler::new("fn f() { const n = 1; ^() { n = 2 } }", true);
+        assert!(compiler.compile().is_err(), "Reassigning a captured constant should not compile");
+
+        let mut compiler = Compiler::new("fn f() { const n = 1; n + 1 } f()", true);
+        assert!(compiler.compile().is_ok(), "Reading a local constant should compile");
+    }
+
+    #[test]
+    fn test_disassembly_shows_source_lines() {
+        let source = "fn add(a, b) {\n  a + b\n}\ntotal = add(1, 2)\nputs total";
+        let mut compiler = Compiler::new(source, false);
+        let script = compiler.compile().unwrap();
+
+        let mut listing = Vec::new();
+        script.disassemble_to(&mut listing).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.starts_with("== <script> =="), "{}", listing);
+        assert!(listing.contains("   1 | fn add(a, b) {"), "{}", listing);
+        assert!(listing.contains("   4 | total = add(1, 2)"), "{}", listing);
+        assert!(listing.contains("   5 | puts total"), "{}", listing);
+        assert!(listing.contains(&format!("{:<12} {:4}", "Call", 2)), "{}", listing);
+        assert!(listing.contains("<fn add(2)>"), "{}", listing);
+        // The body belongs to `add`, not the script
+        assert!(!listing.contains("a + b"), "{}", listing);
+
+        let add = script.chunk.constants.iter()
+            .find(|c| c.is_pointer() && c.as_pointer().1 == PointerTag::Closure)
+            .map(|c| unsafe { &*(c.as_pointer().0 as *const FnClosure) })
+            .expect("the script should hold add's closure");
+        let mut listing = Vec::new();
+        add.func.disassemble_to(&mut listing).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.starts_with("== <fn add(2)> =="), "{}", listing);
+        assert!(listing.contains("   2 |   a + b"), "{}", listing);
+        assert!(listing.contains(&format!("{:<12} {:4}", "GetLocal", 1)), "{}", listing);
+    }
+
+    #[test]
+    fn test_default_params_come_last() {
+        let mut compiler = Compiler::new("fn f(a, b = 1, c = a) { a + b + c }", true);
+        assert!(compiler.compile().is_ok(), "Trailing defaults should compile");
+
+        let mut compiler = Compiler::new("fn f(a = 1, b) { a + b }", true);
+        assert!(compiler.compile().is_err(), "A required param after a default should not compile");
+
+        let mut compiler = Compiler::new("^(a = 1, b) { a + b }", true);
+        assert!(compiler.compile().is_err(), "Lambdas follow the same rule");
+    }
+
+    #[test]
+    fn test_many_distinct_constants() {
+        let source = (0..20_000).map(|i| i.to_string()).collect::<Vec<_>>().join(" + ");
+        let mut compiler = Compiler::new(&source, false);
+        let chunk = compiler.compile().unwrap().chunk;
+        assert_eq!(chunk.constants.len(), 20_000);
+        assert_eq!(chunk.constants[19_999], NanBoxedValue::integer(19_999));
+
+        // Repeats reuse the existing entries
+        let source = format!("{} + {}", source, source);
+        let mut compiler = Compiler::new(&source, false);
+        assert_eq!(compiler.compile().unwrap().chunk.constants.len(), 20_000);
+    }
+
+    #[test]
+    fn test_constant_pool_overflow() {
+        // One more unique constant than a u16 operand can address
+        let source = (0..=65_536).map(|i| i.to_string()).collect::<Vec<_>>().join(" + ");
+        let mut compiler = Compiler::new(&source, false);
+        assert!(compiler.compile().is_err(), "An overflowing constant pool should fail to compile");
+    }
+
+    #[test]
+    fn test_prefixed_number_literals() {
+        let mut compiler = Compiler::new("0xFF", true);
+        let chunk = compiler.compile().unwrap().chunk;
+        assert_eq!(chunk.constants[0], NanBoxedValue::integer(255));
+        assert!(chunk.constants[0].is_int());
+
+        let mut compiler = Compiler::new("0b1010 + 0o17", true);
+        let chunk = compiler.compile().unwrap().chunk;
+        assert_eq!(chunk.constants[0], NanBoxedValue::integer(10));
+        assert_eq!(chunk.constants[1], NanBoxedValue::integer(15));
+
+        let mut compiler = Compiler::new("0xFFFFFFFFFFFFFFFFFF", true);
+        assert!(compiler.compile().is_err(), "Overflowing literals should not compile");
+    }
+
+    #[test]
+    fn test_scientific_and_separated_literals() {
+        let cases = [
+            ("1e3", NanBoxedValue::number(1000.0)),
+            ("2.5e-1", NanBoxedValue::number(0.25)),
+            ("1_000", NanBoxedValue::integer(1000)),
+            ("0b1111_0000", NanBoxedValue::integer(240)),
+        ];
+        for (code, expected) in cases {
+            let mut compiler = Compiler::new(code, true);
+            let chunk = compiler.compile().unwrap().chunk;
+            assert_eq!(chunk.constants[0], expected, "{}", code);
+        }
+
+        let mut compiler = Compiler::new("1e3", true);
+        assert!(compiler.compile().unwrap().chunk.constants[0].is_float(), "exponents always make floats");
+    }
+
     #[test]
     fn test_sequential_function_compilation_debug() {
         // This test specifically targets the scope accumulation bug
@@ -795,6 +1463,15 @@ mod tests {
         assert!(result.is_ok(), "Failed to compile sequential functions: {:?}", result.unwrap_err());
     }
 
+    #[test]
+    fn test_caret_lambda_compiles() {
+        let mut compiler = Compiler::new("^(x){x}", true);
+        let chunk = compiler.compile().unwrap().chunk;
+        assert_eq!(Op::at(chunk.code[0]), Op::Closure);
+        let listing = chunk.disassemble_to_string("lambda");
+        assert!(listing.contains("<fn <lambda>(1)>"), "{}", listing);
+    }
+
     #[test]
     fn test_sequential_lambda_compilation_debug() {
         // This test specifically targets the scope accumulation bug with lambdas
@@ -825,4 +1502,25 @@ mod tests {
         let result = compiler.compile();
         assert!(result.is_ok(), "Failed to compile mixed functions/lambdas: {:?}", result.unwrap_err());
     }
+
+    #[test]
+    fn test_compiling_large_source() {
+        // Parsing keeps no token history, so a long script compiles in linear time
+        let code = "(1 + 2) * 3 - 4\n".repeat(100_000);
+        let start = std::time::Instant::now();
+        let result = Compiler::new(&code, false).compile();
+        assert!(result.is_ok(), "Failed to compile: {:?}", result.unwrap_err());
+        assert!(start.elapsed().as_secs() < 10, "compiling took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_or_jumps_straight_to_the_end() {
+        let or = Compiler::new("x || y", false).compile().unwrap().chunk;
+        let and = Compiler::new("x && y", false).compile().unwrap().chunk;
+        let listing = or.disassemble_to_string("or");
+        assert!(listing.contains("JumpIfTrue"), "{}", listing);
+        assert!(!listing.contains("JumpIfFalse"), "{}", listing);
+        // && needs one more byte, a DUP, since JumpIfFalse pops the value it tests
+        assert_eq!(and.code.len() - or.code.len(), 1);
+    }
 }
@@ -13,7 +13,8 @@ enum ScopeType {
 #[derive(Clone)]
 pub(crate) struct Local {
     name: Box<String>,
-    depth: u8
+    depth: u8,
+    is_const: bool,
 }
 
 type ScopeStack = Rc<RefCell<Vec<InnerScope>>>;
@@ -29,11 +30,11 @@ struct InnerScope {
 }
 
 impl Local {
-    pub fn new(name: String, depth: u8) -> Local {
-        Local { name: name.into(), depth }
+    pub fn new(name: String, depth: u8, is_const: bool) -> Local {
+        Local { name: name.into(), depth, is_const }
     }
 
-    pub fn empty() -> Local { Local { name: "".to_string().into(), depth: 0 } }
+    pub fn empty() -> Local { Local { name: "".to_string().into(), depth: 0, is_const: false } }
 }
 
 impl InnerScope {
@@ -90,8 +91,8 @@ impl Scope {
         self.stack.borrow_mut().pop();
     }
 
-    pub fn declare_local(&mut self, identifier: String) -> usize {
-        let local = Local::new(identifier, self.depth);
+    pub fn declare_local(&mut self, identifier: String, is_const: bool) -> usize {
+        let local = Local::new(identifier, self.depth, is_const);
         if self.stack.borrow().is_empty() {
             return 0;
         }
@@ -141,6 +142,36 @@ impl Scope {
         -1
     }
 
+    /// Names the current scope's reserved first slot, which holds the function being run, so
+    /// the function can refer to itself by `name`
+    pub fn name_function(&mut self, name: &str) {
+        let current_depth = self.depth as usize;
+        if let Some(scope) = self.stack.borrow_mut().get_mut(current_depth) {
+            scope.locals[0] = Local::new(name.to_string(), self.depth, false);
+        }
+    }
+
+    /// Whether the local in `slot` of the current scope was declared with `const`
+    pub fn is_const_local(&self, slot: usize) -> bool {
+        let current_depth = self.depth as usize;
+        self.stack.borrow().get(current_depth)
+            .and_then(|scope| scope.locals.get(slot))
+            .is_some_and(|l| l.is_const)
+    }
+
+    /// Whether `identifier` resolves to a `const` local in an enclosing scope,
+    /// following the same search order as `resolve_upvalue`
+    pub fn is_const_upvalue(&self, identifier: &str) -> bool {
+        let stack = self.stack.borrow();
+        let depth = (self.depth as usize).min(stack.len());
+        for scope in stack[..depth].iter().rev() {
+            if let Some(i) = scope.resolve_local(identifier) {
+                return scope.locals[i].is_const;
+            }
+        }
+        false
+    }
+
     pub fn resolve_upvalue(&mut self, identifier: &str) -> Option<Upvalue> {
         // Call our recursive function which will search up the call stack for upvals
         self.recursive_resolve_upvalue(identifier, self.depth as usize)
@@ -225,7 +256,6 @@ impl Scope {
         self.pop_scope();
     }
 
-    pub fn enter_scope(&mut self) -> Self { self.incr() }
     pub fn exit_scope(&mut self) { self.decr(); }
     
     /// Create an isolated scope for function compilation that prevents
@@ -255,10 +285,11 @@ impl Scope {
             let mut fresh_scope = Scope::new();
             fresh_scope.depth = 1;
             
-            // Only copy global scope for upvalue resolution
+            // Only copy global scope for upvalue resolution. It takes the place of the new
+            // scope's own top level, so the function's scope lands at index 1 - its depth.
             if !self.stack.borrow().is_empty() {
                 let global_scope = self.stack.borrow()[0].clone();
-                fresh_scope.stack.borrow_mut().push(global_scope);
+                fresh_scope.stack.borrow_mut()[0] = global_scope;
             }
             
             // Add fresh scope for this function
@@ -6,4 +6,5 @@ mod precedence;
 mod parse_rule;
 mod internal;
 
-pub use crate::weave::compiler::compiler::Compiler;
\ No newline at end of file
+pub use crate::weave::compiler::compiler::{CompileError, Compiler};
+pub use crate::weave::compiler::scanner::is_incomplete;
\ No newline at end of file
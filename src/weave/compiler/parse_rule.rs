@@ -22,16 +22,20 @@ impl  ParseRule {
         match token_type {
             // No precedence
             TokenType::RightParen => ParseRule::new(),
-            TokenType::LeftBrace => ParseRule::new(),
             TokenType::RightBrace => ParseRule::new(),
-            TokenType::LeftBracket => ParseRule::new(),
             TokenType::RightBracket => ParseRule::new(),
             TokenType::Equal => ParseRule::new(),
+            TokenType::PlusEqual => ParseRule::new(),
+            TokenType::MinusEqual => ParseRule::new(),
+            TokenType::StarEqual => ParseRule::new(),
+            TokenType::SlashEqual => ParseRule::new(),
             TokenType::Comma => ParseRule::new(),
             TokenType::Semicolon => ParseRule::new(),
+            TokenType::Colon => ParseRule::new(),
             
             // Low precedence
             TokenType::Bang => ParseRuleBuilder::p_none().prefix(Compiler::unary).rule,
+            TokenType::Not => ParseRuleBuilder::p_none().prefix(Compiler::unary).rule,
             TokenType::NEqual => ParseRuleBuilder::p_equality().infix(Compiler::binary).rule,
             TokenType::EqEqual => ParseRuleBuilder::p_equality().infix(Compiler::binary).rule,
             TokenType::Greater => ParseRuleBuilder::p_comparison().infix(Compiler::binary).rule,
@@ -39,7 +43,16 @@ impl  ParseRule {
             TokenType::Less => ParseRuleBuilder::p_comparison().infix(Compiler::binary).rule,
             TokenType::LEqual => ParseRuleBuilder::p_comparison().infix(Compiler::binary).rule,
 
+            // Bitwise
+            TokenType::Bar => ParseRuleBuilder::p_bit_or().infix(Compiler::binary).rule,
+            TokenType::Ampersand => ParseRuleBuilder::p_bit_and().infix(Compiler::binary).rule,
+            TokenType::LShift => ParseRuleBuilder::p_shift().infix(Compiler::binary).rule,
+            TokenType::RShift => ParseRuleBuilder::p_shift().infix(Compiler::binary).rule,
+
             TokenType::LeftParen => ParseRuleBuilder::p_call().prefix(Compiler::grouping).infix(Compiler::fn_call).rule,
+            TokenType::LeftBracket => ParseRuleBuilder::p_call().prefix(Compiler::array).infix(Compiler::index).rule,
+            // Blocks only follow keywords, so a brace in expression position is always a map literal
+            TokenType::LeftBrace => ParseRuleBuilder::p_none().prefix(Compiler::map).rule,
 
             // Term
             TokenType::Minus => ParseRuleBuilder::p_term().prefix(Compiler::unary).infix(Compiler::binary).rule,
@@ -48,36 +61,48 @@ impl  ParseRule {
             // Product
             TokenType::Slash => ParseRuleBuilder::p_factor().infix(Compiler::binary).rule,
             TokenType::Star => ParseRuleBuilder::p_factor().infix(Compiler::binary).rule,
+            TokenType::Percent => ParseRuleBuilder::p_factor().infix(Compiler::binary).rule,
             
-            // Lambda expression
-            TokenType::Caret => ParseRuleBuilder::p_none().prefix(Compiler::lambda).rule,
+            // Lambda expression as a prefix, bitwise xor as an infix
+            TokenType::Caret => ParseRuleBuilder::p_bit_xor().prefix(Compiler::lambda).infix(Compiler::binary).rule,
+            // `fn` starting a statement declares a function; anywhere else it is a function value
+            TokenType::FN => ParseRuleBuilder::p_none().prefix(Compiler::function_expression).rule,
 
             // Literals
             TokenType::True => ParseRuleBuilder::p_none().prefix(Compiler::literal).rule,
             TokenType::False => ParseRuleBuilder::p_none().prefix(Compiler::literal).rule,
+            TokenType::Null => ParseRuleBuilder::p_none().prefix(Compiler::null_literal).rule,
             TokenType::Number => ParseRuleBuilder::p_none().prefix(Compiler::number).rule,
             TokenType::String => ParseRuleBuilder::p_none().prefix(Compiler::string).rule,
             TokenType::Identifier => ParseRuleBuilder::p_none().prefix(Compiler::variable).rule,
 
             // Logical operators
             TokenType::AndAnd => ParseRuleBuilder::p_and().infix(Compiler::log_and).rule,
-            TokenType::OrOr => ParseRuleBuilder::p_and().infix(Compiler::log_or).rule,
+            TokenType::OrOr => ParseRuleBuilder::p_or().infix(Compiler::log_or).rule,
+            TokenType::And => ParseRuleBuilder::p_and().infix(Compiler::log_and).rule,
+            TokenType::Or => ParseRuleBuilder::p_or().infix(Compiler::log_or).rule,
+            TokenType::Question => ParseRuleBuilder::p_ternary().infix(Compiler::ternary).rule,
             
             // Flow control
             TokenType::If => ParseRule::new(),
             TokenType::Else => ParseRule::new(),
             TokenType::While => ParseRule::new(),
+            TokenType::For => ParseRule::new(),
+            TokenType::Break => ParseRule::new(),
+            TokenType::Continue => ParseRule::new(),
             
             // TODO
             TokenType::Pipe => ParseRule::new(),
             TokenType::Map => ParseRule::new(),
             TokenType::Reduce => ParseRule::new(),
             TokenType::Container => ParseRule::new(),
-            TokenType::FN => ParseRule::new(),
             TokenType::Return => ParseRule::new(),
+            TokenType::Const => ParseRule::new(),
             TokenType::Puts => ParseRule::new(),
+            TokenType::Echo => ParseRule::new(),
             TokenType::ERROR => ParseRule::new(),
             TokenType::EOF => ParseRule::new(),
+            TokenType::Newline => ParseRule::new(),
         }
         
     }
@@ -117,6 +142,10 @@ impl  ParseRuleBuilder {
         Self::new().precedence(Precedence::ASSIGNMENT)
     }
     
+    pub fn p_ternary() -> ParseRuleBuilder {
+        Self::new().precedence(Precedence::TERNARY)
+    }
+    
     pub fn p_or() -> ParseRuleBuilder {
         Self::new().precedence(Precedence::OR)
     }
@@ -133,6 +162,22 @@ impl  ParseRuleBuilder {
         Self::new().precedence(Precedence::COMPARISON)
     }
     
+    pub fn p_bit_or() -> ParseRuleBuilder {
+        Self::new().precedence(Precedence::BITOR)
+    }
+    
+    pub fn p_bit_xor() -> ParseRuleBuilder {
+        Self::new().precedence(Precedence::BITXOR)
+    }
+    
+    pub fn p_bit_and() -> ParseRuleBuilder {
+        Self::new().precedence(Precedence::BITAND)
+    }
+    
+    pub fn p_shift() -> ParseRuleBuilder {
+        Self::new().precedence(Precedence::SHIFT)
+    }
+    
     pub fn p_term() -> ParseRuleBuilder {
         Self::new().precedence(Precedence::TERM)
     }
@@ -4,14 +4,19 @@ use crate::weave::compiler::token::{Token, TokenType};
 #[derive(Clone)]
 pub(crate) struct Parser {
     scanner: Scanner,
-    tokens: Vec<Token>,
+    // Only the upcoming token and the one before it are ever looked at
+    current: Option<Token>,
+    previous: Option<Token>,
+    peek_starts_line: bool,
 }
 
 impl  Parser {
     pub fn new(code: &str) -> Parser {
         Parser {
             scanner: Scanner::new(code, false),
-            tokens: Vec::new(),
+            current: None,
+            previous: None,
+            peek_starts_line: false,
         }
     }
 
@@ -20,28 +25,51 @@ impl  Parser {
     }
 
     pub fn peek(&self) -> Token {
-        self.prev(0)
+        Self::or_out_of_bounds(&self.current)
     }
 
     pub fn previous(&self) -> Token {
-        self.prev(1)
+        Self::or_out_of_bounds(&self.previous)
     }
 
-    fn prev(&self, i: usize) -> Token {
-        self.tokens.get(i).cloned().unwrap_or(Token::text_token(TokenType::ERROR, (0, 0), "Out of bounds", 0))
+    fn or_out_of_bounds(token: &Option<Token>) -> Token {
+        token.clone().unwrap_or(Token::text_token(TokenType::ERROR, (0, 0), "Out of bounds", 0, 0))
     }
 
     pub fn peek_type(&self) -> TokenType {
         self.peek().token_type
     }
 
+    /// Whether a line break separates the upcoming token from the previous one
+    pub fn peek_starts_line(&self) -> bool {
+        self.peek_starts_line
+    }
+
+    /// The type of the token after the upcoming one, without consuming anything
+    pub fn peek_second_type(&self) -> TokenType {
+        let mut scanner = self.scanner.clone();
+        loop {
+            let token = scanner.scan_token();
+            if token.token_type != TokenType::Newline {
+                return token.token_type;
+            }
+        }
+    }
+
 }
 
 impl Iterator for Parser {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next_tok = self.scanner.scan_token();
+        // Line breaks aren't handed to the compiler - we just remember that one came before
+        // the next real token
+        let mut next_tok = self.scanner.scan_token();
+        let mut starts_line = false;
+        while next_tok.token_type == TokenType::Newline {
+            starts_line = true;
+            next_tok = self.scanner.scan_token();
+        }
         
         if next_tok.token_type == TokenType::EOF {
             if self.peek().token_type == TokenType::EOF { return None }
@@ -49,10 +77,40 @@ impl Iterator for Parser {
         
         if next_tok.token_type == TokenType::ERROR { return Some(next_tok); }
         
-        // Add it to our history, then return it as the next token
-        self.tokens.insert(0, next_tok);
-        self.tokens.get(0).cloned()
+        // The upcoming token becomes the previous one, and anything older is dropped
+        self.previous = self.current.replace(next_tok.clone());
+        self.peek_starts_line = starts_line;
+        Some(next_tok)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn test_peek_and_previous() {
+        let mut parser = Parser::new("a = 1\nb");
+        assert_eq!(parser.peek_type(), TokenType::ERROR);
+
+        parser.next();
+        assert_eq!(parser.peek().lexeme.lexeme(), "a");
+        assert_eq!(parser.previous().token_type, TokenType::ERROR);
+
+        parser.next();
+        assert_eq!(parser.peek_type(), TokenType::Equal);
+        assert_eq!(parser.previous().lexeme.lexeme(), "a");
+        assert_eq!(parser.peek_second_type(), TokenType::Number);
+
+        parser.next();
+        parser.next();
+        assert_eq!(parser.peek().lexeme.lexeme(), "b");
+        assert!(parser.peek_starts_line());
+        assert_eq!(parser.previous().token_type, TokenType::Number);
+
+        parser.next();
+        assert_eq!(parser.peek_type(), TokenType::EOF);
+        assert_eq!(parser.previous().lexeme.lexeme(), "b");
+        assert!(parser.next().is_none());
+    }
+}
@@ -1,11 +1,17 @@
+#[allow(clippy::upper_case_acronyms)]  // Precedence levels read like grammar terminals
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
 pub enum Precedence {
     NONE,
     ASSIGNMENT,  // =
+    TERNARY,     // ? :
     OR,          // or
     AND,         // and
     EQUALITY,    // == !=
     COMPARISON,  // < > <= >=
+    BITOR,      // |
+    BITXOR,     // ^
+    BITAND,     // &
+    SHIFT,       // << >>
     TERM,        // + -
     FACTOR,      // * /
     UNARY,       // ! -
@@ -18,11 +24,16 @@ impl Precedence {
         use Precedence::*;
         match self {
             NONE => ASSIGNMENT,
-            ASSIGNMENT => OR,
+            ASSIGNMENT => TERNARY,
+            TERNARY => OR,
             OR => AND,
             AND => EQUALITY,
             EQUALITY => COMPARISON,
-            COMPARISON => TERM,
+            COMPARISON => BITOR,
+            BITOR => BITXOR,
+            BITXOR => BITAND,
+            BITAND => SHIFT,
+            SHIFT => TERM,
             TERM => FACTOR,
             FACTOR => UNARY,
             UNARY => CALL,
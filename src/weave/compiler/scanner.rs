@@ -8,6 +8,8 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: usize,
+    column: usize,        // Column of the next character to be read
+    start_column: usize,  // Column where the current token began
 }
 
 impl Scanner {
@@ -18,20 +20,34 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_column: 1,
         }
     }
 
+    // `current` is a byte offset, so peeking slices from it rather than walking the whole source
     pub fn peek(&self) -> char {
-        self.code.chars().nth(self.current).unwrap_or('\0')
+        self.rest().chars().next().unwrap_or('\0')
     }
 
     pub fn peek_next(&self) -> char {
-        self.code.chars().nth(self.current + 1).unwrap_or('\0')
+        self.rest().chars().nth(1).unwrap_or('\0')
+    }
+
+    fn rest(&self) -> &str {
+        // `advance` can step past the end, which leaves nothing to read
+        self.code.get(self.current..).unwrap_or("")
     }
 
     pub fn advance(&mut self) -> char {
         let c = self.peek();
-        self.current += 1;
+        self.current += c.len_utf8();
+        // Every character, tabs included, is one column wide
+        if c == '\n' {
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         c
     }
 
@@ -48,20 +64,23 @@ impl Scanner {
     }
 
     pub fn err_token(&self, message: &'static str) -> Token {
-        Token::text_token(TokenType::ERROR, (self.start, self.current), message, self.line)
+        Token::text_token(TokenType::ERROR, (self.start, self.current), message, self.line, self.start_column)
     }
 
     pub fn basic_token(&self, token_type: TokenType) -> Token {
         log_debug!("Scanner emitting token", token_type = format!("{:?}", token_type).as_str(), line = self.line);
-        Token::basic_token(token_type, (self.start, self.current), self.line)
+        Token::basic_token(token_type, (self.start, self.current), self.line, self.start_column)
     }
 
     pub fn text_token(&self, token_type: TokenType, lextext: &str) -> Token {
         log_debug!("Scanner emitting text token", token_type = format!("{:?}", token_type).as_str(), lexeme = lextext, line = self.line);
-        Token::text_token(token_type, (self.start, self.current), lextext, self.line)
+        Token::text_token(token_type, (self.start, self.current), lextext, self.line, self.start_column)
     }
 
-    fn skip_whitespace(&mut self) {
+    /// Skips whitespace and comments, returning whether that crossed a line break.
+    /// Line breaks inside block comments don't count.
+    fn skip_whitespace(&mut self) -> Result<bool, &'static str> {
+        let mut crossed_line = false;
         loop {
             match self.peek() {
                 ' ' | '\t' | '\r' => {
@@ -71,25 +90,61 @@ impl Scanner {
                     log_debug!("Scanner encountered newline", line = self.line + 1);
                     self.line += 1;
                     self.advance();
+                    crossed_line = true;
                 }
                 '#' => {
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
                 }
-                _ => return,
+                '/' if self.peek_next() == '*' => self.skip_block_comment()?,
+                _ => return Ok(crossed_line),
             }
         }
     }
 
+    /// Skips a `/* ... */` comment. Block comments nest, so `/* a /* b */ c */` is one comment.
+    fn skip_block_comment(&mut self) -> Result<(), &'static str> {
+        self.advance(); // consume the /*
+        self.advance();
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err("Unterminated block comment");
+            }
+            match self.advance() {
+                '/' if self.matches('*') => {
+                    self.advance();
+                    depth += 1;
+                }
+                '*' if self.matches('/') => {
+                    self.advance();
+                    depth -= 1;
+                }
+                '\n' => self.line += 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
     pub fn scan_token(&mut self) -> Token {
         log_debug!("Scanner scanning next token", current_pos = self.current);
-        self.skip_whitespace();
+        let skipped = self.skip_whitespace();
         log_debug!("Scanner whitespace skipped", new_pos = self.current);
         self.start = self.current; // Reset the self/scanner
+        self.start_column = self.column;
+
+        match skipped {
+            Err(message) => return self.err_token(message),
+            // A run of line breaks becomes one empty Newline token, placed where the next line's
+            // code starts. The next call picks up from there.
+            Ok(true) => return self.basic_token(TokenType::Newline),
+            Ok(false) => {}
+        }
 
         if self.is_at_end() {
-            return Token::basic_token(TokenType::EOF, (self.start, self.current), self.line);
+            return Token::basic_token(TokenType::EOF, (self.start, self.current), self.line, self.start_column);
         }
 
         match self.advance() {
@@ -104,17 +159,40 @@ impl Scanner {
             ']' => self.basic_token(TokenType::RightBracket),
             ',' => self.basic_token(TokenType::Comma),
 
-            '-' => self.basic_token(TokenType::Minus),
-            '+' => self.basic_token(TokenType::Plus),
+            '-' => {
+                if self.consume('=') {
+                    self.basic_token(TokenType::MinusEqual)
+                } else {
+                    self.basic_token(TokenType::Minus)
+                }
+            }
+            '+' => {
+                if self.consume('=') {
+                    self.basic_token(TokenType::PlusEqual)
+                } else {
+                    self.basic_token(TokenType::Plus)
+                }
+            }
             ';' => self.basic_token(TokenType::Semicolon),
-            '/' => self.basic_token(TokenType::Slash),
+            '?' => self.basic_token(TokenType::Question),
+            ':' => self.basic_token(TokenType::Colon),
+            '/' => {
+                if self.consume('=') {
+                    self.basic_token(TokenType::SlashEqual)
+                } else {
+                    self.basic_token(TokenType::Slash)
+                }
+            }
             '^' => self.basic_token(TokenType::Caret),
+            '%' => self.basic_token(TokenType::Percent),
 
             '"' => self.scan_string(),
 
             '*' => {
                 if self.consume('>') {
                     self.basic_token(TokenType::Map)
+                } else if self.consume('=') {
+                    self.basic_token(TokenType::StarEqual)
                 } else {
                     self.basic_token(TokenType::Star)
                 }
@@ -134,14 +212,18 @@ impl Scanner {
                 }
             }
             '<' => {
-                if self.consume('=') {
+                if self.consume('<') {
+                    self.basic_token(TokenType::LShift)
+                } else if self.consume('=') {
                     self.basic_token(TokenType::LEqual)
                 } else {
                     self.basic_token(TokenType::Less)
                 }
             }
             '>' => {
-                if self.consume('=') {
+                if self.consume('>') {
+                    self.basic_token(TokenType::RShift)
+                } else if self.consume('=') {
                     self.basic_token(TokenType::GEqual)
                 } else {
                     self.basic_token(TokenType::Greater)
@@ -153,7 +235,7 @@ impl Scanner {
                 } else if self.consume('>') {
                     self.basic_token(TokenType::Reduce)
                 } else {
-                    self.err_token("expected &&")
+                    self.basic_token(TokenType::Ampersand)
                 }
             }
             '|' => {
@@ -162,7 +244,7 @@ impl Scanner {
                 } else if self.consume('>') {
                     self.basic_token(TokenType::Pipe)
                 } else {
-                    self.err_token("expected || or |>")
+                    self.basic_token(TokenType::Bar)
                 }
             }
 
@@ -178,36 +260,124 @@ impl Scanner {
     fn scan_string(&mut self) -> Token {
         log_debug!("Scanner scanning string literal", start_pos = self.start, line = self.line);
         // Down the road, we'll want to support interpolation, but for right now, simple string parsing is good enough
+        // Escapes mean the string's value can differ from its source text, so build it up as we go
+        let mut value = String::new();
+        let mut bad_escape = false;
         while !self.is_at_end() && !self.matches('"') {
-            if self.matches('\n') { self.line += 1; }
-            self.advance();
+            match self.advance() {
+                '\\' => {
+                    if self.is_at_end() { break; }
+                    match self.advance() {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'r' => value.push('\r'),
+                        '0' => value.push('\0'),
+                        '\\' => value.push('\\'),
+                        '"' => value.push('"'),
+                        _ => bad_escape = true,
+                    }
+                }
+                '\n' => {
+                    self.line += 1;
+                    value.push('\n');
+                }
+                c => value.push(c),
+            }
         }
         if self.is_at_end() {
             return self.err_token("Unterminated string");
         }
         self.advance(); // consume the "
 
-        // +1 and -1 to account for the quote markers
-        let str_start = self.start + 1;
-        let str_end = (self.current as isize - 1) as usize;
-        self.text_token(TokenType::String, &self.code[str_start..str_end])
+        if bad_escape {
+            return self.err_token("Unknown escape sequence in string");
+        }
+        self.text_token(TokenType::String, &value)
     }
 
     fn scan_number(&mut self) -> Token {
-        while self.peek().is_digit(10) {
-            self.advance();
+        if self.cur_lexeme() == "0" {
+            let radix = match self.peek() {
+                'x' => Some(16),
+                'b' => Some(2),
+                'o' => Some(8),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                return self.scan_radix_number(radix);
+            }
         }
 
-        if self.matches('.') && self.peek_next().is_digit(10) {
+        // The first digit has already been consumed
+        if let Err(msg) = self.scan_digits(10, 1) {
+            return self.err_token(msg);
+        }
+
+        if self.matches('.') {
+            let next = self.peek_next();
+            if next.is_ascii_digit() {
+                self.advance();
+                if let Err(msg) = self.scan_digits(10, 0) {
+                    return self.err_token(msg);
+                }
+            } else if matches!(next, 'e' | 'E' | '_') {
+                // 1.e5 and 1._5 are almost certainly typos - say so rather than guess
+                self.advance();
+                return self.err_token("Expected digits after decimal point");
+            }
+        }
+
+        if matches!(self.peek(), 'e' | 'E') {
             self.advance();
-            while self.peek().is_digit(10) {
+            if matches!(self.peek(), '+' | '-') {
                 self.advance();
             }
+            match self.scan_digits(10, 0) {
+                Ok(0) => return self.err_token("Expected digits in exponent"),
+                Err(msg) => return self.err_token(msg),
+                Ok(_) => {}
+            }
         }
 
         self.text_token(TokenType::Number, &self.code[self.start..self.current])
     }
 
+    /// Consumes digits in `radix`, allowing `_` separators between them.
+    /// `digits` is how many have already been read; returns the new total.
+    fn scan_digits(&mut self, radix: u32, mut digits: usize) -> Result<usize, &'static str> {
+        loop {
+            let c = self.peek();
+            if c.is_digit(radix) {
+                digits += 1;
+            } else if c == '_' {
+                if digits == 0 || !self.peek_next().is_digit(radix) {
+                    return Err("Digit separators must sit between digits");
+                }
+            } else {
+                return Ok(digits);
+            }
+            self.advance();
+        }
+    }
+
+    /// Scans the digits of a 0x/0b/0o literal, after the leading zero
+    fn scan_radix_number(&mut self, radix: u32) -> Token {
+        self.advance(); // consume the radix marker
+        let digits = match self.scan_digits(radix, 0) {
+            Ok(digits) => digits,
+            Err(msg) => return self.err_token(msg),
+        };
+
+        if digits == 0 {
+            return self.err_token("Expected digits after number prefix");
+        }
+        // Catch typos like 0b102 or 0xFG rather than splitting them into two tokens
+        if self.peek().is_alphanumeric() {
+            return self.err_token("Invalid digit in number literal");
+        }
+        self.text_token(TokenType::Number, &self.code[self.start..self.current])
+    }
+
     fn is_alpha(c: char) -> bool {
         c.is_alphabetic()
     }
@@ -235,11 +405,20 @@ impl Scanner {
             "if" => TokenType::If,
             "else" => TokenType::Else,
             "while" => TokenType::While,
+            "for" => TokenType::For,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
             "true" => TokenType::True,
             "false" => TokenType::False,
+            "null" => TokenType::Null,
             "fn" => TokenType::FN,
             "return" => TokenType::Return,
+            "const" => TokenType::Const,
+            "and" => TokenType::And,
+            "or" => TokenType::Or,
+            "not" => TokenType::Not,
             "puts" => TokenType::Puts,
+            "echo" => TokenType::Echo,
 
             // Okay, just a normal identifier
             _ => TokenType::Identifier,
@@ -247,6 +426,35 @@ impl Scanner {
     }
 }
 
+/// Whether `code` stops partway through something - an unclosed bracket, string or block
+/// comment - so the REPL should read more lines before running it. Brackets inside strings and
+/// comments don't count.
+pub fn is_incomplete(code: &str) -> bool {
+    let mut scanner = Scanner::new(code, false);
+    // Open minus closed parens, braces and brackets
+    let mut depths = [0i64; 3];
+    loop {
+        let token = scanner.scan_token();
+        match token.token_type {
+            TokenType::LeftParen => depths[0] += 1,
+            TokenType::RightParen => depths[0] -= 1,
+            TokenType::LeftBrace => depths[1] += 1,
+            TokenType::RightBrace => depths[1] -= 1,
+            TokenType::LeftBracket => depths[2] += 1,
+            TokenType::RightBracket => depths[2] -= 1,
+            TokenType::ERROR => {
+                // Strings and block comments may go on over several lines
+                let message = token.lexeme.lexeme();
+                if message == "Unterminated string" || message == "Unterminated block comment" {
+                    return true;
+                }
+            }
+            TokenType::EOF => return depths.iter().any(|&depth| depth > 0),
+            _ => {}
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -260,6 +468,165 @@ mod tests {
         assert_eq!(token.lexeme.lexeme(), "hello world");
     }
 
+    #[test]
+    fn scan_string_escapes() {
+        let mut scanner = Scanner::new("\"a\\nb\"", true);
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::String);
+        assert_eq!(token.lexeme.lexeme(), "a\nb");
+
+        let mut scanner = Scanner::new(r#""\t\r\0\\\"""#, true);
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::String);
+        assert_eq!(token.lexeme.lexeme(), "\t\r\0\\\"");
+    }
+
+    #[test]
+    fn scan_string_unknown_escape() {
+        let mut scanner = Scanner::new(r#""bad \q" 1"#, true);
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::ERROR);
+
+        // The rest of the string is skipped, so scanning resumes after it
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::Number);
+    }
+
+    #[test]
+    fn scan_block_comment_single_line() {
+        let mut scanner = Scanner::new("/* skip me */ 42 / 2", true);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Number);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Slash);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Number);
+    }
+
+    #[test]
+    fn scan_block_comment_multi_line() {
+        let mut scanner = Scanner::new("/* one\ntwo\nthree */ x", true);
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::Identifier);
+        assert_eq!(token.line, 3);
+    }
+
+    #[test]
+    fn scan_block_comment_nested() {
+        // Block comments nest, so the first */ doesn't end the comment
+        let mut scanner = Scanner::new("/* outer /* inner */ still comment */ x", true);
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::Identifier);
+        assert_eq!(token.lexeme.lexeme(), "x");
+    }
+
+    #[test]
+    fn scan_block_comment_unterminated() {
+        let mut scanner = Scanner::new("1 /* never closed", true);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Number);
+        let token = scanner.scan_token();
+        assert_eq!(token.token_type, TokenType::ERROR);
+        assert_eq!(token.lexeme.lexeme(), "Unterminated block comment");
+    }
+
+    #[test]
+    fn scan_compound_assignment() {
+        let mut scanner = Scanner::new("+= -= *= /= + *>", true);
+        assert_eq!(scanner.scan_token().token_type, TokenType::PlusEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::MinusEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::StarEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::SlashEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Plus);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Map);
+    }
+
+    #[test]
+    fn scan_lambda() {
+        let mut scanner = Scanner::new("^(x){x}", true);
+        let expected = [
+            TokenType::Caret, TokenType::LeftParen, TokenType::Identifier, TokenType::RightParen,
+            TokenType::LeftBrace, TokenType::Identifier, TokenType::RightBrace, TokenType::EOF,
+        ];
+        for token_type in expected {
+            assert_eq!(scanner.scan_token().token_type, token_type);
+        }
+    }
+
+    #[test]
+    fn scan_bitwise_operators() {
+        let mut scanner = Scanner::new("& && &> | || |> ^ << <= < >> >= >", true);
+        let expected = [
+            TokenType::Ampersand, TokenType::AndAnd, TokenType::Reduce,
+            TokenType::Bar, TokenType::OrOr, TokenType::Pipe,
+            TokenType::Caret,
+            TokenType::LShift, TokenType::LEqual, TokenType::Less,
+            TokenType::RShift, TokenType::GEqual, TokenType::Greater,
+        ];
+        for token_type in expected {
+            assert_eq!(scanner.scan_token().token_type, token_type);
+        }
+    }
+
+    #[test]
+    fn scan_logical_keywords() {
+        let mut scanner = Scanner::new("a and b or not c android", true);
+        let expected = [
+            TokenType::Identifier, TokenType::And, TokenType::Identifier, TokenType::Or,
+            TokenType::Not, TokenType::Identifier, TokenType::Identifier, TokenType::EOF,
+        ];
+        for token_type in expected {
+            assert_eq!(scanner.scan_token().token_type, token_type);
+        }
+    }
+
+    #[test]
+    fn scan_conditional_operators() {
+        let mut scanner = Scanner::new("a ? b : c", true);
+        let expected = [
+            TokenType::Identifier, TokenType::Question, TokenType::Identifier,
+            TokenType::Colon, TokenType::Identifier, TokenType::EOF,
+        ];
+        for token_type in expected {
+            assert_eq!(scanner.scan_token().token_type, token_type);
+        }
+    }
+
+    #[test]
+    fn scan_columns() {
+        let mut scanner = Scanner::new("x = 10\n  foo(bar)", true);
+        let columns: Vec<(usize, usize)> = (0..7)
+            .map(|_| scanner.scan_token())
+            .filter(|t| t.token_type != TokenType::Newline)
+            .map(|t| (t.line, t.column))
+            .collect();
+        assert_eq!(columns, vec![(1, 1), (1, 3), (1, 5), (2, 3), (2, 6), (2, 7)]);
+    }
+
+    #[test]
+    fn scan_newlines() {
+        // Runs of line breaks (and the comments between them) collapse into a single token
+        let mut scanner = Scanner::new("a\n\n  # note\nb /* c\nd */ c\n", true);
+        let expected = [
+            TokenType::Identifier, TokenType::Newline, TokenType::Identifier,
+            TokenType::Identifier, TokenType::Newline, TokenType::EOF,
+        ];
+        for token_type in expected {
+            assert_eq!(scanner.scan_token().token_type, token_type);
+        }
+    }
+
+    #[test]
+    fn scan_columns_after_tabs_and_strings() {
+        // A tab counts as a single column
+        let mut scanner = Scanner::new("\tx\t= 1", true);
+        assert_eq!(scanner.scan_token().column, 2);
+        assert_eq!(scanner.scan_token().column, 4);
+        assert_eq!(scanner.scan_token().column, 6);
+
+        // Newlines inside strings and comments restart the column count too
+        let mut scanner = Scanner::new("\"a\nb\" /* c\nd */ y", true);
+        assert_eq!(scanner.scan_token().column, 1);
+        let token = scanner.scan_token();
+        assert_eq!((token.line, token.column), (3, 6));
+    }
+
     #[test]
     fn scan_number() {
         let mut scanner = Scanner::new("123", true);
@@ -268,6 +635,43 @@ mod tests {
         assert_eq!(token.lexeme.lexeme(), "123");
     }
 
+    #[test]
+    fn scan_prefixed_numbers() {
+        for literal in ["0xFF", "0xdead", "0b1010", "0o17", "0"] {
+            let mut scanner = Scanner::new(literal, true);
+            let token = scanner.scan_token();
+            assert_eq!(token.token_type, TokenType::Number, "{}", literal);
+            assert_eq!(token.lexeme.lexeme(), literal);
+            assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
+        }
+
+        for literal in ["0x", "0b", "0o", "0b102", "0o8", "0xFG"] {
+            let mut scanner = Scanner::new(literal, true);
+            assert_eq!(scanner.scan_token().token_type, TokenType::ERROR, "{}", literal);
+        }
+    }
+
+    #[test]
+    fn scan_exponents_and_separators() {
+        for literal in ["1e3", "2.5e-1", "6E+2", "1_000", "1_000.000_5", "0xFF_FF", "1_0e1_0"] {
+            let mut scanner = Scanner::new(literal, true);
+            let token = scanner.scan_token();
+            assert_eq!(token.token_type, TokenType::Number, "{}", literal);
+            assert_eq!(token.lexeme.lexeme(), literal);
+            assert_eq!(scanner.scan_token().token_type, TokenType::EOF, "{}", literal);
+        }
+
+        for literal in ["1e", "1e+", "1.e5", "1._5", "1__0", "1_", "0x_1"] {
+            let mut scanner = Scanner::new(literal, true);
+            assert_eq!(scanner.scan_token().token_type, TokenType::ERROR, "{}", literal);
+        }
+
+        // A trailing '.' isn't part of the number, so `1_000.` fails on the dot
+        let mut scanner = Scanner::new("1_000.", true);
+        assert_eq!(scanner.scan_token().lexeme.lexeme(), "1_000");
+        assert_eq!(scanner.scan_token().token_type, TokenType::ERROR);
+    }
+
     #[test]
     fn scan_identifier() {
         let mut scanner = Scanner::new("hello", true);
@@ -275,4 +679,33 @@ mod tests {
         assert_eq!(token.token_type, TokenType::Identifier);
         assert_eq!(token.lexeme.lexeme(), "hello");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_is_incomplete() {
+        let incomplete = [
+            "fn f() {",
+            "x = [1,\n2",
+            "puts (1 +",
+            "puts \"an open\nstring",
+            "/* a comment\n that goes on",
+            "if x { puts \"}\"",
+        ];
+        for code in incomplete {
+            assert!(is_incomplete(code), "{:?} should be incomplete", code);
+        }
+
+        let complete = [
+            "puts \"{\"",
+            "puts \"([\" # {",
+            "x = 1 # (unclosed in a comment",
+            "/* { */ puts 1",
+            "fn f() { \"}\" }",
+            "puts \"\\\"{\"",
+            // Too many closers is a mistake for the compiler to report, not a reason to wait
+            "puts 1 }",
+        ];
+        for code in complete {
+            assert!(!is_incomplete(code), "{:?} should be complete", code);
+        }
+    }
+}
@@ -6,7 +6,8 @@ use crate::weave::compiler::precedence::Precedence;
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: Lexeme,
-    pub line: usize
+    pub line: usize,
+    pub column: usize,
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -51,21 +52,23 @@ impl Display for Token {
 
 impl  Token {
     
-    pub fn basic_token(token_type: TokenType, span: (usize, usize), line: usize) -> Token {
+    /// `line` and `column` are 1-based and point at the token's first character
+    pub fn basic_token(token_type: TokenType, span: (usize, usize), line: usize, column: usize) -> Token {
         let lex = Lexeme::new(span.0, span.1, None);
-        Token::new(token_type, lex, line)
+        Token::new(token_type, lex, line, column)
     }
     
-    pub fn text_token(token_type: TokenType, span: (usize, usize), lextext: &str, line: usize) -> Token {
+    pub fn text_token(token_type: TokenType, span: (usize, usize), lextext: &str, line: usize, column: usize) -> Token {
         let lex = Lexeme::new(span.0, span.1, Some(lextext.to_string()));
-        Token::new(token_type, lex, line)
+        Token::new(token_type, lex, line, column)
     }
     
-    fn new(token_type: TokenType, lexeme: Lexeme, line: usize) -> Token {
+    fn new(token_type: TokenType, lexeme: Lexeme, line: usize, column: usize) -> Token {
         Token {
             token_type,
             lexeme,
-            line
+            line,
+            column,
         }
     }
 }
@@ -77,12 +80,19 @@ pub enum TokenType {
     LeftBrace, RightBrace,
     LeftBracket, RightBracket,
     Comma, Minus, Plus,
-    Semicolon, Slash, Star, Caret,
+    Semicolon, Slash, Star, Caret, Percent,
+    Question, Colon,
     // One or two character tokens.
     Bang, NEqual,
     Equal, EqEqual,
     Greater, GEqual,
     Less, LEqual,
+    PlusEqual, MinusEqual,
+    StarEqual, SlashEqual,
+    LShift, RShift,
+    
+    // Bitwise operators (Caret doubles as xor and the lambda marker)
+    Ampersand, Bar,
     
     // Logical operators
     AndAnd, OrOr,
@@ -94,16 +104,22 @@ pub enum TokenType {
     Identifier, String, Number, Container,
     // Keywords.
     //  - flow control
-    If, Else, While,
-    True, False,
+    If, Else, While, For, Break, Continue,
+    True, False, Null,
     //  - functions
     FN, Return,
+    //  - bindings
+    Const,
+    //  - word forms of &&, || and !
+    And, Or, Not,
     
-    // Print helper until print() is implemented
-    Puts, 
+    // Print helpers until print() is implemented
+    Puts, Echo,
     // END Keywords
     // Terminations
     ERROR, EOF,
+    // A line break. The parser notes these rather than handing them to the compiler.
+    Newline,
 }
 
 impl TokenType {
@@ -1,11 +1,25 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::io::{self, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const LOG_DIR: &str = ".weaver/logs";
 const LOG_FILE_NAME: &str = "weaver.log";
 const MAX_LOG_FILES: usize = 10;
 const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024; // 5MB
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// When the current log file gets rotated out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RotationPolicy {
+    /// Once the file reaches MAX_FILE_SIZE, into numbered files (weaver.log.1)
+    #[default]
+    Size,
+    /// Once the file was last written on an earlier (UTC) day, into dated files (weaver.log.2024-06-01)
+    Daily,
+    /// Whichever of the above comes first, into dated files
+    SizeOrDaily,
+}
 
 #[derive(Debug)]
 pub enum LogFileError {
@@ -46,21 +60,29 @@ impl std::error::Error for LogFileError {
 
 pub struct FileManager {
     log_dir: PathBuf,
+    policy: RotationPolicy,
 }
 
 impl FileManager {
     pub fn new() -> Self {
         Self {
             log_dir: PathBuf::from(LOG_DIR),
+            policy: RotationPolicy::default(),
         }
     }
 
     pub fn with_custom_dir<P: AsRef<Path>>(dir: P) -> Self {
         Self {
             log_dir: dir.as_ref().to_path_buf(),
+            policy: RotationPolicy::default(),
         }
     }
 
+    pub fn with_rotation_policy(mut self, policy: RotationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
     /// Create the log directory if it doesn't exist
     pub fn ensure_log_directory(&self) -> Result<(), LogFileError> {
         if self.log_dir.exists() {
@@ -107,7 +129,12 @@ impl FileManager {
         }
     }
 
-    /// Check if the current log file exceeds the size limit
+    /// Get the path a dated rotation of the log file would use (e.g., weaver.log.2024-06-01)
+    pub fn get_dated_log_path(&self, date: &str) -> PathBuf {
+        self.log_dir.join(format!("{}.{}", LOG_FILE_NAME, date))
+    }
+
+    /// Check if the current log file is due for rotation under the rotation policy
     pub fn should_rotate(&self) -> Result<bool, LogFileError> {
         let log_path = self.get_log_file_path();
         
@@ -115,17 +142,98 @@ impl FileManager {
             return Ok(false);
         }
 
-        match fs::metadata(&log_path) {
-            Ok(metadata) => Ok(metadata.len() >= MAX_FILE_SIZE),
-            Err(e) => Err(LogFileError::IoError {
-                path: log_path,
-                source: e,
-            }),
+        let metadata = fs::metadata(&log_path).map_err(|e| LogFileError::IoError {
+            path: log_path.clone(),
+            source: e,
+        })?;
+        let too_big = metadata.len() >= MAX_FILE_SIZE;
+
+        match self.policy {
+            RotationPolicy::Size => Ok(too_big),
+            RotationPolicy::Daily | RotationPolicy::SizeOrDaily => {
+                let from_earlier_day = days_since_epoch(self.modified(&log_path)?) < days_since_epoch(SystemTime::now());
+                Ok(from_earlier_day || (self.policy == RotationPolicy::SizeOrDaily && too_big))
+            }
         }
     }
 
-    /// Rotate log files by shifting them (weaver.log -> weaver.log.1, etc.)
+    /// Rotate the current log file out of the way, as the rotation policy dictates
     pub fn rotate_files(&self) -> Result<(), LogFileError> {
+        match self.policy {
+            RotationPolicy::Size => self.rotate_numbered_files(),
+            RotationPolicy::Daily | RotationPolicy::SizeOrDaily => self.rotate_dated_files(),
+        }
+    }
+
+    /// Rename the current log file after the day it was last written to, then prune the
+    /// oldest dated files beyond MAX_LOG_FILES
+    fn rotate_dated_files(&self) -> Result<(), LogFileError> {
+        let current_log = self.get_log_file_path();
+        if !current_log.exists() {
+            return Ok(());
+        }
+
+        // A size-triggered rotation can happen more than once a day, so number any repeats
+        let date = format_date(days_since_epoch(self.modified(&current_log)?));
+        let mut target = self.get_dated_log_path(&date);
+        let mut repeat = 1;
+        while target.exists() {
+            target = self.get_dated_log_path(&format!("{}.{}", date, repeat));
+            repeat += 1;
+        }
+
+        fs::rename(&current_log, &target).map_err(|e| LogFileError::IoError {
+            path: current_log.clone(),
+            source: e,
+        })?;
+
+        let dated_files = self.dated_log_files()?;
+        if dated_files.len() > MAX_LOG_FILES {
+            for oldest in &dated_files[..dated_files.len() - MAX_LOG_FILES] {
+                fs::remove_file(oldest).map_err(|e| LogFileError::IoError {
+                    path: oldest.clone(),
+                    source: e,
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Dated rotations in the log directory, oldest first
+    fn dated_log_files(&self) -> Result<Vec<PathBuf>, LogFileError> {
+        let entries = fs::read_dir(&self.log_dir).map_err(|e| LogFileError::IoError {
+            path: self.log_dir.clone(),
+            source: e,
+        })?;
+
+        let prefix = format!("{}.", LOG_FILE_NAME);
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_prefix(&prefix))
+                    .is_some_and(is_dated_suffix)
+            })
+            .collect();
+        // ISO dates sort chronologically, and repeat numbers sort after their base date
+        files.sort();
+        Ok(files)
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime, LogFileError> {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| LogFileError::IoError {
+                path: path.to_path_buf(),
+                source: e,
+            })
+    }
+
+    /// Rotate log files by shifting them (weaver.log -> weaver.log.1, etc.)
+    fn rotate_numbered_files(&self) -> Result<(), LogFileError> {
         // Remove the oldest file if it exists (weaver.log.10 when MAX_LOG_FILES=10)
         let oldest_file = self.get_rotated_log_path(MAX_LOG_FILES);  // This will be weaver.log.10
         if oldest_file.exists() {
@@ -184,8 +292,9 @@ impl FileManager {
     pub fn get_total_log_size(&self) -> Result<u64, LogFileError> {
         let mut total_size = 0;
         
-        for i in 0..MAX_LOG_FILES {
-            let log_path = self.get_rotated_log_path(i);
+        let numbered = (0..MAX_LOG_FILES).map(|i| self.get_rotated_log_path(i));
+        let dated = if self.log_dir.exists() { self.dated_log_files()? } else { vec![] };
+        for log_path in numbered.chain(dated) {
             if log_path.exists() {
                 match fs::metadata(&log_path) {
                     Ok(metadata) => total_size += metadata.len(),
@@ -207,6 +316,34 @@ impl Default for FileManager {
     }
 }
 
+fn days_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() / SECONDS_PER_DAY).unwrap_or(0)
+}
+
+/// Formats days since the Unix epoch as a YYYY-MM-DD date
+fn format_date(days: u64) -> String {
+    // Civil-from-days conversion from Howard Hinnant's date algorithms
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// True for `2024-06-01` and its repeats like `2024-06-01.2`
+fn is_dated_suffix(suffix: &str) -> bool {
+    let (date, repeat) = suffix.split_once('.').unwrap_or((suffix, "0"));
+    let bytes = date.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| if i == 4 || i == 7 { *b == b'-' } else { b.is_ascii_digit() })
+        && repeat.parse::<usize>().is_ok()
+}
+
 /// Crash the application with a detailed error message
 pub fn crash_with_error(error: LogFileError) -> ! {
     eprintln!("FATAL: Logging system failure - {}", error);
@@ -332,6 +469,69 @@ mod tests {
         assert_eq!(fs::read_to_string(&manager.get_rotated_log_path(3)).unwrap(), "old log 2");
     }
 
+    /// Backdates a file's modification time by whole days
+    fn age_file(path: &Path, days: u64) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        let then = SystemTime::now() - std::time::Duration::from_secs(days * SECONDS_PER_DAY);
+        file.set_modified(then).unwrap();
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(19_875), "2024-06-01");
+        assert_eq!(format_date(11_016), "2000-02-29");
+        assert!(is_dated_suffix("2024-06-01"));
+        assert!(is_dated_suffix("2024-06-01.2"));
+        assert!(!is_dated_suffix("3"));
+    }
+
+    #[test]
+    fn test_daily_policy_rotates_old_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileManager::with_custom_dir(temp_dir.path()).with_rotation_policy(RotationPolicy::Daily);
+        manager.ensure_log_directory().unwrap();
+
+        let log_file = manager.get_log_file_path();
+        fs::write(&log_file, "today's log").unwrap();
+        assert!(!manager.should_rotate().unwrap());
+
+        age_file(&log_file, 2);
+        assert!(manager.should_rotate().unwrap());
+
+        // The size policy doesn't care how old the file is
+        let size_manager = FileManager::with_custom_dir(temp_dir.path());
+        assert!(!size_manager.should_rotate().unwrap());
+
+        let modified = fs::metadata(&log_file).unwrap().modified().unwrap();
+        let expected = manager.get_dated_log_path(&format_date(days_since_epoch(modified)));
+        manager.rotate_files().unwrap();
+        assert!(!log_file.exists());
+        assert_eq!(fs::read_to_string(&expected).unwrap(), "today's log");
+    }
+
+    #[test]
+    fn test_dated_rotation_prunes_oldest() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileManager::with_custom_dir(temp_dir.path()).with_rotation_policy(RotationPolicy::SizeOrDaily);
+        manager.ensure_log_directory().unwrap();
+
+        for day in 1..=MAX_LOG_FILES {
+            fs::write(manager.get_dated_log_path(&format!("2024-01-{:02}", day)), "old").unwrap();
+        }
+        // Numbered rotations from the size policy are left alone
+        fs::write(manager.get_rotated_log_path(1), "numbered").unwrap();
+
+        fs::write(manager.get_log_file_path(), "current").unwrap();
+        manager.rotate_files().unwrap();
+
+        let dated = manager.dated_log_files().unwrap();
+        assert_eq!(dated.len(), MAX_LOG_FILES);
+        assert!(!manager.get_dated_log_path("2024-01-01").exists());
+        assert!(manager.get_dated_log_path("2024-01-02").exists());
+        assert!(manager.get_rotated_log_path(1).exists());
+    }
+
     #[test]
     fn test_simple_rotation() {
         let temp_dir = TempDir::new().unwrap();
@@ -5,7 +5,7 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Env
 pub mod file_manager;
 pub mod macros;
 
-pub use file_manager::{FileManager, LogFileError, crash_with_error};
+pub use file_manager::{FileManager, LogFileError, RotationPolicy, crash_with_error};
 pub use macros::{log_debug, log_error, log_info, log_warn};
 
 pub struct LoggingConfig {
@@ -13,6 +13,7 @@ pub struct LoggingConfig {
     pub console_output: bool,
     pub file_path: Option<String>,
     pub format: LogFormat,
+    pub rotation: RotationPolicy,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
@@ -36,6 +37,7 @@ impl Default for LoggingConfig {
             console_output: false,
             file_path: None,
             format: LogFormat::Text,
+            rotation: RotationPolicy::Size,
         }
     }
 }
@@ -82,7 +84,7 @@ pub fn init_logging(config: LoggingConfig) -> Result<(), Box<dyn std::error::Err
     use std::io;
     
     // Create file manager and ensure log directory exists
-    let file_manager = FileManager::new();
+    let file_manager = FileManager::new().with_rotation_policy(config.rotation);
     if let Err(e) = file_manager.ensure_log_directory() {
         crash_with_error(e);
     }
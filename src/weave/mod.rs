@@ -1,8 +1,8 @@
-pub(crate) mod vm;
+pub mod vm;
 mod color;
-mod compiler;
-pub(crate) mod shell;
-pub(crate) mod logging;
+pub(crate) mod compiler;
+pub mod shell;
+pub mod logging;
 
 pub use vm::chunk::Chunk;
 pub use vm::opcode::{Op};
\ No newline at end of file
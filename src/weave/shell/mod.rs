@@ -1 +1 @@
-pub(crate) mod repl;
\ No newline at end of file
+pub mod repl;
\ No newline at end of file
@@ -1,15 +1,81 @@
-use crate::weave::vm::vm::VM;
+use crate::weave::compiler::is_incomplete;
+use crate::weave::vm::types::{type_name, NanBoxedValue};
+use crate::weave::vm::vm::{VMError, VM};
 use rustyline::error::ReadlineError;
 use rustyline::{Editor, Config, Cmd, KeyEvent, Modifiers, KeyCode};
 use std::io::{self, Write};
+use std::time::Instant;
 
-pub fn repl() {
-    let mut vm = VM::new();
+/// How the REPL prompts for input and shows results
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplConfig {
+    pub prompt: String,
+    /// Shown while a multi-line input is incomplete
+    pub continuation_prompt: String,
+    pub echo_results: bool,
+    /// Show results with their type, e.g. `42 : number`
+    pub show_types: bool,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        ReplConfig {
+            prompt: "wv> ".to_string(),
+            continuation_prompt: "... ".to_string(),
+            echo_results: true,
+            show_types: false,
+        }
+    }
+}
+
+impl ReplConfig {
+    /// The defaults, overridden by `WEAVER_PROMPT`, `WEAVER_CONTINUATION_PROMPT`,
+    /// `WEAVER_ECHO_RESULTS` and `WEAVER_SHOW_TYPES`
+    pub fn from_env() -> ReplConfig {
+        let mut config = ReplConfig::default();
+        if let Ok(prompt) = std::env::var("WEAVER_PROMPT") {
+            config.prompt = prompt;
+        }
+        if let Ok(prompt) = std::env::var("WEAVER_CONTINUATION_PROMPT") {
+            config.continuation_prompt = prompt;
+        }
+        if let Some(echo) = env_flag("WEAVER_ECHO_RESULTS") {
+            config.echo_results = echo;
+        }
+        if let Some(show) = env_flag("WEAVER_SHOW_TYPES") {
+            config.show_types = show;
+        }
+        config
+    }
+
+    /// Formats a result for display, empty if there is nothing to show
+    pub fn format_result(&self, vm: &VM, value: NanBoxedValue) -> String {
+        let formatted = vm.format_value(value);
+        if self.show_types && !formatted.is_empty() {
+            format!("{} : {}", formatted, type_name(value))
+        } else {
+            formatted
+        }
+    }
+}
+
+/// A boolean environment variable: `1`/`true`/`yes` or `0`/`false`/`no`
+fn env_flag(name: &str) -> Option<bool> {
+    match std::env::var(name).ok()?.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn repl(mut repl_config: ReplConfig) {
+    let mut vm = VM::new(false);
     let config = Config::builder().auto_add_history(true).build();
     let mut rl: Editor<(),_> = Editor::with_config(config).unwrap();
     let mut buffer = String::new();
-    let mut prompt = "wv> ";
+    let mut continuing = false;
     loop {
+        let prompt = if continuing { &repl_config.continuation_prompt } else { &repl_config.prompt };
         let readline = rl.readline(prompt);
         match readline {
             Ok(line) => {
@@ -17,34 +83,29 @@ pub fn repl() {
                 if buffer.is_empty() && trimmed == "exit" {
                     break;
                 }
+                if buffer.is_empty() && trimmed.starts_with(':') {
+                    let _ = meta_command(&mut vm, &mut repl_config, trimmed, &mut io::stdout());
+                    continue;
+                }
                 buffer.push_str(&line);
                 buffer.push('\n');
-                // Heuristic: if code block is likely incomplete, prompt for more lines
-                // We'll use open braces/brackets/parens as a simple heuristic
-                let open_braces = buffer.chars().filter(|&c| c == '{').count();
-                let close_braces = buffer.chars().filter(|&c| c == '}').count();
-                let open_parens = buffer.chars().filter(|&c| c == '(').count();
-                let close_parens = buffer.chars().filter(|&c| c == ')').count();
-                let open_brackets = buffer.chars().filter(|&c| c == '[').count();
-                let close_brackets = buffer.chars().filter(|&c| c == ']').count();
-                let is_incomplete = open_braces > close_braces
-                    || open_parens > close_parens
-                    || open_brackets > close_brackets;
-                if is_incomplete {
-                    prompt = "... ";
+                // Keep reading lines while a bracket, string or block comment is left open
+                continuing = is_incomplete(&buffer);
+                if continuing {
                     continue;
-                } else {
-                    prompt = "wv> ";
                 }
                 let input = std::mem::take(&mut buffer);
                 match vm.interpret(&input) {
                     Ok(result) => {
-                        if !matches!(result.to_string().as_str(), "") {
-                            println!("{}", result);
+                        let formatted = repl_config.format_result(&vm, result);
+                        if repl_config.echo_results && !formatted.is_empty() {
+                            println!("{}", formatted);
                         }
                     }
+                    // `exit()` leaves the REPL rather than the process, as typing `exit` does
+                    Err(VMError::Exit(_)) => break,
                     Err(e) => {
-                        let _ = writeln!(io::stderr(), "Error: {:?}", e);
+                        let _ = writeln!(io::stderr(), "Error: {}", e);
                     }
                 }
             }
@@ -57,4 +118,235 @@ pub fn repl() {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+const HELP: &str = "\
+Commands:
+  :help          Show this message
+  :load <path>   Run a file in the current session
+  :clear         Reset the VM, forgetting all globals
+  :globals       List the names of defined globals
+  :time [n] <expr>  Run an expression (n times) and report how long it took
+  :types         Toggle showing the type of each result
+  :disasm <expr> Show the bytecode for an expression, or for a function given its name
+  exit           Leave the REPL";
+
+/// Handles a colon-prefixed REPL command, writing any output to `out`
+pub(crate) fn meta_command(vm: &mut VM, config: &mut ReplConfig, input: &str, out: &mut impl Write) -> io::Result<()> {
+    let mut parts = input.splitn(2, char::is_whitespace);
+    let command = parts.next().unwrap_or("");
+    let arg = parts.next().map(str::trim).unwrap_or("");
+
+    match command {
+        ":help" => writeln!(out, "{}", HELP),
+        ":load" => {
+            if arg.is_empty() {
+                return writeln!(out, "Usage: :load <path>");
+            }
+            match std::fs::read_to_string(arg) {
+                Ok(source) => match vm.interpret(&source) {
+                    Ok(result) => writeln!(out, "{}", config.format_result(vm, result)),
+                    Err(e) => writeln!(out, "Error: {}", e),
+                },
+                Err(e) => writeln!(out, "Could not load {}: {}", arg, e),
+            }
+        }
+        ":clear" => {
+            *vm = VM::new(false);
+            writeln!(out, "VM state cleared")
+        }
+        ":globals" => writeln!(out, "{}", vm.global_names().join("\n")),
+        ":time" => time_command(vm, config, arg, out),
+        ":types" => {
+            config.show_types = !config.show_types;
+            writeln!(out, "Result types {}", if config.show_types { "shown" } else { "hidden" })
+        }
+        ":disasm" => {
+            if arg.is_empty() {
+                return writeln!(out, "Usage: :disasm <expr>");
+            }
+            // A defined function's name shows that function's code rather than a lone global read
+            let listing = match vm.disassemble_global(arg) {
+                Some(listing) => Ok(listing),
+                None => crate::disassemble(arg),
+            };
+            match listing {
+                Ok(listing) => write!(out, "{}", listing),
+                Err(e) => writeln!(out, "Error: {}", e),
+            }
+        }
+        _ => writeln!(out, "Unknown command {}. Try :help", command),
+    }
+}
+
+/// Runs `:time [n] <expr>`, reporting the last result and the mean time per run
+fn time_command(vm: &mut VM, config: &ReplConfig, arg: &str, out: &mut impl Write) -> io::Result<()> {
+    let (runs, code) = match arg.split_once(char::is_whitespace) {
+        Some((count, rest)) => match count.parse::<u32>() {
+            Ok(n) if n > 0 => (n, rest.trim()),
+            _ => (1, arg),
+        },
+        None => (1, arg),
+    };
+    if code.is_empty() {
+        return writeln!(out, "Usage: :time [n] <expr>");
+    }
+
+    let mut result = None;
+    let start = Instant::now();
+    for _ in 0..runs {
+        match vm.interpret(code) {
+            Ok(value) => result = Some(value),
+            Err(e) => return writeln!(out, "Error: {}", e),
+        }
+    }
+    let elapsed = start.elapsed();
+
+    if let Some(value) = result {
+        writeln!(out, "{}", config.format_result(vm, value))?;
+    }
+    if runs == 1 {
+        writeln!(out, "time: {:?}", elapsed)
+    } else {
+        writeln!(out, "time: {:?} total, {:?} per run over {} runs", elapsed, elapsed / runs, runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(vm: &mut VM, input: &str) -> String {
+        let mut out = Vec::new();
+        meta_command(vm, &mut ReplConfig::default(), input, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_help() {
+        let mut vm = VM::new(false);
+        let out = run(&mut vm, ":help");
+        for command in [":help", ":load", ":clear", ":globals", ":time", ":types", ":disasm"] {
+            assert!(out.contains(command), "help is missing {}", command);
+        }
+    }
+
+    #[test]
+    fn test_load_keeps_globals() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "loaded = 40 + 2").unwrap();
+
+        let mut vm = VM::new(false);
+        let out = run(&mut vm, &format!(":load {}", file.path().display()));
+        assert_eq!(out.trim(), "42");
+
+        let res = vm.interpret("loaded");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(vm.format_value(res.unwrap()), "42");
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let mut vm = VM::new(false);
+        let out = run(&mut vm, ":load /no/such/file.wv");
+        assert!(out.starts_with("Could not load /no/such/file.wv"), "{}", out);
+
+        let out = run(&mut vm, ":load");
+        assert!(out.starts_with("Usage"), "{}", out);
+    }
+
+    #[test]
+    fn test_globals_and_clear() {
+        let mut vm = VM::new(false);
+        vm.interpret("zeta = 1; alpha = 2").unwrap();
+        let out = run(&mut vm, ":globals");
+        let names: Vec<&str> = out.lines().collect();
+        assert!(names.contains(&"alpha") && names.contains(&"zeta"), "{}", out);
+
+        run(&mut vm, ":clear");
+        let out = run(&mut vm, ":globals");
+        assert!(!out.contains("alpha"), "{}", out);
+        assert!(vm.interpret("zeta").is_err());
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut vm = VM::new(false);
+        assert!(run(&mut vm, ":bogus").starts_with("Unknown command :bogus"));
+    }
+
+    #[test]
+    fn test_time() {
+        let mut vm = VM::new(false);
+        vm.interpret("fn sq(n) { n * n }").unwrap();
+
+        let out = run(&mut vm, ":time sq(7)");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "49", "{}", out);
+        assert!(lines[1].starts_with("time: "), "{}", out);
+
+        let out = run(&mut vm, ":time 5 sq(3) + 1");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "10", "{}", out);
+        assert!(lines[1].ends_with("per run over 5 runs"), "{}", out);
+
+        // A leading number with nothing after it is the expression itself
+        assert!(run(&mut vm, ":time 12").starts_with("12\n"));
+        assert!(run(&mut vm, ":time").starts_with("Usage"));
+        assert!(run(&mut vm, ":time undefined_thing").starts_with("Error"));
+    }
+
+    #[test]
+    fn test_disasm() {
+        let mut vm = VM::new(false);
+        let out = run(&mut vm, ":disasm 1+2");
+        for op in ["ConstantByte", "ADD", "RETURN"] {
+            assert!(out.contains(op), "missing {} in {}", op, out);
+        }
+
+        // The expression is only compiled, never run
+        run(&mut vm, ":disasm x = 5");
+        assert!(vm.get_global("x").is_none());
+
+        vm.interpret("fn sq(n) { n * n }").unwrap();
+        let out = run(&mut vm, ":disasm sq");
+        assert!(out.contains("<fn sq(1)>") && out.contains("MUL"), "{}", out);
+        let out = run(&mut vm, ":disasm sq(3)");
+        assert!(out.contains("GetGlobalByIndex") && out.contains("'sq'"), "{}", out);
+
+        assert!(run(&mut vm, ":disasm 1 +").starts_with("Error"));
+        assert!(run(&mut vm, ":disasm").starts_with("Usage"));
+        // Globals survive, whatever was disassembled
+        assert!(vm.interpret("sq(3)").is_ok());
+    }
+
+    #[test]
+    fn test_format_result() {
+        let mut vm = VM::new(false);
+        let value = vm.interpret("40 + 2").unwrap();
+        let mut config = ReplConfig::default();
+        assert_eq!(config.format_result(&vm, value), "42");
+
+        config.show_types = true;
+        assert_eq!(config.format_result(&vm, value), "42 : number");
+        let value = vm.interpret("[1, \"a\"]").unwrap();
+        assert_eq!(config.format_result(&vm, value), "[1, a] : array");
+    }
+
+    #[test]
+    fn test_types_toggle() {
+        let mut vm = VM::new(false);
+        let mut config = ReplConfig::default();
+        let mut out = Vec::new();
+        meta_command(&mut vm, &mut config, ":types", &mut out).unwrap();
+        assert!(config.show_types);
+        assert_eq!(String::from_utf8(out).unwrap(), "Result types shown\n");
+
+        let mut out = Vec::new();
+        meta_command(&mut vm, &mut config, ":time true", &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("true : boolean\n"));
+
+        meta_command(&mut vm, &mut config, ":types", &mut Vec::new()).unwrap();
+        assert!(!config.show_types);
+    }
+}
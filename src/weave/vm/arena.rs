@@ -1,3 +1,4 @@
+use std::fmt;
 use std::marker::PhantomData;
 
 /// A generational arena for managing object lifetimes safely
@@ -15,6 +16,26 @@ pub struct Arena<T> {
     next_generation: u32,
 }
 
+/// A snapshot of an arena's occupancy, from `Arena::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArenaStats {
+    /// Objects currently stored
+    pub live: usize,
+    /// Slots freed and waiting to be reused
+    pub freed_slots: usize,
+    /// Every slot ever allocated, live or freed
+    pub total_slots: usize,
+    /// The generation the next insert will be stamped with
+    pub generation: u32,
+}
+
+impl fmt::Display for ArenaStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} live, {} freed, {} slots (generation {})",
+               self.live, self.freed_slots, self.total_slots, self.generation)
+    }
+}
+
 /// A handle to an object in the arena with generation checking
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Handle<T> {
@@ -156,6 +177,56 @@ impl<T> Arena<T> {
         self.objects.len()
     }
     
+    /// Releases freed slots at the end of the arena. Live objects never move, so every
+    /// outstanding handle stays valid; use `compact_and_remap` to also close interior gaps.
+    pub fn compact(&mut self) {
+        let keep = self.objects.iter().rposition(|obj| obj.is_some()).map_or(0, |last| last + 1);
+        if keep == self.objects.len() {
+            return;
+        }
+        // Generations only ever grow, so stale handles into a trimmed slot can't match
+        // whatever is allocated there later
+        self.objects.truncate(keep);
+        self.generations.truncate(keep);
+        self.free_list.retain(|&index| index < keep);
+        self.objects.shrink_to_fit();
+        self.generations.shrink_to_fit();
+    }
+
+    /// Packs every live object to the front of the arena, releasing all freed slots.
+    /// Moved objects get new handles, so callers must translate any handles they've
+    /// stored through the returned remap; old handles to moved objects become invalid.
+    pub fn compact_and_remap(&mut self) -> HandleRemap<T> {
+        let mut remap = HandleRemap { moved: Vec::new() };
+        let mut dest = 0;
+        for src in 0..self.objects.len() {
+            if self.objects[src].is_none() {
+                continue;
+            }
+            if src != dest {
+                let old = Handle { index: src, generation: self.generations[src], _phantom: PhantomData };
+                let generation = self.next_generation;
+                self.next_generation = self.next_generation.wrapping_add(1);
+                self.objects.swap(src, dest);
+                self.generations[dest] = generation;
+                remap.moved.push((old, Handle { index: dest, generation, _phantom: PhantomData }));
+            }
+            dest += 1;
+        }
+        self.free_list.clear();
+        self.compact();
+        remap
+    }
+
+    pub fn stats(&self) -> ArenaStats {
+        ArenaStats {
+            live: self.len(),
+            freed_slots: self.free_list.len(),
+            total_slots: self.objects.len(),
+            generation: self.next_generation,
+        }
+    }
+    
     /// Iterator over all live objects and their handles
     pub fn iter(&self) -> ArenaIterator<T> {
         ArenaIterator {
@@ -184,6 +255,9 @@ impl<T> Handle<T> {
     
     /// Create a handle from raw parts (unsafe - no validation)
     /// This should only be used for deserialization or testing
+    ///
+    /// # Safety
+    /// The handle is only meaningful for the arena whose slot `index` was stamped with `generation`.
     pub unsafe fn from_raw_parts(index: usize, generation: u32) -> Self {
         Self {
             index,
@@ -210,6 +284,29 @@ impl<T> Handle<T> {
     }
 }
 
+/// Old-to-new handle translations from `Arena::compact_and_remap`
+pub struct HandleRemap<T> {
+    moved: Vec<(Handle<T>, Handle<T>)>,
+}
+
+impl<T> HandleRemap<T> {
+    /// The handle to use in place of `old`. Handles to objects that didn't move map to themselves.
+    pub fn remap(&self, old: Handle<T>) -> Handle<T> {
+        self.moved.iter()
+            .find(|(from, _)| from.index == old.index && from.generation == old.generation)
+            .map_or(old, |(_, to)| Handle { index: to.index, generation: to.generation, _phantom: PhantomData })
+    }
+
+    /// Number of objects that changed position
+    pub fn len(&self) -> usize {
+        self.moved.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty()
+    }
+}
+
 /// Iterator over live objects in an arena
 pub struct ArenaIterator<'a, T> {
     arena: &'a Arena<T>,
@@ -327,6 +424,85 @@ mod tests {
         assert_eq!(items[1].1, &"c");
     }
     
+    #[test]
+    fn test_stats() {
+        let mut arena = Arena::new();
+        assert_eq!(arena.stats(), ArenaStats { live: 0, freed_slots: 0, total_slots: 0, generation: 1 });
+
+        let h1 = arena.insert("a");
+        let h2 = arena.insert("b");
+        arena.insert("c");
+        arena.remove(h1);
+        arena.remove(h2);
+        assert_eq!(arena.stats(), ArenaStats { live: 1, freed_slots: 2, total_slots: 3, generation: 4 });
+
+        // Reusing a freed slot doesn't grow the arena
+        arena.insert("d");
+        assert_eq!(arena.stats(), ArenaStats { live: 2, freed_slots: 1, total_slots: 3, generation: 5 });
+
+        // Removing through a stale handle changes nothing
+        arena.remove(h1);
+        assert_eq!(arena.stats().live, 2);
+        assert_eq!(format!("{}", arena.stats()), "2 live, 1 freed, 3 slots (generation 5)");
+    }
+
+    #[test]
+    fn test_compact_trims_trailing_free_slots() {
+        let mut arena = Arena::new();
+        let handles: Vec<_> = (0..100).map(|i| arena.insert(i)).collect();
+        for handle in &handles[50..] {
+            arena.remove(*handle);
+        }
+        // A hole in the middle has to stay put - something may still point past it
+        arena.remove(handles[10]);
+        assert_eq!(arena.capacity(), 100);
+
+        arena.compact();
+        assert_eq!(arena.capacity(), 50);
+        assert_eq!(arena.stats().freed_slots, 1);
+        for (i, handle) in handles[..50].iter().enumerate() {
+            let expected = if i == 10 { None } else { Some(&(i as i32)) };
+            assert_eq!(arena.get(*handle), expected);
+        }
+        // Handles into the trimmed tail stay dead, even once the slots are reallocated
+        assert_eq!(arena.insert(-1).index(), 10);
+        for i in 0..50 {
+            arena.insert(1000 + i);
+        }
+        assert_eq!(arena.capacity(), 100);
+        assert!(handles[50..].iter().all(|h| !arena.is_valid(*h)));
+
+        // Nothing to trim is a no-op
+        arena.compact();
+        assert_eq!(arena.capacity(), 100);
+    }
+
+    #[test]
+    fn test_compact_and_remap() {
+        let mut arena = Arena::new();
+        let handles: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+        for i in [0, 3, 4, 9] {
+            arena.remove(handles[i]);
+        }
+
+        let remap = arena.compact_and_remap();
+        assert_eq!(arena.capacity(), 6);
+        assert_eq!(arena.stats().freed_slots, 0);
+        // 1 and 2 shift down by one, 5 through 8 by three
+        assert_eq!(remap.len(), 6);
+        for i in [1, 2, 5, 6, 7, 8] {
+            let new = remap.remap(handles[i]);
+            assert_eq!(arena.get(new), Some(&(i as i32)));
+            assert!(!arena.is_valid(handles[i]), "the old handle to {} should be invalid", i);
+        }
+
+        // Already dense - nothing moves and handles map to themselves
+        let remap = arena.compact_and_remap();
+        assert!(remap.is_empty());
+        let (first, _) = arena.iter().next().unwrap();
+        assert_eq!(remap.remap(first), first);
+    }
+
     #[test]
     fn test_clear() {
         let mut arena = Arena::new();
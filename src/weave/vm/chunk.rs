@@ -1,19 +1,42 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::{Error};
 use crate::weave::Op;
 use crate::weave::vm::traits::disassemble::Disassemble;
-use crate::weave::vm::types::NanBoxedValue;
-use crate::log_debug;
+use crate::weave::vm::types::{FnClosure, NanBoxedValue, PointerTag};
 
 #[derive(Clone, Debug)]
 pub struct Chunk {
     pub code: Vec<u8>,
     pub constants: Vec<NanBoxedValue>, // Now using NanBoxedValue for 4x memory reduction
-    pub lines: Vec<(usize, usize)>
+    pub lines: Vec<(usize, usize)>,
+    /// Maps a constant's raw bits to its index in `constants`, for O(1) deduplication
+    constant_index: HashMap<u64, usize>,
+    /// The global slot each name constant resolved to, filled in by the VM as globals are used.
+    /// Chunks can be shared between VMs, so the slots are tagged with the id of the VM they're for.
+    global_slots: RefCell<(u64, Vec<Option<usize>>)>,
+}
+
+/// Constants are addressed by a u16 operand, so a chunk can hold at most this many
+pub const MAX_CONSTANTS: usize = u16::MAX as usize + 1;
+
+/// Leads every serialized chunk, followed by the format version
+const BYTECODE_MAGIC: &[u8; 4] = b"WVBC";
+pub const BYTECODE_VERSION: u8 = 3;
+
+// How each serialized constant is stored
+const CONST_VALUE: u8 = 0;   // numbers, booleans and null, as raw bits
+const CONST_STRING: u8 = 1;  // a u32 length followed by UTF-8 bytes
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chunk {
     pub fn new() -> Chunk {
-        Chunk { code: vec![], constants: vec![], lines: Vec::new() }
+        Chunk { code: vec![], constants: vec![], lines: Vec::new(), constant_index: HashMap::new(), global_slots: RefCell::default() }
     }
     
     pub fn write_op(&mut self, op: Op, line: usize) {
@@ -21,18 +44,20 @@ impl Chunk {
     }
 
     /// TODO: Helper for the dissassembler - this should probably move elsewhere
+    /// The line number if a new source line starts at `offset`, otherwise a continuation marker
     pub fn line_str(&self, offset: usize) -> String {
-        let (line_offset, line) = *self.lines.iter()
-            .find(|(l_offset, _line)| *l_offset >= offset)
-            .unwrap_or(&(0,0));
-
-        let is_newline = offset == line_offset;
-        if is_newline { format!("{:4 }", line) } else { "   |".to_string() }
+        match self.lines.iter().find(|(l_offset, _line)| *l_offset == offset) {
+            Some((_, line)) => format!("{:4 }", line),
+            None => "   |".to_string(),
+        }
     }
 
     pub(crate) fn line_number_at(&self, offset: usize) -> usize {
+        // `lines` holds the offset where each run of a line starts, so the owning run is the
+        // last one starting at or before `offset`
         let (_line_offset, line) = *self.lines.iter()
-            .find(|(l_offset, _line)| *l_offset >= offset)
+            .rev()
+            .find(|(l_offset, _line)| *l_offset <= offset)
             .unwrap_or(&(0,0));
         line
     }
@@ -49,26 +74,33 @@ impl Chunk {
         }
     }
 
-    pub fn emit_constant(&mut self, value: NanBoxedValue, line: usize) -> usize {
-        self.write_op(Op::CONSTANT, line);
-        self.add_constant(value, line)
-    }
-
-    pub fn add_constant(&mut self, value: NanBoxedValue, line: usize) -> usize {
-        let idx = self.add_constant_only(value);
-        self.write(&(idx as u16).to_be_bytes().to_vec(), line); // Write BigEndian bytes to the chunk
-        idx
+    pub fn emit_constant(&mut self, value: NanBoxedValue, line: usize) -> Result<usize, &'static str> {
+        // Resolve the index first so a full pool leaves no dangling CONSTANT op behind
+        let idx = self.add_constant_only(value)?;
+        // Most chunks have few constants, so they get the shorter one-byte form
+        if let Ok(byte) = u8::try_from(idx) {
+            self.write_op(Op::ConstantByte, line);
+            self.write_byte(byte, line);
+        } else {
+            self.write_op(Op::CONSTANT, line);
+            self.write(&(idx as u16).to_be_bytes().to_vec(), line); // Write BigEndian bytes to the chunk
+        }
+        Ok(idx)
     }
 
     /// Add a constant to the constants table without emitting bytecode
-    pub fn add_constant_only(&mut self, value: NanBoxedValue) -> usize {
-        // NanBoxedValue implements Copy and PartialEq, so this is efficient
-        if let Some(pos) = self.constants.iter().position(|&v| v == value) {
-            pos
-        } else {
-            self.constants.push(value);
-            self.constants.len() - 1
+    pub fn add_constant_only(&mut self, value: NanBoxedValue) -> Result<usize, &'static str> {
+        // Key on bits rather than PartialEq so that `1` and `1.0` stay distinct constants
+        if let Some(&idx) = self.constant_index.get(&value.bits()) {
+            return Ok(idx);
+        }
+        if self.constants.len() >= MAX_CONSTANTS {
+            return Err("Too many constants in one chunk");
         }
+        self.constants.push(value);
+        let idx = self.constants.len() - 1;
+        self.constant_index.insert(value.bits(), idx);
+        Ok(idx)
     }
 
     pub fn get_constant(&self, idx: usize) -> NanBoxedValue {
@@ -78,12 +110,485 @@ impl Chunk {
         self.constants[idx] // Copy, not reference - NanBoxedValue is Copy
     }
 
+    /// The global slot the name constant at `idx` was resolved to by VM `vm_id`, if it has been
+    pub fn cached_global_slot(&self, vm_id: u64, idx: usize) -> Option<usize> {
+        let cache = self.global_slots.borrow();
+        if cache.0 != vm_id {
+            return None;
+        }
+        cache.1.get(idx).copied().flatten()
+    }
+
+    /// Remembers VM `vm_id`'s slot for the name constant at `idx`, forgetting any other VM's slots
+    pub fn cache_global_slot(&self, vm_id: u64, idx: usize, slot: usize) {
+        let mut cache = self.global_slots.borrow_mut();
+        if cache.0 != vm_id {
+            *cache = (vm_id, vec![]);
+        }
+        let slots = &mut cache.1;
+        if slots.len() <= idx {
+            slots.resize(idx + 1, None);
+        }
+        slots[idx] = Some(slot);
+    }
+
+    /// Prints the listing from `disassemble_to_string`
     pub fn disassemble(&self, name: &str) -> Result<(), Error> {
-        log_debug!("Disassemble chunk", chunk_name = name);
+        print!("{}", self.disassemble_to_string(name));
+        Ok(())
+    }
+
+    pub fn disassemble_to_string(&self, name: &str) -> String {
+        let mut listing = format!("== {} ==\n", name);
         let mut offset = 0;
         while offset < self.code.len() {
-            offset = Op::at(self.code[offset]).disassemble(offset, self);
+            offset = Op::at(self.code[offset]).disassemble(offset, self, &mut listing);
+        }
+        listing
+    }
+
+    /// Encodes the chunk for a `.weavec` file: magic and version, then the code, line table and
+    /// constants, with every length and offset as a big-endian u32. Functions can't be saved yet.
+    pub fn serialize(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = BYTECODE_MAGIC.to_vec();
+        bytes.push(BYTECODE_VERSION);
+
+        write_u32(&mut bytes, self.code.len());
+        bytes.extend_from_slice(&self.code);
+
+        write_u32(&mut bytes, self.lines.len());
+        for (offset, line) in &self.lines {
+            write_u32(&mut bytes, *offset);
+            write_u32(&mut bytes, *line);
+        }
+
+        write_u32(&mut bytes, self.constants.len());
+        for value in &self.constants {
+            if value.is_string() {
+                bytes.push(CONST_STRING);
+                write_u32(&mut bytes, value.as_string().len());
+                bytes.extend_from_slice(value.as_string().as_bytes());
+            } else if value.is_pointer() {
+                return Err(format!("Cannot serialize constant {} - only numbers, booleans, null and strings are supported", value));
+            } else {
+                bytes.push(CONST_VALUE);
+                bytes.extend_from_slice(&value.bits().to_be_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a chunk written by `serialize`
+    pub fn deserialize(bytes: &[u8]) -> Result<Chunk, String> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        if reader.take(BYTECODE_MAGIC.len())? != BYTECODE_MAGIC {
+            return Err("Not a Weave bytecode file".to_string());
+        }
+        let version = reader.take(1)?[0];
+        if version != BYTECODE_VERSION {
+            return Err(format!("Unsupported bytecode version {} (expected {})", version, BYTECODE_VERSION));
+        }
+
+        let mut chunk = Chunk::new();
+        let code_len = reader.u32()?;
+        chunk.code = reader.take(code_len)?.to_vec();
+
+        for _ in 0..reader.u32()? {
+            let offset = reader.u32()?;
+            let line = reader.u32()?;
+            chunk.lines.push((offset, line));
+        }
+
+        let constant_count = reader.u32()?;
+        if constant_count > MAX_CONSTANTS {
+            return Err(format!("Too many constants: {}", constant_count));
+        }
+        for _ in 0..constant_count {
+            let value = match reader.take(1)?[0] {
+                CONST_VALUE => {
+                    let value = NanBoxedValue::from_bits(u64::from_be_bytes(reader.take(8)?.try_into().unwrap()));
+                    // A pointer read back from disk would point at nothing
+                    if value.is_pointer() {
+                        return Err("Invalid constant in bytecode".to_string());
+                    }
+                    value
+                }
+                CONST_STRING => {
+                    let len = reader.u32()?;
+                    let text = std::str::from_utf8(reader.take(len)?).map_err(|_| "Invalid UTF-8 in string constant".to_string())?;
+                    NanBoxedValue::string(text.to_string())
+                }
+                tag => return Err(format!("Unknown constant tag {}", tag)),
+            };
+            // Pushed directly rather than deduplicated so every index stays where it was
+            chunk.constant_index.entry(value.bits()).or_insert(chunk.constants.len());
+            chunk.constants.push(value);
+        }
+
+        if reader.pos != bytes.len() {
+            return Err("Unexpected data after the end of the chunk".to_string());
+        }
+        chunk.verify()?;
+        Ok(chunk)
+    }
+
+    /// Checks that a top-level chunk is safe to run: every opcode is known, operands fit in the
+    /// code, constants are in range and of the right type, jumps land on an instruction, and no
+    /// instruction takes more values than the stack can hold at that point.
+    /// Functions defined in the chunk are checked too.
+    pub fn verify(&self) -> Result<(), String> {
+        // A script starts with only its own closure on the stack
+        self.verify_with(0, &[(0, 1)])
+    }
+
+    /// Verifies the code of a function with `upvalue_count` upvalues, which can start running at
+    /// any of the `entries`, given as (offset, values on the stack).
+    fn verify_with(&self, upvalue_count: u8, entries: &[(usize, usize)]) -> Result<(), String> {
+        let code = &self.code;
+        let u16_at = |offset: usize| -> Result<usize, String> {
+            match code.get(offset..offset + 2) {
+                Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize),
+                None => Err(format!("Operand at {:04x} runs past the end of the code", offset)),
+            }
+        };
+        let byte_at = |offset: usize| -> Result<usize, String> {
+            code.get(offset).map(|byte| *byte as usize)
+                .ok_or_else(|| format!("Operand at {:04x} runs past the end of the code", offset))
+        };
+        let constant = |idx: usize, offset: usize| -> Result<NanBoxedValue, String> {
+            self.constants.get(idx).copied()
+                .ok_or_else(|| format!("Constant {} at {:04x} is out of range", idx, offset))
+        };
+
+        // First decode each instruction where it starts, so jumps can be checked against them
+        let mut instructions: Vec<Option<Instruction>> = vec![None; code.len()];
+        let mut offset = 0;
+        while offset < code.len() {
+            let op = Op::at(code[offset]);
+            let (operands, pops, pushes, flow) = match op {
+                Op::INVALID(byte) => return Err(format!("Unknown opcode {} at {:04x}", byte, offset)),
+                Op::SetGlobal | Op::GetGlobal => {
+                    return Err(format!("{:?} at {:04x} is no longer supported - recompile the script", op, offset));
+                }
+                Op::CONSTANT => {
+                    constant(u16_at(offset + 1)?, offset)?;
+                    (2, 0, 1, Flow::Next)
+                }
+                Op::ConstantByte => {
+                    constant(byte_at(offset + 1)?, offset)?;
+                    (1, 0, 1, Flow::Next)
+                }
+                Op::SetGlobalByIndex | Op::GetGlobalByIndex => {
+                    if !constant(u16_at(offset + 1)?, offset)?.is_string() {
+                        return Err(format!("Global name at {:04x} is not a string", offset));
+                    }
+                    // Setting a global leaves the value on the stack
+                    let pops = if op == Op::SetGlobalByIndex { 1 } else { 0 };
+                    (2, pops, pops.max(1), Flow::Next)
+                }
+                Op::Closure => {
+                    let value = constant(u16_at(offset + 1)?, offset)?;
+                    if !value.is_pointer() || value.as_pointer().1 != PointerTag::Closure {
+                        return Err(format!("Closure at {:04x} doesn't refer to a function", offset));
+                    }
+                    // SAFETY: the tag says this constant is a closure the compiler leaked for the chunk
+                    let closure = unsafe { &*(value.as_pointer().0 as *const FnClosure) };
+                    closure.func.chunk.verify_with(closure.func.upvalue_count, &closure.func.entry_points())?;
+                    // Two bytes describe each upvalue the closure captures
+                    (2 + 2 * closure.func.upvalue_count as usize, 0, 1, Flow::Next)
+                }
+                Op::Jump => (2, 0, 0, Flow::Jump(offset + 3 + u16_at(offset + 1)?)),
+                Op::JumpIfFalse => (2, 1, 0, Flow::Branch(offset + 3 + u16_at(offset + 1)?)),
+                Op::JumpIfTrue => (2, 1, 1, Flow::Branch(offset + 3 + u16_at(offset + 1)?)),
+                Op::Loop => {
+                    let target = (offset + 3).checked_sub(u16_at(offset + 1)?)
+                        .ok_or_else(|| format!("Loop at {:04x} jumps before the start of the code", offset))?;
+                    (2, 0, 0, Flow::Jump(target))
+                }
+                Op::GetUpvalue | Op::SetUpvalue => {
+                    if byte_at(offset + 1)? >= upvalue_count as usize {
+                        return Err(format!("Upvalue at {:04x} is out of range", offset));
+                    }
+                    let pops = if op == Op::SetUpvalue { 1 } else { 0 };
+                    (1, pops, 1, Flow::Next)
+                }
+                Op::BuildArray => (2, u16_at(offset + 1)?, 1, Flow::Next),
+                Op::BuildMap => (2, 2 * u16_at(offset + 1)?, 1, Flow::Next),
+                // The callee sits below its arguments, and the result replaces them all
+                Op::Call => (1, byte_at(offset + 1)? + 1, 1, Flow::Next),
+                Op::PrintN | Op::WRITE => {
+                    let count = byte_at(offset + 1)?;
+                    if count == 0 {
+                        return Err(format!("{:?} at {:04x} has no values to print", op, offset));
+                    }
+                    // Only the last value printed stays on the stack
+                    (1, count, 1, Flow::Next)
+                }
+                Op::GetLocal => (1, 0, 1, Flow::Next),
+                Op::SetLocal => (1, 1, 1, Flow::Next),
+                Op::CloseUpvalues => (1, 0, 0, Flow::Next),
+                Op::RETURN => (0, 1, 0, Flow::Stop),
+                Op::TRUE | Op::FALSE => (0, 0, 1, Flow::Next),
+                Op::POP => (0, 1, 0, Flow::Next),
+                Op::DUP => (0, 1, 2, Flow::Next),
+                Op::SWAP => (0, 2, 2, Flow::Next),
+                Op::OVER => (0, 2, 3, Flow::Next),
+                Op::NOT | Op::NEGATE | Op::PRINT => (0, 1, 1, Flow::Next),
+                Op::GREATER | Op::LESS | Op::GEqual | Op::LEqual | Op::EQUAL
+                | Op::ADD | Op::SUB | Op::MUL | Op::DIV | Op::MOD
+                | Op::BitAnd | Op::BitOr | Op::BitXor | Op::Shl | Op::Shr | Op::Index => (0, 2, 1, Flow::Next),
+                Op::Slice | Op::SetIndex => (0, 3, 1, Flow::Next),
+            };
+            instructions[offset] = Some(Instruction { len: 1 + operands, pops, pushes, flow });
+            offset += 1 + operands;
+        }
+        if offset > code.len() {
+            return Err("The last instruction runs past the end of the code".to_string());
+        }
+        // Landing exactly at the end is fine - it just ends the function
+        let lands = |target: usize| target == code.len() || instructions.get(target).is_some_and(Option::is_some);
+        for (offset, instruction) in instructions.iter().enumerate() {
+            if let Some(Instruction { flow: Flow::Jump(target) | Flow::Branch(target), .. }) = instruction
+                && !lands(*target) {
+                return Err(format!("Jump at {:04x} doesn't land on an instruction", offset));
+            }
+        }
+
+        // Then follow every path from each entry, keeping the fewest values the stack can hold
+        // at each instruction, so that nothing takes more values than are there
+        let mut depths: Vec<Option<usize>> = vec![None; code.len()];
+        let mut pending = entries.to_vec();
+        while let Some((offset, depth)) = pending.pop() {
+            if offset == code.len() {
+                continue;
+            }
+            let Some(instruction) = instructions.get(offset).and_then(Option::as_ref) else {
+                return Err(format!("Function entry {:04x} isn't an instruction", offset));
+            };
+            if depths[offset].is_some_and(|seen| seen <= depth) {
+                continue;
+            }
+            depths[offset] = Some(depth);
+            let Some(left) = depth.checked_sub(instruction.pops) else {
+                return Err(format!("{:?} at {:04x} takes {} values from the stack, which may hold only {}",
+                                   Op::at(code[offset]), offset, instruction.pops, depth));
+            };
+            let after = left + instruction.pushes;
+            match instruction.flow {
+                Flow::Next => pending.push((offset + instruction.len, after)),
+                Flow::Jump(target) => pending.push((target, after)),
+                Flow::Branch(target) => {
+                    pending.push((offset + instruction.len, after));
+                    pending.push((target, after));
+                }
+                Flow::Stop => {}
+            }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// One decoded instruction, as `Chunk::verify` sees it
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    len: usize,
+    pops: usize,
+    pushes: usize,
+    flow: Flow,
+}
+
+/// Where an instruction can send control next
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    Next,
+    Jump(usize),
+    /// Either on to the next instruction or to the target
+    Branch(usize),
+    Stop,
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u32).to_be_bytes());
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| "Bytecode ends unexpectedly".to_string())?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<usize, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weave::compiler::Compiler;
+
+    fn compile(source: &str) -> Chunk {
+        Compiler::new(source, false).compile().unwrap().chunk
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let chunk = compile("x = 1.5\ny = \"héllo\"\nif x > 1 { puts y, true, null, 7 } else { puts false }\nwhile x < 3 { x = x + 1 }");
+        let bytes = chunk.serialize().unwrap();
+        assert_eq!(&bytes[..5], b"WVBC\x03");
+
+        let loaded = Chunk::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.lines, chunk.lines);
+        assert_eq!(loaded.disassemble_to_string("script"), chunk.disassemble_to_string("script"));
+    }
+
+    #[test]
+    fn test_serialize_rejects_functions() {
+        let chunk = compile("fn f() { 1 }");
+        assert!(chunk.serialize().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_rejects_bad_input() {
+        let bytes = compile("1 + 2").serialize().unwrap();
+
+        assert!(Chunk::deserialize(b"nope").is_err());
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = BYTECODE_VERSION + 1;
+        assert!(Chunk::deserialize(&wrong_version).unwrap_err().contains("version"));
+        assert!(Chunk::deserialize(&bytes[..bytes.len() - 1]).is_err(), "Truncated bytecode should not load");
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Chunk::deserialize(&trailing).is_err());
+    }
+
+    /// A chunk holding `constants`, with `code` copied into it as-is
+    fn raw_chunk(code: &[u8], constants: &[NanBoxedValue]) -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.code = code.to_vec();
+        chunk.constants = constants.to_vec();
+        chunk
+    }
+
+    #[test]
+    fn test_verify() {
+        let name = NanBoxedValue::string("x".to_string());
+        let number = NanBoxedValue::integer(7);
+        let get_global = Op::GetGlobalByIndex.bytecode()[0];
+        let constant = Op::ConstantByte.bytecode()[0];
+        let jump = Op::Jump.bytecode()[0];
+        let looping = Op::Loop.bytecode()[0];
+        let ret = Op::RETURN.bytecode()[0];
+
+        assert!(compile("fn f(n) { g = ^() { n }\ng() }\nf(1)").verify().is_ok());
+        assert!(raw_chunk(&[get_global, 0, 0, ret], &[name]).verify().is_ok());
+
+        let bad = [
+            (raw_chunk(&[get_global, 0, 0, ret], &[number]), "not a string"),
+            (raw_chunk(&[get_global, 0, 1, ret], &[name]), "out of range"),
+            (raw_chunk(&[constant, 3], &[number]), "out of range"),
+            (raw_chunk(&[get_global, 0], &[name]), "past the end"),
+            (raw_chunk(&[200], &[]), "Unknown opcode"),
+            (raw_chunk(&[jump, 0, 1, constant, 0, ret], &[number]), "doesn't land"),
+            (raw_chunk(&[ret, looping, 0, 9], &[]), "before the start"),
+            (raw_chunk(&[Op::GetUpvalue.bytecode()[0], 0], &[]), "Upvalue"),
+            (raw_chunk(&[Op::Closure.bytecode()[0], 0, 0], &[number]), "doesn't refer to a function"),
+            // Nothing may take more values than the stack holds, on any path
+            (raw_chunk(&[Op::BuildArray.bytecode()[0], 0xff, 0, ret], &[]), "BuildArray at 0000 takes 65280 values"),
+            (raw_chunk(&[Op::BuildMap.bytecode()[0], 0x80, 0, ret], &[]), "BuildMap at 0000 takes 65536 values"),
+            (raw_chunk(&[Op::Call.bytecode()[0], 5, ret], &[]), "Call at 0000 takes 6 values"),
+            (raw_chunk(&[Op::PrintN.bytecode()[0], 0, ret], &[]), "PrintN at 0000 has no values"),
+            (raw_chunk(&[Op::WRITE.bytecode()[0], 2, ret], &[]), "WRITE at 0000 takes 2 values"),
+            (raw_chunk(&[Op::ADD.bytecode()[0], ret], &[]), "ADD at 0000 takes 2 values"),
+            (raw_chunk(&[Op::POP.bytecode()[0], ret], &[]), "RETURN at 0001 takes 1 values"),
+            // A jump can skip the push that the code after it relies on
+            (raw_chunk(&[Op::TRUE.bytecode()[0], Op::JumpIfFalse.bytecode()[0], 0, 2, constant, 0, Op::ADD.bytecode()[0], ret], &[number]), "ADD at 0006"),
+            (raw_chunk(&[Op::SetGlobal.bytecode()[0], ret], &[]), "no longer supported"),
+            (raw_chunk(&[Op::GetGlobal.bytecode()[0], ret], &[]), "no longer supported"),
+        ];
+        for (chunk, expected) in bad {
+            let err = chunk.verify().unwrap_err();
+            assert!(err.contains(expected), "{} should mention {}", err, expected);
+        }
+
+        // Loading a file runs the same checks
+        let mut bytes = raw_chunk(&[get_global, 0, 0, ret], &[number]).serialize().unwrap();
+        assert!(Chunk::deserialize(&bytes).unwrap_err().contains("not a string"));
+        bytes = raw_chunk(&[jump, 0, 0, ret], &[]).serialize().unwrap();
+        assert!(Chunk::deserialize(&bytes).is_ok());
+    }
+
+
+    #[test]
+    fn test_constant_pool_limit() {
+        let mut chunk = Chunk::new();
+        for i in 0..MAX_CONSTANTS {
+            assert_eq!(chunk.add_constant_only(NanBoxedValue::integer(i as i64)), Ok(i));
+        }
+        // Existing constants still dedup once the pool is full...
+        assert_eq!(chunk.add_constant_only(NanBoxedValue::integer(7)), Ok(7));
+        // ...but a new one can't be addressed by a u16 operand
+        assert!(chunk.emit_constant(NanBoxedValue::integer(-1), 1).is_err());
+        assert!(chunk.code.is_empty(), "A rejected constant should emit no bytecode");
+    }
+
+    #[test]
+    fn test_constant_index_width() {
+        let mut chunk = Chunk::new();
+        for i in 0..256 {
+            chunk.emit_constant(NanBoxedValue::integer(i), 1).unwrap();
+        }
+        // The first 256 constants take a one-byte index...
+        assert_eq!(chunk.code.len(), 256 * 2);
+        assert_eq!(Op::at(chunk.code[0]), Op::ConstantByte);
+
+        // ...and the rest fall back to two bytes
+        chunk.emit_constant(NanBoxedValue::integer(256), 2).unwrap();
+        assert_eq!(chunk.code.len(), 256 * 2 + 3);
+        assert_eq!(Op::at(chunk.code[512]), Op::CONSTANT);
+        assert_eq!(&chunk.code[513..], &[1, 0]);
+        let listing = chunk.disassemble_to_string("wide");
+        assert!(listing.ends_with(&format!("{:<12} {:4} '256'\n", "CONSTANT", 256)), "{}", listing);
+    }
+
+    #[test]
+    fn test_disassemble_to_string() {
+        let mut chunk = Chunk::new();
+        chunk.emit_constant(NanBoxedValue::integer(1), 1).unwrap();
+        chunk.emit_constant(NanBoxedValue::integer(2), 1).unwrap();
+        chunk.write_op(Op::ADD, 1);
+        chunk.write_op(Op::JumpIfFalse, 2);
+        chunk.write(&vec![0, 1], 2);
+        chunk.write_op(Op::POP, 2);
+        chunk.write_op(Op::RETURN, 3);
+
+        let listing = chunk.disassemble_to_string("test");
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[0], "== test ==");
+        assert_eq!(lines[1], format!("0000     1  {:<12} {:4} '1'", "ConstantByte", 0));
+        assert_eq!(lines[2], format!("0002     |  {:<12} {:4} '2'", "ConstantByte", 1));
+        assert_eq!(lines[3], "0004     |  ADD");
+        assert_eq!(lines[4], format!("0005     2  {:<12} {:4} -> 0009", "JumpIfFalse", 1));
+        assert_eq!(lines[5], "0008     |  POP");
+        assert_eq!(lines[6], "0009     3  RETURN");
+        assert_eq!(lines.len(), 7);
+    }
+
+    #[test]
+    fn test_disassemble_compiled_code() {
+        let mut compiler = crate::weave::compiler::Compiler::new("x = [1, 2]\nwhile x[0] < 3 { x[0] = x[0] + 1 }", false);
+        let listing = compiler.compile().unwrap().chunk.disassemble_to_string("script");
+        for mnemonic in ["BuildArray", "Index", "SetIndex", "LESS", "JumpIfFalse", "Loop", "SetGlobal", "RETURN"] {
+            assert!(listing.contains(mnemonic), "missing {} in\n{}", mnemonic, listing);
+        }
+    }
+}
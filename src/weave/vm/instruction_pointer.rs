@@ -1,4 +1,5 @@
 use crate::log_debug;
+use crate::weave::vm::vm::VMError;
 
 pub(crate) struct IP {
     pub ip: usize,
@@ -42,14 +43,21 @@ impl IP {
         hi << 8 | lo
     }
     
-    pub fn jump(&mut self, jmp_offset: u16) {
-        let jmp_offset = self.ip + jmp_offset as usize;
-        self.ip = jmp_offset;
+    /// Jumps forward. Landing exactly at the end of the bytecode is allowed - it just ends
+    /// execution - but anything past it means the chunk is malformed.
+    pub fn jump(&mut self, jmp_offset: u16) -> Result<(), VMError> {
+        let target = self.ip + jmp_offset as usize;
+        if target > self.bytecode.len() {
+            return Err(VMError::InvalidChunk);
+        }
+        self.ip = target;
+        Ok(())
     }
 
-    pub fn jump_back(&mut self, jmp_offset: u16) {
-        let jmp_offset = self.ip - jmp_offset as usize;
-        self.ip = jmp_offset;
+    /// Jumps backward, failing if the target would be before the start of the bytecode
+    pub fn jump_back(&mut self, jmp_offset: u16) -> Result<(), VMError> {
+        self.ip = self.ip.checked_sub(jmp_offset as usize).ok_or(VMError::InvalidChunk)?;
+        Ok(())
     }
     
     pub fn idx(&self, offset: isize) -> usize {
@@ -3,6 +3,6 @@ pub(crate) mod opcode;
 pub(crate) mod types;
 mod traits;
 mod instruction_pointer;
-pub(crate) mod arena;
+pub mod arena;
 
 pub mod vm;
@@ -1,7 +1,8 @@
+use std::fmt::Write;
 use crate::weave::Chunk;
 use crate::weave::Op::INVALID;
 use crate::weave::vm::traits::disassemble::Disassemble;
-use crate::log_debug;
+use crate::weave::vm::types::{FnClosure, PointerTag, Upvalue};
 
 #[derive(Debug, PartialEq)]
 pub enum Op {
@@ -9,8 +10,11 @@ pub enum Op {
     TRUE,
     FALSE,
     CONSTANT,  // TODO: Always 64 bit double right now. Fix that.
+    ConstantByte,  // CONSTANT with a u8 index, for the first 256 constants
     SetGlobal,
     GetGlobal,
+    SetGlobalByIndex,  // SetGlobal with the name's constant index as an operand, so its slot can be cached
+    GetGlobalByIndex,
     SetLocal,
     GetLocal,
     SetUpvalue,
@@ -22,6 +26,8 @@ pub enum Op {
     // Comparison
     GREATER,
     LESS,
+    GEqual,
+    LEqual,
     EQUAL,
     
     // Arithmetic
@@ -30,19 +36,42 @@ pub enum Op {
     SUB,
     MUL,
     DIV,
+    MOD,
+
+    // Bitwise
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     
     // Control
     Loop,
     Jump,
     JumpIfFalse,
+    JumpIfTrue,  // Unlike JumpIfFalse, leaves the value it tests on the stack
     Closure,
     Call,
     RETURN,
     POP,
+    DUP,
+    SWAP,
+    OVER,
     CloseUpvalues,
 
+    // Arrays
+    BuildArray,
+    Index,
+    SetIndex,
+    Slice,
+
+    // Maps
+    BuildMap,
+
     // IO
     PRINT,
+    PrintN,
+    WRITE,
     
     // Error handling
     INVALID(u8),
@@ -79,6 +108,28 @@ impl Op {
             Op::SetUpvalue => vec![24],
             Op::GetUpvalue => vec![25],
             Op::CloseUpvalues => vec![26],
+            Op::MOD => vec![27],
+            Op::BuildArray => vec![28],
+            Op::Index => vec![29],
+            Op::SetIndex => vec![30],
+            Op::PrintN => vec![31],
+            Op::BitAnd => vec![32],
+            Op::BitOr => vec![33],
+            Op::BitXor => vec![34],
+            Op::Shl => vec![35],
+            Op::Shr => vec![36],
+            Op::DUP => vec![37],
+            Op::SWAP => vec![38],
+            Op::GEqual => vec![39],
+            Op::LEqual => vec![40],
+            Op::WRITE => vec![41],
+            Op::Slice => vec![42],
+            Op::BuildMap => vec![43],
+            Op::ConstantByte => vec![44],
+            Op::OVER => vec![45],
+            Op::SetGlobalByIndex => vec![46],
+            Op::GetGlobalByIndex => vec![47],
+            Op::JumpIfTrue => vec![48],
             
             Op::INVALID(byte) => vec![255],
         }
@@ -115,6 +166,28 @@ impl Op {
             24 => Op::SetUpvalue,
             25 => Op::GetUpvalue,
             26 => Op::CloseUpvalues,
+            27 => Op::MOD,
+            28 => Op::BuildArray,
+            29 => Op::Index,
+            30 => Op::SetIndex,
+            31 => Op::PrintN,
+            32 => Op::BitAnd,
+            33 => Op::BitOr,
+            34 => Op::BitXor,
+            35 => Op::Shl,
+            36 => Op::Shr,
+            37 => Op::DUP,
+            38 => Op::SWAP,
+            39 => Op::GEqual,
+            40 => Op::LEqual,
+            41 => Op::WRITE,
+            42 => Op::Slice,
+            43 => Op::BuildMap,
+            44 => Op::ConstantByte,
+            45 => Op::OVER,
+            46 => Op::SetGlobalByIndex,
+            47 => Op::GetGlobalByIndex,
+            48 => Op::JumpIfTrue,
 
             _ => INVALID(byte), // Should never happen, but when it does - die.
         }
@@ -128,114 +201,74 @@ impl From<u8> for Op {
 }
 
 impl Disassemble for Op {
-    fn disassemble(&self, offset: usize, chunk: &Chunk) -> usize {
+    fn disassemble(&self, offset: usize, chunk: &Chunk, out: &mut String) -> usize {
+        let _ = write!(out, "{:04x}  {}  ", offset, chunk.line_str(offset));
         match self {
             Op::CONSTANT => {
-                log_debug!("Disassemble CONSTANT/Closure start", offset = format!("{:04x}", offset).as_str(), line = chunk.line_str(offset).as_str());
-                let mut offset = offset + 1; // Skip the opcode, already consumed
-
-                // Read two bytes for the index
-                let idx = u16::from_be_bytes(chunk.code[offset..offset + 2].try_into().unwrap()) as usize;
-                offset += 2;
-
-                // Now retrieve the value from the constants table and print it
-                let value = &chunk.constants[idx];
-                log_debug!("Disassemble CONSTANT", idx = idx, value = format!("{}", value).as_str());
-                offset
+                let idx = read_u16(chunk, offset + 1) as usize;
+                let _ = writeln!(out, "{:<12} {:4} '{}'", "CONSTANT", idx, chunk.constants[idx]);
+                offset + 3
+            },
+            Op::ConstantByte => {
+                let idx = chunk.code[offset + 1] as usize;
+                let _ = writeln!(out, "{:<12} {:4} '{}'", "ConstantByte", idx, chunk.constants[idx]);
+                offset + 2
+            },
+            Op::SetGlobalByIndex | Op::GetGlobalByIndex => {
+                let idx = read_u16(chunk, offset + 1) as usize;
+                let _ = writeln!(out, "{:<12} {:4} '{}'", format!("{:?}", self), idx, chunk.constants[idx]);
+                offset + 3
             },
             Op::Closure => {
-                #[cfg(debug_assertions)]
-                print!("{0:04x}  {1}  {2:?}", offset, chunk.line_str(offset), self);
-                let mut offset = offset + 1; // Skip the opcode, already consumed
-
-                // Read two bytes for the function index
-                let idx = u16::from_be_bytes(chunk.code[offset..offset + 2].try_into().unwrap()) as usize;
-                offset += 2;
-
-                // retrieve the value from the constants table and print it
+                let idx = read_u16(chunk, offset + 1) as usize;
                 let value = &chunk.constants[idx];
-                #[cfg(debug_assertions)]
-                println!("\t{0:04x}  {1}", idx, value);
-                
-                // Now read N upvalues, and show them too.
-                if value.is_pointer() {
-                    let (_ptr, tag) = value.as_pointer();
-                    match tag {
-                        crate::weave::vm::types::PointerTag::Closure => {
-                            // Cast pointer back to get upvalue count
-                            let closure_ref = unsafe { &*(_ptr as *const crate::weave::vm::types::FnClosure) };
-                            let upvalue_count = closure_ref.func.upvalue_count;
-                            
-                            #[cfg(debug_assertions)]
-                            println!("\t\tClosure with {} upvalues", upvalue_count);
-                            
-                            // Read the upvalue bytes that the VM expects
-                            for i in 0..upvalue_count {
-                                let upvalue = crate::weave::vm::types::Upvalue::from_bytes(&chunk.code, offset);
-                                #[cfg(debug_assertions)]
-                                println!("\t\t{0}  {1:04x}", upvalue, i);
-                                offset += 2;
-                            }
-                        }
-                        _ => {
-                            #[cfg(debug_assertions)]
-                            println!("\t\tNot a closure pointer.");
-                        }
+                let mut offset = offset + 3;
+
+                if value.is_pointer() && value.as_pointer().1 == PointerTag::Closure {
+                    let closure = unsafe { &*(value.as_pointer().0 as *const FnClosure) };
+                    let _ = writeln!(out, "{:<12} {:4} {}", "Closure", idx, closure.func);
+                    // The upvalue descriptors follow the opcode, two bytes apiece
+                    for _ in 0..closure.func.upvalue_count {
+                        let upvalue = Upvalue::from_bytes(&chunk.code, offset);
+                        let _ = writeln!(out, "{:04x}     |  {:<12} {:4}", offset, upvalue, upvalue.idx);
+                        offset += 2;
                     }
                 } else {
-                    #[cfg(debug_assertions)]
-                    println!("\t\tNot a pointer.");
+                    let _ = writeln!(out, "{:<12} {:4} {}", "Closure", idx, value);
                 }
-                
                 offset
             },
             Op::Call => {
-                let mut offset = offset;
-                log_debug!("Disassemble Call start", offset = format!("{:04x}", offset).as_str(), line = chunk.line_str(offset).as_str());
-                offset += 1; // We've read our opcode, next, get the jump offset
-                let slot = (offset as isize - chunk.code[offset] as isize) as usize;
-                offset += 1;
-                log_debug!("Disassemble Call", slot = slot);
-                offset
+                let _ = writeln!(out, "{:<12} {:4}", "Call", chunk.code[offset + 1]);
+                offset + 2
             }
             Op::Loop => {
-                let mut offset = offset;
-                log_debug!("Disassemble Loop start", offset = format!("{:04x}", offset).as_str(), line = chunk.line_str(offset).as_str());
-                offset += 1; // We've read our opcode, next, get the jump offset
-                let jump = u16::from_be_bytes(chunk.code[offset..offset + 2].try_into().unwrap()) as usize;
-                offset += 2;
-                let new_pos = (offset as isize - jump as isize) as usize;
-                log_debug!("Disassemble Loop", new_position = format!("{:04x}", new_pos).as_str());
-
-                offset
-            }, 
-            Op::Jump | Op::JumpIfFalse => {
-                let mut offset = offset;
-                log_debug!("Disassemble Jump start", offset = format!("{:04x}", offset).as_str(), line = chunk.line_str(offset).as_str(), opcode = format!("{:?}", self).as_str());
-                offset += 1; // We've read our opcode, next, get the jump offset
-                let jump = u16::from_be_bytes(chunk.code[offset..offset + 2].try_into().unwrap()) as usize;
-                offset += 2;
-                log_debug!("Disassemble Jump", target = format!("{:04x}", offset + jump).as_str());
-                
-                offset
+                let jump = read_u16(chunk, offset + 1) as usize;
+                let _ = writeln!(out, "{:<12} {:4} -> {:04x}", "Loop", jump, (offset + 3).saturating_sub(jump));
+                offset + 3
+            },
+            Op::Jump | Op::JumpIfFalse | Op::JumpIfTrue => {
+                let jump = read_u16(chunk, offset + 1) as usize;
+                let _ = writeln!(out, "{:<12} {:4} -> {:04x}", format!("{:?}", self), jump, offset + 3 + jump);
+                offset + 3
             }
-            Op::GetLocal | Op::SetLocal => {
-                log_debug!("Disassemble Local start", offset = format!("{:04x}", offset).as_str(), line = chunk.line_str(offset).as_str(), opcode = format!("{:?}", self).as_str());
-                // Lookup the slot and print its contents
-                let slot = chunk.code[offset + 1];
-                let value = &chunk.constants.get(slot as usize);
-                log_debug!("Disassemble Local", slot = slot, value = format!("{:?}", value).as_str());
-                offset + 2
+            Op::BuildArray | Op::BuildMap => {
+                let _ = writeln!(out, "{:<12} {:4}", format!("{:?}", self), read_u16(chunk, offset + 1));
+                offset + 3
             }
-            Op::GetUpvalue | Op::SetUpvalue | Op::CloseUpvalues => {
-                #[cfg(debug_assertions)]
-                println!("{0:04x}  {1}  {2:?}", offset, chunk.line_str(offset), self);
+            Op::PrintN | Op::WRITE | Op::GetLocal | Op::SetLocal
+            | Op::GetUpvalue | Op::SetUpvalue | Op::CloseUpvalues => {
+                let _ = writeln!(out, "{:<12} {:4}", format!("{:?}", self), chunk.code[offset + 1]);
                 offset + 2
             }
             op => {
-                log_debug!("Disassemble Op", offset = format!("{:04x}", offset).as_str(), line = chunk.line_str(offset).as_str(), opcode = format!("{:?}", op).as_str());
+                let _ = writeln!(out, "{:?}", op);
                 offset + 1
             }
         }
     }
 }
+
+fn read_u16(chunk: &Chunk, offset: usize) -> u16 {
+    u16::from_be_bytes(chunk.code[offset..offset + 2].try_into().unwrap())
+}
@@ -1,5 +1,6 @@
 use crate::weave::Chunk;
 
 pub trait Disassemble {
-    fn disassemble(&self, offset: usize, chunk: &Chunk) -> usize;
-}
\ No newline at end of file
+    /// Appends the listing for the item at `offset` to `out`, returning the offset of the next one
+    fn disassemble(&self, offset: usize, chunk: &Chunk, out: &mut String) -> usize;
+}
@@ -0,0 +1,103 @@
+use crate::weave::vm::types::NanBoxedValue;
+use std::collections::HashMap;
+use std::ops::Index;
+
+/// The VM's global variables. Each name gets a slot the first time it is seen, and keeps it for
+/// good - so bytecode can remember a slot and skip looking the name up again.
+#[derive(Debug, Default)]
+pub struct Globals {
+    slots: HashMap<String, usize>,
+    /// The value in each slot. A slot stays None until its global is first assigned.
+    values: Vec<Option<NanBoxedValue>>,
+}
+
+impl Globals {
+    pub fn new() -> Globals {
+        Globals::default()
+    }
+
+    /// The slot for `name`, reserving an empty one if the name hasn't been seen before
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        self.values.push(None);
+        let slot = self.values.len() - 1;
+        self.slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    /// The value in `slot`, if its global has been assigned
+    pub fn get_slot(&self, slot: usize) -> Option<NanBoxedValue> {
+        self.values.get(slot).copied().flatten()
+    }
+
+    pub fn set_slot(&mut self, slot: usize, value: NanBoxedValue) {
+        self.values[slot] = Some(value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NanBoxedValue> {
+        self.values.get(*self.slots.get(name)?)?.as_ref()
+    }
+
+    /// Assigns the global `name`, defining it if need be
+    pub fn insert(&mut self, name: String, value: NanBoxedValue) {
+        let slot = self.slot(&name);
+        self.set_slot(slot, value);
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Names of the globals that have been assigned, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.slots.iter()
+            .filter(|(_, slot)| self.values[**slot].is_some())
+            .map(|(name, _)| name)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &NanBoxedValue> {
+        self.values.iter().flatten()
+    }
+
+    /// How many globals have been assigned
+    pub fn len(&self) -> usize {
+        self.values().count()
+    }
+}
+
+impl Index<&str> for Globals {
+    type Output = NanBoxedValue;
+
+    fn index(&self, name: &str) -> &NanBoxedValue {
+        self.get(name).unwrap_or_else(|| panic!("Undefined global {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slots_are_stable() {
+        let mut globals = Globals::new();
+        let x = globals.slot("x");
+        assert_eq!(globals.get_slot(x), None);
+        assert!(!globals.contains_key("x"));
+        assert_eq!(globals.len(), 0);
+
+        globals.insert("y".to_string(), NanBoxedValue::integer(1));
+        globals.insert("x".to_string(), NanBoxedValue::integer(2));
+        assert_eq!(globals.slot("x"), x);
+        assert_eq!(globals.get_slot(x), Some(NanBoxedValue::integer(2)));
+
+        // Redefining a global reuses its slot
+        globals.set_slot(x, NanBoxedValue::integer(3));
+        assert_eq!(globals["x"], NanBoxedValue::integer(3));
+        assert_eq!(globals.len(), 2);
+        let mut names: Vec<&String> = globals.keys().collect();
+        names.sort();
+        assert_eq!(names, ["x", "y"]);
+    }
+}
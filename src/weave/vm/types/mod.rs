@@ -2,16 +2,27 @@ mod weave_number;
 mod nan_boxed_value;
 
 mod weave_string;
+mod string_pool;
+mod globals;
+mod weave_array;
+mod weave_map;
 mod weave_fn;
 mod native_fn;
 mod weave_upvalue;
+mod value;
 mod upvalues;
-pub use weave_fn::{WeaveFn, FnClosure, Upvalue};
+pub use weave_fn::{WeaveFn, FnClosure, FnParam, Upvalue};
 pub use weave_upvalue::WeaveUpvalue;
-pub use native_fn::{ NativeFn, NativeFnType };
+pub use native_fn::{ NativeContext, NativeFn, NativeFnType };
+pub(crate) use native_fn::type_name;
 pub use nan_boxed_value::{NanBoxedValue, PointerTag};
 pub use weave_string::WeaveString;
+pub use string_pool::StringPool;
+pub use globals::Globals;
+pub use weave_array::{format_value, WeaveArray};
+pub use weave_map::WeaveMap;
 pub use weave_number::WeaveNumber;
+pub use value::Value;
 
 // Arena type aliases for VM use
 use crate::weave::vm::arena::{Arena, Handle};
@@ -21,4 +32,8 @@ pub type StringArena = Arena<WeaveString>;
 pub type StringHandle = Handle<WeaveString>;
 pub type UpvalueArena = Arena<WeaveUpvalue>;
 pub type UpvalueHandle = Handle<WeaveUpvalue>;
+pub type ArrayArena = Arena<WeaveArray>;
+pub type ArrayHandle = Handle<WeaveArray>;
+pub type MapArena = Arena<WeaveMap>;
+pub type MapHandle = Handle<WeaveMap>;
 
@@ -1,16 +1,19 @@
 use std::fmt;
+use crate::weave::vm::types::WeaveNumber;
 
 /// NaN-boxing implementation for efficient value representation
 ///
 /// Uses IEEE 754 double-precision floating-point representation to encode
 /// multiple value types in a single 64-bit value:
 ///
-/// - Numbers: Stored directly as f64 values
+/// - Numbers: Stored directly as f64 values, with every NaN stored as 0x7FF8000000000000
+/// - Integers: 48-bit signed payload under a negative quiet NaN (0xFFF9...)
+/// - Map handles: under the next negative quiet NaN tag (0xFFFA...)
 /// - Boolean true: 0x7FF8000000000003
 /// - Boolean false: 0x7FF8000000000002
 /// - Null: 0x7FF8000000000004
 /// - Pointers: Use 48-bit payload space with tag bits for type discrimination
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy)]
 pub struct NanBoxedValue {
     bits: u64,
 }
@@ -18,7 +21,11 @@ pub struct NanBoxedValue {
 // NaN-boxing bit patterns and constants
 const QUIET_NAN_MASK: u64 = 0x7FF8000000000000;
 // const PAYLOAD_MASK: u64 = 0x0007FFFFFFFFFFFF;
-// const SIGN_BIT: u64 = 0x8000000000000000;
+const SIGN_BIT: u64 = 0x8000000000000000;
+
+// Every NaN is stored as this one pattern (Rust's f64::NAN). Its payload is empty, which no
+// pointer tag, integer or special value uses, so an arithmetic NaN can't be misread as one of them.
+const CANONICAL_NAN: u64 = QUIET_NAN_MASK;
 
 // Special value encodings in the quiet NaN space
 const NULL_BITS: u64 = QUIET_NAN_MASK | 0x0004;
@@ -32,13 +39,37 @@ const CLOSURE_TAG: u64 = 0x0003000000000000;
 const NATIVE_FN_TAG: u64 = 0x0004000000000000;
 const UPVALUE_TAG: u64 = 0x0005000000000000;
 const CLOSURE_HANDLE_TAG: u64 = 0x0006000000000000;
+const ARRAY_HANDLE_TAG: u64 = 0x0007000000000000;
+const POINTER_TAG_MASK: u64 = 0x0007000000000000;
+
+// Integers live in the sign-bit half of the quiet NaN space, since all 7 pointer tags are taken.
+// x86 produces 0xFFF8000000000000 for invalid float ops, so the tag starts one step above that.
+const INT_TAG: u64 = SIGN_BIT | QUIET_NAN_MASK | 0x0001000000000000;
+const INT_TAG_MASK: u64 = 0xFFFF000000000000;
+const INT_PAYLOAD_MASK: u64 = 0x0000FFFFFFFFFFFF;
+// Maps ran out of pointer tags too, so their handles sit in the same half, one tag above the integers
+const MAP_HANDLE_TAG: u64 = SIGN_BIT | QUIET_NAN_MASK | 0x0002000000000000;
+const INT_MIN: i64 = -(1 << 47);
+const INT_MAX: i64 = (1 << 47) - 1;
 
 impl NanBoxedValue {
     /// Creates a new NanBoxedValue from a number
     #[inline]
     pub fn number(value: f64) -> Self {
+        // NaNs carry arbitrary sign and payload bits depending on how they were produced
+        let bits = if value.is_nan() { CANONICAL_NAN } else { value.to_bits() };
+        Self { bits }
+    }
+
+    /// Creates a new NanBoxedValue from an integer.
+    /// Values outside the 48-bit payload range fall back to an f64.
+    #[inline]
+    pub fn integer(value: i64) -> Self {
+        if !(INT_MIN..=INT_MAX).contains(&value) {
+            return Self::number(value as f64);
+        }
         Self {
-            bits: value.to_bits(),
+            bits: INT_TAG | (value as u64 & INT_PAYLOAD_MASK),
         }
     }
 
@@ -76,6 +107,24 @@ impl NanBoxedValue {
         }
     }
 
+    /// Creates a new NanBoxedValue from an array handle (arena-allocated)
+    #[inline]
+    pub fn array_handle(handle: crate::weave::vm::types::ArrayHandle) -> Self {
+        let packed = handle.to_u64();
+        Self {
+            bits: QUIET_NAN_MASK | ARRAY_HANDLE_TAG | (packed & 0x0000FFFFFFFFFFFF),
+        }
+    }
+
+    /// Creates a new NanBoxedValue from a map handle (arena-allocated)
+    #[inline]
+    pub fn map_handle(handle: crate::weave::vm::types::MapHandle) -> Self {
+        let packed = handle.to_u64();
+        Self {
+            bits: MAP_HANDLE_TAG | (packed & 0x0000FFFFFFFFFFFF),
+        }
+    }
+
     /// Creates a new NanBoxedValue from a raw pointer with type tag
     #[inline]
     pub fn pointer(ptr: *const (), tag: PointerTag) -> Self {
@@ -91,6 +140,8 @@ impl NanBoxedValue {
             PointerTag::NativeFn => NATIVE_FN_TAG,
             PointerTag::Upvalue => UPVALUE_TAG,
             PointerTag::ClosureHandle => CLOSURE_HANDLE_TAG,
+            PointerTag::Array => ARRAY_HANDLE_TAG,
+            PointerTag::Map => MAP_HANDLE_TAG,
         };
 
         Self {
@@ -101,16 +152,23 @@ impl NanBoxedValue {
     /// Fast type checking - returns true if this value represents a number
     #[inline]
     pub fn is_number(self) -> bool {
-        // If it's not in the quiet NaN range, it's a number
-        if (self.bits & QUIET_NAN_MASK) != QUIET_NAN_MASK {
-            true
-        } else {
-            // In the quiet NaN range - check if it's a special encoded value
-            // f64::NAN is 0x7FF8000000000000, which is just the QUIET_NAN_MASK
-            // Our special values have additional payload bits set
-            self.bits == QUIET_NAN_MASK || // f64::NAN case
-            (!self.is_null() && !self.is_boolean() && !self.is_pointer())
-        }
+        // Outside the quiet NaN range every value is a plain float. Inside it, only the
+        // canonical NaN and the integers are numbers.
+        (self.bits & QUIET_NAN_MASK) != QUIET_NAN_MASK
+            || self.bits == CANONICAL_NAN
+            || self.is_int()
+    }
+
+    /// Fast type checking - returns true if this value is an integer
+    #[inline]
+    pub fn is_int(self) -> bool {
+        (self.bits & INT_TAG_MASK) == INT_TAG
+    }
+
+    /// Fast type checking - returns true if this value is a number but not an integer
+    #[inline]
+    pub fn is_float(self) -> bool {
+        self.is_number() && !self.is_int()
     }
 
     /// Fast type checking - returns true if this value represents null
@@ -128,7 +186,10 @@ impl NanBoxedValue {
     /// Fast type checking - returns true if this value represents a pointer
     #[inline]
     pub fn is_pointer(self) -> bool {
-        (self.bits & QUIET_NAN_MASK) == QUIET_NAN_MASK && !self.is_null() && !self.is_boolean()
+        // Null, the booleans and the canonical NaN all sit under the empty tag
+        ((self.bits & (SIGN_BIT | QUIET_NAN_MASK)) == QUIET_NAN_MASK
+            && (self.bits & POINTER_TAG_MASK) != 0)
+            || self.is_map()
     }
 
     /// Fast type checking - returns true if this value represents a string
@@ -153,11 +214,85 @@ impl NanBoxedValue {
         }
     }
 
-    /// Extracts the number value (assumes is_number() == true)
+    /// Returns true if this value can be called: a closure, a bare function or a native
+    #[inline]
+    pub fn is_function(self) -> bool {
+        if self.is_pointer() {
+            let (_, tag) = self.as_pointer();
+            matches!(tag, PointerTag::ClosureHandle | PointerTag::Closure
+                | PointerTag::Function | PointerTag::NativeFn)
+        } else {
+            false
+        }
+    }
+
+    /// Fast type checking - returns true if this value represents an array handle
+    #[inline]
+    pub fn is_array(self) -> bool {
+        if self.is_pointer() {
+            let (_, tag) = self.as_pointer();
+            tag == PointerTag::Array
+        } else {
+            false
+        }
+    }
+
+    /// Fast type checking - returns true if this value represents a map handle
+    #[inline]
+    pub fn is_map(self) -> bool {
+        (self.bits & INT_TAG_MASK) == MAP_HANDLE_TAG
+    }
+
+    /// Extracts the map handle (assumes is_map() == true)
+    #[inline]
+    pub fn as_map_handle(self) -> crate::weave::vm::types::MapHandle {
+        debug_assert!(self.is_map(), "Value is not a map handle");
+        crate::weave::vm::types::MapHandle::from_u64(self.bits & 0x0000FFFFFFFFFFFF)
+    }
+
+    /// Extracts the number value, widening integers to f64 (assumes is_number() == true)
     #[inline]
     pub fn as_number(self) -> f64 {
         debug_assert!(self.is_number(), "Value is not a number");
-        f64::from_bits(self.bits)
+        if self.is_int() {
+            self.as_int() as f64
+        } else {
+            f64::from_bits(self.bits)
+        }
+    }
+
+    /// Extracts the integer value (assumes is_int() == true)
+    #[inline]
+    pub fn as_int(self) -> i64 {
+        debug_assert!(self.is_int(), "Value is not an integer");
+        // Shift the 48-bit payload up to the sign bit and back down to sign-extend it
+        ((self.bits << 16) as i64) >> 16
+    }
+
+    /// Returns both operands as integers if neither is a float
+    #[inline]
+    fn int_pair(self, other: NanBoxedValue) -> Option<(i64, i64)> {
+        if self.is_int() && other.is_int() {
+            Some((self.as_int(), other.as_int()))
+        } else {
+            None
+        }
+    }
+
+    /// The value as an i64, if it is an integer or a float with no fractional part
+    #[inline]
+    pub fn as_whole_number(self) -> Option<i64> {
+        if self.is_int() {
+            return Some(self.as_int());
+        }
+        if self.is_number() {
+            let n = self.as_number();
+            // i64::MAX isn't representable as an f64, so the upper bound is exclusive
+            if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+                return Some(n as i64);
+            }
+        }
+        None
     }
 
     /// Extracts the boolean value (assumes is_boolean() == true)
@@ -170,10 +305,15 @@ impl NanBoxedValue {
     /// Extracts the string value (assumes is_string() == true)
     #[inline]
     pub fn as_string(self) -> &'static str {
+        self.as_weave_string().as_str()
+    }
+
+    /// Extracts the underlying WeaveString (assumes is_string() == true)
+    #[inline]
+    pub fn as_weave_string(self) -> &'static crate::weave::vm::types::WeaveString {
         debug_assert!(self.is_string(), "Value is not a string");
         let (ptr, _) = self.as_pointer();
-        use crate::weave::vm::types::WeaveString;
-        unsafe { &*(ptr as *const WeaveString) }.as_str()
+        unsafe { &*(ptr as *const crate::weave::vm::types::WeaveString) }
     }
 
     /// Extracts the closure handle (assumes is_closure_handle() == true)
@@ -185,6 +325,14 @@ impl NanBoxedValue {
         crate::weave::vm::types::ClosureHandle::from_u64(handle_data)
     }
 
+    /// Extracts the array handle (assumes is_array() == true)
+    #[inline]
+    pub fn as_array_handle(self) -> crate::weave::vm::types::ArrayHandle {
+        debug_assert!(self.is_array(), "Value is not an array handle");
+        let handle_data = self.bits & 0x0000FFFFFFFFFFFF;
+        crate::weave::vm::types::ArrayHandle::from_u64(handle_data)
+    }
+
     /// Fast type checking - returns true if this value represents an upvalue
     #[inline]
     pub fn is_upvalue(self) -> bool {
@@ -205,7 +353,10 @@ impl NanBoxedValue {
         debug_assert!(self.is_pointer(), "Value is not a pointer");
 
         let ptr = (self.bits & 0x0000FFFFFFFFFFFF) as *const ();
-        let tag_bits = self.bits & 0x0007000000000000;
+        if self.is_map() {
+            return (ptr, PointerTag::Map);
+        }
+        let tag_bits = self.bits & POINTER_TAG_MASK;
 
         let tag = match tag_bits {
             STRING_TAG => PointerTag::String,
@@ -214,6 +365,7 @@ impl NanBoxedValue {
             NATIVE_FN_TAG => PointerTag::NativeFn,
             UPVALUE_TAG => PointerTag::Upvalue,
             CLOSURE_HANDLE_TAG => PointerTag::ClosureHandle,
+            ARRAY_HANDLE_TAG => PointerTag::Array,
             _ => panic!("Invalid pointer tag: {:#x}", tag_bits),
         };
 
@@ -226,13 +378,27 @@ impl NanBoxedValue {
         self.bits
     }
 
+    /// Rebuilds a value from `bits()`. Only sound for non-pointer values, since a pointer's
+    /// target won't outlive the value it came from.
+    pub(crate) fn from_bits(bits: u64) -> Self {
+        Self { bits }
+    }
+
     // Fast-path arithmetic operations for NaN-boxed values
     // These operate directly on the bit representation for maximum performance
 
     /// Fast addition of two NaN-boxed values
     /// Returns None if the operation cannot be performed (e.g., non-numeric operands)
+    ///
+    /// Integer addition, subtraction, multiplication and negation never wrap or fail: a result too
+    /// big for an integer is promoted to a float, the same as `integer()` does for out of range values.
     #[inline]
     pub fn fast_add(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if let Some((a, b)) = self.int_pair(other)
+            && let Some(sum) = a.checked_add(b)
+        {
+            return Some(NanBoxedValue::integer(sum));
+        }
         if self.is_number() && other.is_number() {
             let result = self.as_number() + other.as_number();
             Some(NanBoxedValue::number(result))
@@ -244,6 +410,11 @@ impl NanBoxedValue {
     /// Fast subtraction of two NaN-boxed values
     #[inline]
     pub fn fast_sub(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if let Some((a, b)) = self.int_pair(other)
+            && let Some(difference) = a.checked_sub(b)
+        {
+            return Some(NanBoxedValue::integer(difference));
+        }
         if self.is_number() && other.is_number() {
             let result = self.as_number() - other.as_number();
             Some(NanBoxedValue::number(result))
@@ -255,6 +426,11 @@ impl NanBoxedValue {
     /// Fast multiplication of two NaN-boxed values
     #[inline]
     pub fn fast_mul(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if let Some((a, b)) = self.int_pair(other)
+            && let Some(product) = a.checked_mul(b)
+        {
+            return Some(NanBoxedValue::integer(product));
+        }
         if self.is_number() && other.is_number() {
             let result = self.as_number() * other.as_number();
             Some(NanBoxedValue::number(result))
@@ -263,9 +439,31 @@ impl NanBoxedValue {
         }
     }
 
-    /// Fast division of two NaN-boxed values
+    /// Fast negation of a NaN-boxed value
+    #[inline]
+    pub fn fast_negate(self) -> Option<NanBoxedValue> {
+        if self.is_int() {
+            // Negating the smallest integer overflows the payload; integer() boxes that as a float
+            Some(NanBoxedValue::integer(-self.as_int()))
+        } else if self.is_number() {
+            Some(NanBoxedValue::number(-self.as_number()))
+        } else {
+            None
+        }
+    }
+
+    /// Fast division of two NaN-boxed values.
+    /// Integer division floors toward negative infinity, so `5 / 2 == 2` and `-5 / 2 == -3`.
+    /// Dividing an integer by zero falls back to float division.
     #[inline]
     pub fn fast_div(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if let Some((a, b)) = self.int_pair(other)
+            && b != 0
+        {
+            let q = a / b;
+            let q = if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q };
+            return Some(NanBoxedValue::integer(q));
+        }
         if self.is_number() && other.is_number() {
             let result = self.as_number() / other.as_number();
             Some(NanBoxedValue::number(result))
@@ -274,6 +472,25 @@ impl NanBoxedValue {
         }
     }
 
+    /// Fast remainder of two NaN-boxed values.
+    /// Integer remainders take the sign of the divisor to match floored division.
+    #[inline]
+    pub fn fast_mod(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if let Some((a, b)) = self.int_pair(other)
+            && b != 0
+        {
+            let m = a % b;
+            let m = if m != 0 && (m < 0) != (b < 0) { m + b } else { m };
+            return Some(NanBoxedValue::integer(m));
+        }
+        if self.is_number() && other.is_number() {
+            let result = self.as_number() % other.as_number();
+            Some(NanBoxedValue::number(result))
+        } else {
+            None
+        }
+    }
+
     /// Fast comparison - greater than
     #[inline]
     pub fn fast_greater(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
@@ -296,6 +513,28 @@ impl NanBoxedValue {
         }
     }
 
+    /// Fast comparison - greater than or equal; false if either side is NaN
+    #[inline]
+    pub fn fast_geq(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if self.is_number() && other.is_number() {
+            let result = self.as_number() >= other.as_number();
+            Some(NanBoxedValue::boolean(result))
+        } else {
+            None
+        }
+    }
+
+    /// Fast comparison - less than or equal; false if either side is NaN
+    #[inline]
+    pub fn fast_leq(self, other: NanBoxedValue) -> Option<NanBoxedValue> {
+        if self.is_number() && other.is_number() {
+            let result = self.as_number() <= other.as_number();
+            Some(NanBoxedValue::boolean(result))
+        } else {
+            None
+        }
+    }
+
     /// Fast equality comparison
     #[inline]
     pub fn fast_equal(self, other: NanBoxedValue) -> NanBoxedValue {
@@ -307,6 +546,13 @@ impl NanBoxedValue {
             return NanBoxedValue::boolean(a == b);
         }
 
+        // Functions compare by identity: two closures are equal only when they are the same
+        // closure handle, even if they were built from the same code. A function is never
+        // equal to a value of any other type.
+        if self.is_function() || other.is_function() {
+            return NanBoxedValue::boolean(self.bits == other.bits);
+        }
+
         // Fast path for exact bit equality (works for booleans, null, pointers)
         if self.bits == other.bits {
             return NanBoxedValue::boolean(true);
@@ -343,11 +589,28 @@ pub enum PointerTag {
     NativeFn,
     Upvalue,
     ClosureHandle,
+    Array,
+    Map,
+}
+
+/// Bit equality, except that an integer equals a float of the same value
+impl PartialEq for NanBoxedValue {
+    fn eq(&self, other: &Self) -> bool {
+        if self.bits == other.bits {
+            true
+        } else if self.is_int() != other.is_int() && self.is_number() && other.is_number() {
+            self.as_number() == other.as_number()
+        } else {
+            false
+        }
+    }
 }
 
 impl fmt::Display for NanBoxedValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_number() {
+        if self.is_int() {
+            write!(f, "{}", self.as_int())
+        } else if self.is_number() {
             write!(f, "{}", self.as_number())
         } else if self.is_boolean() {
             write!(f, "{}", self.as_boolean())
@@ -360,6 +623,16 @@ impl fmt::Display for NanBoxedValue {
             let index = handle.clone().index();
             let generation = handle.generation();
             write!(f, "<closure handle {}:{}>", index, generation)
+        } else if self.is_array() {
+            let handle = self.as_array_handle();
+            let index = handle.clone().index();
+            let generation = handle.generation();
+            write!(f, "<array handle {}:{}>", index, generation)
+        } else if self.is_map() {
+            let handle = self.as_map_handle();
+            let index = handle.clone().index();
+            let generation = handle.generation();
+            write!(f, "<map handle {}:{}>", index, generation)
         } else if self.is_pointer() {
             // For Display, we can't safely dereference non-string pointers without more context
             // So we'll just show pointer info
@@ -373,7 +646,9 @@ impl fmt::Display for NanBoxedValue {
 
 impl fmt::Debug for NanBoxedValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_number() {
+        if self.is_int() {
+            write!(f, "{}i", self.as_int())
+        } else if self.is_number() {
             write!(f, "{}", self.as_number())
         } else if self.is_boolean() {
             write!(f, "{}", self.as_boolean())
@@ -393,6 +668,10 @@ impl fmt::Debug for NanBoxedValue {
                 write!(f, "<upval {:?}>", ptr)
             } else if tag == PointerTag::ClosureHandle {
                 write!(f, "<clh {:?}>", ptr)
+            } else if tag == PointerTag::Array {
+                write!(f, "<arr {:?}>", ptr)
+            } else if tag == PointerTag::Map {
+                write!(f, "<map {:?}>", ptr)
             } else {
                 write!(f, "{:?}, {:p})", tag, ptr)
             }
@@ -409,6 +688,13 @@ impl From<f64> for NanBoxedValue {
     }
 }
 
+impl From<i64> for NanBoxedValue {
+    #[inline]
+    fn from(value: i64) -> Self {
+        Self::integer(value)
+    }
+}
+
 impl From<bool> for NanBoxedValue {
     #[inline]
     fn from(value: bool) -> Self {
@@ -423,6 +709,34 @@ impl From<()> for NanBoxedValue {
     }
 }
 
+impl From<WeaveNumber> for NanBoxedValue {
+    /// Integers too wide for the 48-bit payload become floats, as they do in arithmetic
+    fn from(value: WeaveNumber) -> Self {
+        match value {
+            WeaveNumber::Float(n) => Self::number(n),
+            WeaveNumber::Int(n) => Self::integer(n),
+            WeaveNumber::UInt(n) => match i64::try_from(n) {
+                Ok(n) => Self::integer(n),
+                Err(_) => Self::number(n as f64),
+            },
+        }
+    }
+}
+
+impl NanBoxedValue {
+    /// The number this value holds as a `WeaveNumber`, keeping integers and floats apart,
+    /// or None if it isn't a number
+    pub fn to_weave_number(self) -> Option<WeaveNumber> {
+        if self.is_int() {
+            Some(WeaveNumber::Int(self.as_int()))
+        } else if self.is_number() {
+            Some(WeaveNumber::Float(self.as_number()))
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -450,8 +764,6 @@ mod tests {
         assert_eq!(neg_zero.as_number().to_bits(), (-0.0f64).to_bits());
 
         // Test signaling NaN (should be preserved as number)
-        // Note: f64::NAN produces a quiet NaN that conflicts with our encoding scheme
-        // So we use a signaling NaN instead which has different bit pattern
         let signaling_nan_bits = 0x7FF0000000000001u64; // Signaling NaN
         let signaling_nan = f64::from_bits(signaling_nan_bits);
         let nan = NanBoxedValue::number(signaling_nan);
@@ -501,6 +813,180 @@ mod tests {
         assert_eq!(tag, PointerTag::String);
     }
 
+    #[test]
+    fn test_array_handle_encoding() {
+        use crate::weave::vm::types::{ArrayArena, WeaveArray};
+        let mut arena = ArrayArena::new();
+        arena.insert(WeaveArray::default());
+        let handle = arena.insert(WeaveArray::new(vec![NanBoxedValue::number(1.0)]));
+
+        let val = NanBoxedValue::array_handle(handle.clone());
+        assert!(val.is_pointer());
+        assert!(val.is_array());
+        assert!(!val.is_closure_handle());
+        assert!(!val.is_string());
+        assert!(!val.is_number());
+        assert_eq!(val.as_array_handle().to_u64(), handle.to_u64());
+        assert_eq!(arena.get(val.as_array_handle()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_map_handle_encoding() {
+        use crate::weave::vm::types::{MapArena, WeaveMap};
+        let mut arena = MapArena::new();
+        arena.insert(WeaveMap::new());
+        let mut map = WeaveMap::new();
+        map.set("a", NanBoxedValue::integer(1));
+        let handle = arena.insert(map);
+
+        let val = NanBoxedValue::map_handle(handle.clone());
+        assert!(val.is_pointer());
+        assert!(val.is_map());
+        assert_eq!(val.as_pointer().1, PointerTag::Map);
+        assert!(!val.is_array());
+        assert!(!val.is_number());
+        assert!(!val.is_int());
+        assert!(!val.is_null());
+        assert_eq!(val.as_map_handle().to_u64(), handle.to_u64());
+        assert_eq!(arena.get(val.as_map_handle()).unwrap().get("a"), Some(NanBoxedValue::integer(1)));
+
+        // ...and nothing else looks like a map
+        for other in [NanBoxedValue::integer(-1), NanBoxedValue::number(f64::NAN), NanBoxedValue::null()] {
+            assert!(!other.is_map(), "{:?}", other);
+        }
+        assert!(!val.is_function());
+    }
+
+    #[test]
+    fn test_integer_encoding() {
+        for &n in &[0i64, 1, -1, 42, -42, INT_MAX, INT_MIN] {
+            let val = NanBoxedValue::integer(n);
+            assert!(val.is_int(), "{} should be an integer", n);
+            assert!(val.is_number());
+            assert!(!val.is_float());
+            assert!(!val.is_pointer());
+            assert!(!val.is_null());
+            assert!(!val.is_boolean());
+            assert_eq!(val.as_int(), n);
+            assert_eq!(val.as_number(), n as f64);
+        }
+
+        // Out of range integers become floats rather than wrapping
+        let big = NanBoxedValue::integer(INT_MAX + 1);
+        assert!(big.is_float());
+        assert_eq!(big.as_number(), (INT_MAX + 1) as f64);
+
+        // The default x86 NaN has the sign bit set and must not look like an integer or pointer
+        let x86_nan = NanBoxedValue::number(f64::from_bits(0xFFF8000000000000));
+        assert!(x86_nan.is_float());
+        assert!(!x86_nan.is_pointer());
+    }
+
+    #[test]
+    fn test_nan_never_aliases_other_types() {
+        let zero = NanBoxedValue::number(0.0);
+        let computed = zero.fast_div(zero).unwrap();
+        let nans = [
+            computed,
+            NanBoxedValue::number(f64::NAN),
+            NanBoxedValue::number(-f64::NAN),
+            NanBoxedValue::number(f64::INFINITY - f64::INFINITY),
+            // NaNs whose payloads match the null, boolean, pointer and integer encodings
+            NanBoxedValue::number(f64::from_bits(NULL_BITS)),
+            NanBoxedValue::number(f64::from_bits(TRUE_BITS)),
+            NanBoxedValue::number(f64::from_bits(QUIET_NAN_MASK | STRING_TAG | 0x1234)),
+            NanBoxedValue::number(f64::from_bits(INT_TAG | 5)),
+        ];
+        for nan in nans {
+            assert_eq!(nan.bits(), CANONICAL_NAN);
+            assert!(nan.is_number());
+            assert!(nan.is_float());
+            assert!(nan.as_number().is_nan());
+            assert!(!nan.is_int());
+            assert!(!nan.is_null());
+            assert!(!nan.is_boolean());
+            assert!(!nan.is_pointer());
+            assert!(!nan.is_string());
+            assert!(!nan.is_truthy());
+            assert_eq!(format!("{}", nan), "NaN");
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let five = NanBoxedValue::integer(5);
+        let two = NanBoxedValue::integer(2);
+        assert!(five.fast_add(two).unwrap().is_int());
+        assert_eq!(five.fast_add(two).unwrap().as_int(), 7);
+        assert_eq!(five.fast_sub(two).unwrap().as_int(), 3);
+        assert_eq!(five.fast_mul(two).unwrap().as_int(), 10);
+
+        // Integer division floors, and the remainder follows the divisor's sign
+        assert_eq!(five.fast_div(two).unwrap().as_int(), 2);
+        assert_eq!(NanBoxedValue::integer(-5).fast_div(two).unwrap().as_int(), -3);
+        assert_eq!(NanBoxedValue::integer(-5).fast_mod(two).unwrap().as_int(), 1);
+        assert_eq!(five.fast_mod(NanBoxedValue::integer(-2)).unwrap().as_int(), -1);
+
+        // Mixing in a float yields a float
+        let half = five.fast_div(NanBoxedValue::number(2.0)).unwrap();
+        assert!(half.is_float());
+        assert_eq!(half.as_number(), 2.5);
+
+        // Integer division by zero falls back to float semantics
+        assert!(five.fast_div(NanBoxedValue::integer(0)).unwrap().as_number().is_infinite());
+    }
+
+    #[test]
+    fn test_integer_overflow_promotes_to_float() {
+        let max = NanBoxedValue::integer(INT_MAX);
+        let min = NanBoxedValue::integer(INT_MIN);
+        let one = NanBoxedValue::integer(1);
+
+        // Overflowing the payload...
+        let sum = max.fast_add(one).unwrap();
+        assert!(sum.is_float());
+        assert_eq!(sum.as_number(), (INT_MAX + 1) as f64);
+        let difference = min.fast_sub(one).unwrap();
+        assert!(difference.is_float());
+        assert_eq!(difference.as_number(), (INT_MIN - 1) as f64);
+        let negated = min.fast_negate().unwrap();
+        assert!(negated.is_float());
+        assert_eq!(negated.as_number(), -(INT_MIN as f64));
+
+        // ...or i64 itself still gives the float result
+        let square = max.fast_mul(max).unwrap();
+        assert!(square.is_float());
+        assert_eq!(square.as_number(), INT_MAX as f64 * INT_MAX as f64);
+        let square = min.fast_mul(min).unwrap();
+        assert!(square.is_float());
+        assert_eq!(square.as_number(), INT_MIN as f64 * INT_MIN as f64);
+
+        // Results that fit stay integers
+        assert!(max.fast_sub(one).unwrap().is_int());
+        assert_eq!(max.fast_mul(NanBoxedValue::integer(-1)).unwrap().as_int(), -INT_MAX);
+        assert_eq!(one.fast_negate().unwrap().as_int(), -1);
+        assert_eq!(NanBoxedValue::number(1.5).fast_negate().unwrap().as_number(), -1.5);
+        assert_eq!(NanBoxedValue::null().fast_negate(), None);
+    }
+
+    #[test]
+    fn test_integer_equality() {
+        assert_eq!(NanBoxedValue::integer(3), NanBoxedValue::number(3.0));
+        assert_ne!(NanBoxedValue::integer(3), NanBoxedValue::number(3.5));
+        assert!(NanBoxedValue::integer(3).fast_equal(NanBoxedValue::number(3.0)).as_boolean());
+        assert_eq!(format!("{}", NanBoxedValue::integer(-7)), "-7");
+    }
+
+    #[test]
+    fn test_whole_numbers() {
+        assert_eq!(NanBoxedValue::integer(-4).as_whole_number(), Some(-4));
+        assert_eq!(NanBoxedValue::number(8.0).as_whole_number(), Some(8));
+        assert_eq!(NanBoxedValue::number(8.5).as_whole_number(), None);
+        assert_eq!(NanBoxedValue::number(f64::NAN).as_whole_number(), None);
+        assert_eq!(NanBoxedValue::number(1e300).as_whole_number(), None);
+        assert_eq!(NanBoxedValue::boolean(true).as_whole_number(), None);
+    }
+
     #[test]
     fn test_from_traits() {
         let num_val: NanBoxedValue = 3.14.into();
@@ -557,9 +1043,15 @@ mod tests {
         assert!(quot.is_number());
         assert_eq!(quot.as_number(), 5.0 / 3.0);
 
+        // Test fast remainder
+        let rem = a.fast_mod(b).unwrap();
+        assert!(rem.is_number());
+        assert_eq!(rem.as_number(), 2.0);
+
         // Test with non-numeric operands (should return None)
         let bool_val = NanBoxedValue::boolean(true);
         assert!(a.fast_add(bool_val).is_none());
+        assert!(a.fast_mod(bool_val).is_none());
         assert!(bool_val.fast_mul(a).is_none());
     }
 
@@ -588,6 +1080,16 @@ mod tests {
         let eq2 = a.fast_equal(b);
         assert_eq!(eq2.as_boolean(), false);
 
+        // Test greater/less or equal
+        assert!(a.fast_geq(c).unwrap().as_boolean());
+        assert!(!b.fast_geq(a).unwrap().as_boolean());
+        assert!(a.fast_leq(c).unwrap().as_boolean());
+        assert!(!a.fast_leq(b).unwrap().as_boolean());
+
+        let nan = NanBoxedValue::number(f64::NAN);
+        assert!(!nan.fast_geq(a).unwrap().as_boolean());
+        assert!(!nan.fast_leq(a).unwrap().as_boolean());
+
         // Test with non-numeric operands
         let bool_val = NanBoxedValue::boolean(true);
         assert!(a.fast_greater(bool_val).is_none());
@@ -657,6 +1159,9 @@ mod tests {
 impl NanBoxedValue {
     /// Manually deallocate heap-allocated pointer if this value owns it
     /// This should be called when a value is being discarded and won't be used again
+    ///
+    /// # Safety
+    /// The value must own its allocation, and neither it nor any copy may be used afterwards.
     pub unsafe fn deallocate(&self) {
         if self.is_pointer() {
             let (ptr, tag) = self.as_pointer();
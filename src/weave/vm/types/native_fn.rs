@@ -1,7 +1,10 @@
+use std::cell::Cell;
 use std::fmt::Display;
-use crate::weave::vm::types::NanBoxedValue;
+use std::io::BufRead;
+use crate::weave::vm::types::{format_value, ArrayArena, Globals, MapArena, NanBoxedValue, PointerTag, StringPool, WeaveArray};
 use crate::weave::vm::vm::VMError;
-use std::time::SystemTime;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
 use crate::log_debug;
 
 #[derive(Debug, Clone)]
@@ -11,15 +14,151 @@ pub enum NativeFnType {
     Clock,
     ReadFile,
     WriteFile,
+    Len,
+    Sqrt,
+    Floor,
+    Ceil,
+    Abs,
+    Round,
+    Pow,
+    Mod,
+    Type,
+    Assert,
+    Str,
+    Num,
+    Upper,
+    Lower,
+    Trim,
+    Split,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Env,
+    Args,
+    Rand,
+    RandInt,
+    Sleep,
+    ClockNanos,
+    Exit,
+    Panic,
+    Defined,
+    Range,
+    Map,
+    Filter,
+    Reduce,
 }
 
 impl NativeFnType {
+    /// Natives that call Weave functions. The VM runs these itself, since a native's `func`
+    /// can't call back into it.
+    pub fn calls_back(&self) -> bool {
+        matches!(self, NativeFnType::Map | NativeFnType::Filter | NativeFnType::Reduce)
+    }
+
     pub fn variants() -> Vec<NativeFnType> {
         vec![NativeFnType::Input, 
              NativeFnType::Print, 
              NativeFnType::Clock, 
              NativeFnType::ReadFile, 
-             NativeFnType::WriteFile]
+             NativeFnType::WriteFile,
+             NativeFnType::Len,
+             NativeFnType::Sqrt,
+             NativeFnType::Floor,
+             NativeFnType::Ceil,
+             NativeFnType::Abs,
+             NativeFnType::Round,
+             NativeFnType::Pow,
+             NativeFnType::Mod,
+             NativeFnType::Type,
+             NativeFnType::Assert,
+             NativeFnType::Str,
+             NativeFnType::Num,
+             NativeFnType::Upper,
+             NativeFnType::Lower,
+             NativeFnType::Trim,
+             NativeFnType::Split,
+             NativeFnType::Contains,
+             NativeFnType::StartsWith,
+             NativeFnType::EndsWith,
+             NativeFnType::Env,
+             NativeFnType::Args,
+             NativeFnType::Rand,
+             NativeFnType::RandInt,
+             NativeFnType::Sleep,
+             NativeFnType::ClockNanos,
+             NativeFnType::Exit,
+             NativeFnType::Panic,
+             NativeFnType::Defined,
+             NativeFnType::Range,
+             NativeFnType::Map,
+             NativeFnType::Filter,
+             NativeFnType::Reduce]
+    }
+}
+
+/// The slice of VM state a native function is allowed to touch
+pub struct NativeContext<'a> {
+    pub arrays: &'a mut ArrayArena,
+    pub maps: &'a mut MapArena,
+    strings: &'a mut StringPool,
+    script_args: &'a [String],
+    globals: Option<&'a Globals>,
+    allocated: usize,
+}
+
+impl<'a> NativeContext<'a> {
+    pub fn new(arrays: &'a mut ArrayArena, maps: &'a mut MapArena, strings: &'a mut StringPool) -> NativeContext<'a> {
+        NativeContext { arrays, maps, strings, script_args: &[], globals: None, allocated: 0 }
+    }
+
+    /// Lets natives look up the VM's global variables
+    pub fn with_globals(mut self, globals: &'a Globals) -> NativeContext<'a> {
+        self.globals = Some(globals);
+        self
+    }
+
+    /// Makes the arguments the script was started with available to `args()`
+    pub fn with_script_args(mut self, script_args: &'a [String]) -> NativeContext<'a> {
+        self.script_args = script_args;
+        self
+    }
+
+    /// Interns a string, allocating it if needed where the VM's garbage collector can free it
+    pub fn string(&mut self, value: String) -> NanBoxedValue {
+        let (string, allocated) = self.strings.intern(value);
+        if allocated {
+            self.allocated += 1;
+        }
+        string
+    }
+
+    /// Hands a freshly allocated string over to the VM's string pool and garbage collector
+    pub fn track(&mut self, value: NanBoxedValue) -> NanBoxedValue {
+        if !value.is_string() {
+            return value;
+        }
+        let (string, kept) = self.strings.adopt(value);
+        if kept {
+            self.allocated += 1;
+        }
+        string
+    }
+
+    /// Allocates an array the VM's garbage collector knows how to free
+    pub fn array(&mut self, items: Vec<NanBoxedValue>) -> NanBoxedValue {
+        let handle = self.arrays.insert(WeaveArray::new(items));
+        self.allocated += 1;
+        NanBoxedValue::array_handle(handle)
+    }
+
+    /// Renders a value the way `puts` would, including the contents of arrays and maps
+    pub fn format(&self, value: NanBoxedValue) -> String {
+        format_value(self.arrays, self.maps, value)
+    }
+
+    /// Number of heap objects allocated through this context
+    pub fn allocated(&self) -> usize {
+        self.allocated
     }
 }
 
@@ -27,7 +166,10 @@ impl NativeFnType {
 pub struct NativeFn {
     pub name: NativeFnType,
     pub arity: usize,
-    pub func: fn(&[NanBoxedValue]) -> Result<NanBoxedValue, VMError>,
+    /// How many of the arguments must be given; the rest are optional
+    pub required: usize,
+    /// Errors can leave `line` as 0 - the VM fills in the line of the call
+    pub func: fn(&mut NativeContext, &[NanBoxedValue]) -> Result<NanBoxedValue, VMError>,
 }
 
 impl NativeFn {
@@ -36,35 +178,247 @@ impl NativeFn {
             NativeFnType::Input => NativeFn {
                 name: NativeFnType::Input,
                 arity: 0,
+                required: 0,
                 func: input,
             },
             NativeFnType::Print => NativeFn {
                 name: NativeFnType::Print,
                 arity: 1,
+                required: 1,
                 func: print,
             },
             NativeFnType::Clock => NativeFn {
                 name: NativeFnType::Clock,
                 arity: 0,
+                required: 0,
                 func: clock,
             },
             NativeFnType::ReadFile => NativeFn {
                 name: NativeFnType::ReadFile,
                 arity: 1,
+                required: 1,
                 func: read_file,
             },
             NativeFnType::WriteFile => NativeFn {
                 name: NativeFnType::WriteFile,
                 arity: 2,
+                required: 2,
                 func: write_file,
             },
+            NativeFnType::Len => NativeFn {
+                name: NativeFnType::Len,
+                arity: 1,
+                required: 1,
+                func: len,
+            },
+            NativeFnType::Sqrt => NativeFn {
+                name: NativeFnType::Sqrt,
+                arity: 1,
+                required: 1,
+                func: sqrt,
+            },
+            NativeFnType::Floor => NativeFn {
+                name: NativeFnType::Floor,
+                arity: 1,
+                required: 1,
+                func: floor,
+            },
+            NativeFnType::Ceil => NativeFn {
+                name: NativeFnType::Ceil,
+                arity: 1,
+                required: 1,
+                func: ceil,
+            },
+            NativeFnType::Abs => NativeFn {
+                name: NativeFnType::Abs,
+                arity: 1,
+                required: 1,
+                func: abs,
+            },
+            NativeFnType::Round => NativeFn {
+                name: NativeFnType::Round,
+                arity: 1,
+                required: 1,
+                func: round,
+            },
+            NativeFnType::Pow => NativeFn {
+                name: NativeFnType::Pow,
+                arity: 2,
+                required: 2,
+                func: pow,
+            },
+            NativeFnType::Mod => NativeFn {
+                name: NativeFnType::Mod,
+                arity: 2,
+                required: 2,
+                func: modulo,
+            },
+            NativeFnType::Type => NativeFn {
+                name: NativeFnType::Type,
+                arity: 1,
+                required: 1,
+                func: type_of,
+            },
+            NativeFnType::Assert => NativeFn {
+                name: NativeFnType::Assert,
+                arity: 2,
+                required: 2,
+                func: assert,
+            },
+            NativeFnType::Str => NativeFn {
+                name: NativeFnType::Str,
+                arity: 1,
+                required: 1,
+                func: str,
+            },
+            NativeFnType::Num => NativeFn {
+                name: NativeFnType::Num,
+                arity: 1,
+                required: 1,
+                func: num,
+            },
+            NativeFnType::Upper => NativeFn {
+                name: NativeFnType::Upper,
+                arity: 1,
+                required: 1,
+                func: upper,
+            },
+            NativeFnType::Lower => NativeFn {
+                name: NativeFnType::Lower,
+                arity: 1,
+                required: 1,
+                func: lower,
+            },
+            NativeFnType::Trim => NativeFn {
+                name: NativeFnType::Trim,
+                arity: 1,
+                required: 1,
+                func: trim,
+            },
+            NativeFnType::Split => NativeFn {
+                name: NativeFnType::Split,
+                arity: 2,
+                required: 2,
+                func: split,
+            },
+            NativeFnType::Contains => NativeFn {
+                name: NativeFnType::Contains,
+                arity: 2,
+                required: 2,
+                func: contains,
+            },
+            NativeFnType::StartsWith => NativeFn {
+                name: NativeFnType::StartsWith,
+                arity: 2,
+                required: 2,
+                func: starts_with,
+            },
+            NativeFnType::EndsWith => NativeFn {
+                name: NativeFnType::EndsWith,
+                arity: 2,
+                required: 2,
+                func: ends_with,
+            },
+            NativeFnType::Env => NativeFn {
+                name: NativeFnType::Env,
+                arity: 1,
+                required: 1,
+                func: env,
+            },
+            NativeFnType::Args => NativeFn {
+                name: NativeFnType::Args,
+                arity: 0,
+                required: 0,
+                func: args,
+            },
+            NativeFnType::Rand => NativeFn {
+                name: NativeFnType::Rand,
+                arity: 0,
+                required: 0,
+                func: rand,
+            },
+            NativeFnType::RandInt => NativeFn {
+                name: NativeFnType::RandInt,
+                arity: 2,
+                required: 2,
+                func: rand_int,
+            },
+            NativeFnType::Sleep => NativeFn {
+                name: NativeFnType::Sleep,
+                arity: 1,
+                required: 1,
+                func: sleep,
+            },
+            NativeFnType::ClockNanos => NativeFn {
+                name: NativeFnType::ClockNanos,
+                arity: 0,
+                required: 0,
+                func: clock_nanos,
+            },
+            NativeFnType::Exit => NativeFn {
+                name: NativeFnType::Exit,
+                arity: 1,
+                required: 1,
+                func: exit,
+            },
+            NativeFnType::Panic => NativeFn {
+                name: NativeFnType::Panic,
+                arity: 1,
+                required: 1,
+                func: panic,
+            },
+            NativeFnType::Defined => NativeFn {
+                name: NativeFnType::Defined,
+                arity: 1,
+                required: 1,
+                func: defined,
+            },
+            NativeFnType::Range => NativeFn {
+                name: NativeFnType::Range,
+                arity: 2,
+                required: 1,
+                func: range,
+            },
+            NativeFnType::Map => NativeFn {
+                name: NativeFnType::Map,
+                arity: 2,
+                required: 2,
+                func: runs_in_vm,
+            },
+            NativeFnType::Filter => NativeFn {
+                name: NativeFnType::Filter,
+                arity: 2,
+                required: 2,
+                func: runs_in_vm,
+            },
+            NativeFnType::Reduce => NativeFn {
+                name: NativeFnType::Reduce,
+                arity: 3,
+                required: 2,
+                func: runs_in_vm,
+            },
+        }
+    }
+}
+
+impl NativeFn {
+    /// Whether the function can be called with `arg_count` arguments
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        (self.required..=self.arity).contains(&arg_count)
+    }
+
+    pub fn arity_str(&self) -> String {
+        if self.required == self.arity {
+            format!("{}", self.arity)
+        } else {
+            format!("{} to {}", self.required, self.arity)
         }
     }
 }
 
 impl Display for NativeFn {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<n.fn: {}({})>", self.name, self.arity)
+        write!(f, "<n.fn: {}({})>", self.name, self.arity_str())
     }
 }
 
@@ -76,11 +430,43 @@ impl Display for NativeFnType {
             NativeFnType::Clock => write!(f, "clock"),
             NativeFnType::ReadFile => write!(f, "read"),
             NativeFnType::WriteFile => write!(f, "write"),
+            NativeFnType::Len => write!(f, "len"),
+            NativeFnType::Sqrt => write!(f, "sqrt"),
+            NativeFnType::Floor => write!(f, "floor"),
+            NativeFnType::Ceil => write!(f, "ceil"),
+            NativeFnType::Abs => write!(f, "abs"),
+            NativeFnType::Round => write!(f, "round"),
+            NativeFnType::Pow => write!(f, "pow"),
+            NativeFnType::Mod => write!(f, "mod"),
+            NativeFnType::Type => write!(f, "type"),
+            NativeFnType::Assert => write!(f, "assert"),
+            NativeFnType::Str => write!(f, "str"),
+            NativeFnType::Num => write!(f, "num"),
+            NativeFnType::Upper => write!(f, "upper"),
+            NativeFnType::Lower => write!(f, "lower"),
+            NativeFnType::Trim => write!(f, "trim"),
+            NativeFnType::Split => write!(f, "split"),
+            NativeFnType::Contains => write!(f, "contains"),
+            NativeFnType::StartsWith => write!(f, "starts_with"),
+            NativeFnType::EndsWith => write!(f, "ends_with"),
+            NativeFnType::Env => write!(f, "env"),
+            NativeFnType::Args => write!(f, "args"),
+            NativeFnType::Rand => write!(f, "rand"),
+            NativeFnType::RandInt => write!(f, "rand_int"),
+            NativeFnType::Sleep => write!(f, "sleep"),
+            NativeFnType::ClockNanos => write!(f, "clock_nanos"),
+            NativeFnType::Exit => write!(f, "exit"),
+            NativeFnType::Panic => write!(f, "panic"),
+            NativeFnType::Defined => write!(f, "defined"),
+            NativeFnType::Range => write!(f, "range"),
+            NativeFnType::Map => write!(f, "map"),
+            NativeFnType::Filter => write!(f, "filter"),
+            NativeFnType::Reduce => write!(f, "reduce"),
         }
     }
 }
 
-fn print(args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+fn print(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
     let printable = args
         .iter()
         .map(|a| a.to_string())
@@ -91,15 +477,29 @@ fn print(args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
     Ok(NanBoxedValue::null())
 }
 
-fn input(_args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+fn input(ctx: &mut NativeContext, _args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let line = read_line_from(&mut std::io::stdin().lock())?;
+    Ok(ctx.track(line))
+}
+
+/// Reads a single line from `reader`, minus its line ending. Returns null at EOF.
+fn read_line_from<R: BufRead>(reader: &mut R) -> Result<NanBoxedValue, VMError> {
     let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-    // TODO: Implement string support in NanBoxedValue
-    // For now, return null as placeholder
-    Ok(NanBoxedValue::null())
+    let bytes_read = reader.read_line(&mut input).map_err(|e| VMError::RuntimeError {
+        line: 0,
+        msg: format!("Failed to read input: {}", e),
+        backtrace: vec![],
+    })?;
+
+    if bytes_read == 0 {
+        return Ok(NanBoxedValue::null());
+    }
+
+    let trimmed = input.trim_end_matches(['\n', '\r']);
+    Ok(NanBoxedValue::string(trimmed.to_string()))
 }
 
-fn clock(_args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+fn clock(_ctx: &mut NativeContext, _args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
     // Get system time (ms since epoch)
     let time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -108,18 +508,603 @@ fn clock(_args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
     Ok(NanBoxedValue::number(time as f64))
 }
 
-fn read_file(args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+/// Nanoseconds on a monotonic clock, for timing code rather than telling the time.
+///
+/// An f64 only holds integers exactly up to 2^53, and nanoseconds since the Unix epoch are
+/// already well past that - so this counts from the first call in the process instead. That
+/// keeps full nanosecond resolution for roughly 104 days of uptime, after which it degrades
+/// gracefully to a few nanoseconds of rounding.
+fn clock_nanos(_ctx: &mut NativeContext, _args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+    let start = CLOCK_START.get_or_init(Instant::now);
+    Ok(NanBoxedValue::number(start.elapsed().as_nanos() as f64))
+}
+
+/// Pauses for the given number of milliseconds. Negative or NaN delays don't sleep at all.
+fn sleep(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let millis = number_arg("sleep", args[0])?;
+    // `as u64` saturates, and maps NaN to zero
+    std::thread::sleep(Duration::from_millis(millis.max(0.0) as u64));
+    Ok(NanBoxedValue::null())
+}
+
+fn read_file(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
     let path = args[0].to_string();
-    let contents = std::fs::read_to_string(path).unwrap();
+    let contents = std::fs::read_to_string(&path).map_err(|e| VMError::RuntimeError {
+        line: 0,
+        msg: format!("Failed to read '{}': {}", path, e),
+        backtrace: vec![],
+    })?;
     // TODO: this should be a Container of bytes which we can convert to a Weave String
     //       and/or format with a desired 'formatter' function
-    // For now, return null as placeholder until string support is added
-    Ok(NanBoxedValue::null())
+    Ok(ctx.string(contents))
 }
 
-fn write_file(args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+fn write_file(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
     let path = args[0].to_string();
     let contents = args[1].to_string();
     std::fs::write(path, contents).unwrap();
     Ok(NanBoxedValue::null())
 }
+
+
+fn len(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let value = args[0];
+    if value.is_string() {
+        Ok(NanBoxedValue::integer(value.as_string().chars().count() as i64))
+    } else if value.is_array() {
+        let length = ctx.arrays.get(value.as_array_handle()).map(|a| a.len()).unwrap_or(0);
+        Ok(NanBoxedValue::integer(length as i64))
+    } else if value.is_map() {
+        let length = ctx.maps.get(value.as_map_handle()).map(|m| m.len()).unwrap_or(0);
+        Ok(NanBoxedValue::integer(length as i64))
+    } else {
+        Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("Cannot take the length of {}", value),
+            backtrace: vec![],
+        })
+    }
+}
+
+/// Unwraps a numeric argument, or explains which native wanted one
+fn number_arg(fn_name: &str, value: NanBoxedValue) -> Result<f64, VMError> {
+    if value.is_number() {
+        Ok(value.as_number())
+    } else {
+        Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("{} expects a number, got {}", fn_name, value),
+            backtrace: vec![],
+        })
+    }
+}
+
+fn string_arg(fn_name: &str, value: NanBoxedValue) -> Result<&'static str, VMError> {
+    if value.is_string() {
+        Ok(value.as_string())
+    } else {
+        Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("{} expects a string, got {}", fn_name, value),
+            backtrace: vec![],
+        })
+    }
+}
+
+fn sqrt(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(NanBoxedValue::number(number_arg("sqrt", args[0])?.sqrt()))
+}
+
+fn floor(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(NanBoxedValue::number(number_arg("floor", args[0])?.floor()))
+}
+
+fn ceil(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(NanBoxedValue::number(number_arg("ceil", args[0])?.ceil()))
+}
+
+fn abs(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(NanBoxedValue::number(number_arg("abs", args[0])?.abs()))
+}
+
+fn round(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(NanBoxedValue::number(number_arg("round", args[0])?.round()))
+}
+
+fn pow(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let base = number_arg("pow", args[0])?;
+    let exponent = number_arg("pow", args[1])?;
+    Ok(NanBoxedValue::number(base.powf(exponent)))
+}
+
+fn modulo(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    number_arg("mod", args[0])?;
+    number_arg("mod", args[1])?;
+    // Same semantics as the `%` operator
+    Ok(args[0].fast_mod(args[1]).unwrap())
+}
+
+/// Name of a value's type, as reported by `type()`
+pub(crate) fn type_name(value: NanBoxedValue) -> &'static str {
+    if value.is_number() {
+        "number"
+    } else if value.is_boolean() {
+        "boolean"
+    } else if value.is_null() {
+        "null"
+    } else if value.is_string() {
+        "string"
+    } else if value.is_array() {
+        "array"
+    } else if value.is_map() {
+        "map"
+    } else if value.is_pointer() {
+        match value.as_pointer().1 {
+            PointerTag::Function | PointerTag::Closure | PointerTag::ClosureHandle | PointerTag::NativeFn => "function",
+            PointerTag::Upvalue => "upvalue",
+            PointerTag::String => "string",
+            PointerTag::Array => "array",
+            PointerTag::Map => "map",
+        }
+    } else {
+        "unknown"
+    }
+}
+
+fn type_of(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(ctx.string(type_name(args[0]).to_string()))
+}
+
+/// Fails with the given message when the condition isn't truthy
+fn assert(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    if args[0].is_truthy() {
+        Ok(NanBoxedValue::null())
+    } else {
+        Err(VMError::RuntimeError {
+            line: 0,
+            msg: args[1].to_string(),
+            backtrace: vec![],
+        })
+    }
+}
+
+/// Stops the script with the given status. The VM hands it back as `VMError::Exit`, and
+/// the command line ends the process with it.
+fn exit(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Err(VMError::Exit(exit_status(args[0])?))
+}
+
+/// The process status `exit()` was asked for, which must be a whole number that fits an i32
+fn exit_status(value: NanBoxedValue) -> Result<i32, VMError> {
+    let status = number_arg("exit", value)?;
+    if status.fract() != 0.0 || status < i32::MIN as f64 || status > i32::MAX as f64 {
+        return Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("exit expects a whole number status, got {}", value),
+            backtrace: vec![],
+        });
+    }
+    Ok(status as i32)
+}
+
+/// Aborts the script with a runtime error, which unwinds to whoever ran it
+fn panic(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Err(VMError::RuntimeError {
+        line: 0,
+        msg: ctx.format(args[0]),
+        backtrace: vec![],
+    })
+}
+
+/// Converts any value to its display string
+fn str(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = ctx.format(args[0]);
+    Ok(ctx.string(text))
+}
+
+/// Parses a string into a number, following the same integer/float split as literals.
+/// Unparseable text yields null rather than an error, so scripts can check `input()` for bad values.
+fn num(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let value = args[0];
+    if !value.is_string() {
+        return Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("num expects a string, got {}", value),
+            backtrace: vec![],
+        });
+    }
+
+    let text = value.as_string().trim();
+    if let Ok(n) = text.parse::<i64>() {
+        return Ok(NanBoxedValue::integer(n));
+    }
+    match text.parse::<f64>() {
+        Ok(n) => Ok(NanBoxedValue::number(n)),
+        Err(_) => Ok(NanBoxedValue::null()),
+    }
+}
+
+fn upper(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("upper", args[0])?.to_uppercase();
+    Ok(ctx.string(text))
+}
+
+fn lower(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("lower", args[0])?.to_lowercase();
+    Ok(ctx.string(text))
+}
+
+fn trim(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("trim", args[0])?.trim().to_string();
+    Ok(ctx.string(text))
+}
+
+/// Splits a string on a delimiter into an array of strings. An empty delimiter splits it into
+/// characters.
+fn split(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("split", args[0])?;
+    let delimiter = string_arg("split", args[1])?;
+    let pieces: Vec<String> = if delimiter.is_empty() {
+        text.chars().map(|c| c.to_string()).collect()
+    } else {
+        text.split(delimiter).map(|piece| piece.to_string()).collect()
+    };
+    let items = pieces.into_iter().map(|piece| ctx.string(piece)).collect();
+    Ok(ctx.array(items))
+}
+
+fn contains(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("contains", args[0])?;
+    let pattern = string_arg("contains", args[1])?;
+    Ok(NanBoxedValue::boolean(text.contains(pattern)))
+}
+
+fn starts_with(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("starts_with", args[0])?;
+    let prefix = string_arg("starts_with", args[1])?;
+    Ok(NanBoxedValue::boolean(text.starts_with(prefix)))
+}
+
+fn ends_with(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let text = string_arg("ends_with", args[0])?;
+    let suffix = string_arg("ends_with", args[1])?;
+    Ok(NanBoxedValue::boolean(text.ends_with(suffix)))
+}
+
+/// Reads an environment variable, or null if it is unset (or not valid unicode)
+fn env(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let name = string_arg("env", args[0])?;
+    match std::env::var(name) {
+        Ok(value) => Ok(ctx.string(value)),
+        Err(_) => Ok(NanBoxedValue::null()),
+    }
+}
+
+/// Whether a global variable with the given name exists, so scripts can check before using it
+fn defined(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let name = string_arg("defined", args[0])?;
+    let exists = ctx.globals.is_some_and(|globals| globals.contains_key(name));
+    Ok(NanBoxedValue::boolean(exists))
+}
+
+/// The command-line arguments passed to the script, as an array of strings
+fn args(ctx: &mut NativeContext, _args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let script_args = ctx.script_args;
+    let items = script_args.iter().map(|arg| ctx.string(arg.clone())).collect();
+    Ok(ctx.array(items))
+}
+
+thread_local! {
+    // xorshift64* state - never zero, or the generator gets stuck there
+    static RNG_STATE: Cell<u64> = Cell::new(rng_seed());
+}
+
+fn rng_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    // Scramble the clock so seeds taken close together still diverge quickly
+    (nanos ^ 0x9E37_79B9_7F4A_7C15).max(1)
+}
+
+/// Next value from the thread's xorshift64* generator
+fn next_random() -> u64 {
+    RNG_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    })
+}
+
+/// A float in [0.0, 1.0), built from the top 53 bits so every value is exactly representable
+fn random_unit() -> f64 {
+    (next_random() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn rand(_ctx: &mut NativeContext, _args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Ok(NanBoxedValue::number(random_unit()))
+}
+
+/// An integer in [lo, hi)
+fn rand_int(_ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let lo = number_arg("rand_int", args[0])?.floor() as i64;
+    let hi = number_arg("rand_int", args[1])?.floor() as i64;
+    if hi <= lo {
+        return Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("rand_int expects lo < hi, got {} and {}", lo, hi),
+            backtrace: vec![],
+        });
+    }
+    let span = hi.wrapping_sub(lo) as u64;
+    Ok(NanBoxedValue::integer(lo.wrapping_add((next_random() % span) as i64)))
+}
+
+/// Stands in for the natives that `NativeFnType::calls_back`. The VM runs those itself, since
+/// they call Weave functions, so calling this one directly is an error rather than a crash.
+fn runs_in_vm(_ctx: &mut NativeContext, _args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    Err(VMError::RuntimeError {
+        line: 0,
+        msg: "map, filter and reduce can only be called by the VM".to_string(),
+        backtrace: vec![],
+    })
+}
+
+/// The most integers one `range` call may hold, so a huge count is an error rather than an
+/// allocation that takes the whole process down
+const MAX_RANGE_LEN: i64 = 10_000_000;
+
+/// `range(n)` counts from 0 up to n, and `range(a, b)` from a up to b, in arrays of integers
+fn range(ctx: &mut NativeContext, args: &[NanBoxedValue]) -> Result<NanBoxedValue, VMError> {
+    let (start, end) = match args {
+        [end] => (0, whole_number_arg("range", *end)?),
+        [start, end] => (whole_number_arg("range", *start)?, whole_number_arg("range", *end)?),
+        _ => unreachable!("range takes one or two arguments"),
+    };
+    if end < start {
+        return Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("range can't count down from {} to {}", start, end),
+            backtrace: vec![],
+        });
+    }
+    if (end as i128 - start as i128) > MAX_RANGE_LEN as i128 {
+        return Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("range from {} to {} would hold more than {} items", start, end, MAX_RANGE_LEN),
+            backtrace: vec![],
+        });
+    }
+    let items = (start..end).map(NanBoxedValue::integer).collect();
+    Ok(ctx.array(items))
+}
+
+/// Like `number_arg`, but also rejects numbers with a fractional part
+fn whole_number_arg(fn_name: &str, value: NanBoxedValue) -> Result<i64, VMError> {
+    let number = number_arg(fn_name, value)?;
+    if number.fract() != 0.0 || !number.is_finite() {
+        return Err(VMError::RuntimeError {
+            line: 0,
+            msg: format!("{} expects a whole number, got {}", fn_name, value),
+            backtrace: vec![],
+        });
+    }
+    Ok(number as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_line_returns_string() {
+        let mut reader = Cursor::new("hello weave\nsecond line\n");
+        let value = read_line_from(&mut reader).unwrap();
+        assert!(value.is_string());
+        assert_eq!(value.as_string(), "hello weave");
+
+        let value = read_line_from(&mut reader).unwrap();
+        assert_eq!(value.as_string(), "second line");
+    }
+
+    #[test]
+    fn test_read_line_strips_crlf() {
+        let mut reader = Cursor::new("windows\r\n");
+        let value = read_line_from(&mut reader).unwrap();
+        assert_eq!(value.as_string(), "windows");
+    }
+
+    #[test]
+    fn test_read_file_returns_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("weave.txt");
+        let path_value = NanBoxedValue::string(path.to_string_lossy().to_string());
+        let contents = NanBoxedValue::string("woven\ntogether".to_string());
+
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+        write_file(&mut ctx, &[path_value, contents]).unwrap();
+        let value = read_file(&mut ctx, &[path_value]).unwrap();
+        assert!(value.is_string());
+        assert_eq!(value.as_string(), "woven\ntogether");
+        assert_eq!(ctx.allocated(), 1);
+    }
+
+    #[test]
+    fn test_read_missing_file_is_runtime_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.txt");
+        let path_value = NanBoxedValue::string(path.to_string_lossy().to_string());
+
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let res = read_file(&mut NativeContext::new(&mut arrays, &mut maps, &mut strings), &[path_value]);
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_len() {
+        let mut arrays = ArrayArena::new();
+        let handle = arrays.insert(crate::weave::vm::types::WeaveArray::new(vec![NanBoxedValue::null(); 3]));
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+
+        let value = len(&mut ctx, &[NanBoxedValue::string("hello".to_string())]).unwrap();
+        assert_eq!(value, NanBoxedValue::number(5.0));
+
+        let value = len(&mut ctx, &[NanBoxedValue::array_handle(handle)]).unwrap();
+        assert_eq!(value, NanBoxedValue::number(3.0));
+
+        let res = len(&mut ctx, &[NanBoxedValue::number(5.0)]);
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_type_names() {
+        assert_eq!(type_name(NanBoxedValue::integer(1)), "number");
+        assert_eq!(type_name(NanBoxedValue::number(1.5)), "number");
+        assert_eq!(type_name(NanBoxedValue::boolean(false)), "boolean");
+        assert_eq!(type_name(NanBoxedValue::null()), "null");
+        assert_eq!(type_name(NanBoxedValue::string("a".to_string())), "string");
+
+        let mut arrays = ArrayArena::new();
+        let handle = arrays.insert(crate::weave::vm::types::WeaveArray::default());
+        assert_eq!(type_name(NanBoxedValue::array_handle(handle)), "array");
+    }
+
+    #[test]
+    fn test_str() {
+        let mut arrays = ArrayArena::new();
+        let handle = arrays.insert(crate::weave::vm::types::WeaveArray::new(vec![NanBoxedValue::integer(1), NanBoxedValue::boolean(true)]));
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+
+        assert_eq!(str(&mut ctx, &[NanBoxedValue::null()]).unwrap().as_string(), "null");
+        assert_eq!(str(&mut ctx, &[NanBoxedValue::number(-0.5)]).unwrap().as_string(), "-0.5");
+        assert_eq!(str(&mut ctx, &[NanBoxedValue::array_handle(handle)]).unwrap().as_string(), "[1, true]");
+        assert_eq!(ctx.allocated(), 3);
+    }
+
+    #[test]
+    fn test_rand_in_range() {
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+
+        let samples: Vec<f64> = (0..1000)
+            .map(|_| rand(&mut ctx, &[]).unwrap().as_number())
+            .collect();
+        assert!(samples.iter().all(|n| (0.0..1.0).contains(n)));
+        assert!(samples.iter().any(|n| *n != samples[0]), "rand() should not repeat one value");
+        // Not a rigorous check, but a broken generator would land far from the midpoint
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((0.4..0.6).contains(&mean), "mean of rand() was {}", mean);
+    }
+
+    #[test]
+    fn test_rand_int_in_range() {
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+
+        let args = [NanBoxedValue::integer(-3), NanBoxedValue::integer(4)];
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            let value = rand_int(&mut ctx, &args).unwrap();
+            assert!(value.is_int());
+            let n = value.as_int();
+            assert!((-3..4).contains(&n));
+            seen[(n + 3) as usize] = true;
+        }
+        assert!(seen.iter().all(|s| *s), "rand_int(-3, 4) missed a value: {:?}", seen);
+
+        let res = rand_int(&mut ctx, &[NanBoxedValue::integer(2), NanBoxedValue::integer(2)]);
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_clock_nanos_is_monotonic() {
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+
+        let mut last = clock_nanos(&mut ctx, &[]).unwrap().as_number();
+        for _ in 0..100 {
+            let now = clock_nanos(&mut ctx, &[]).unwrap().as_number();
+            assert!(now >= last, "clock_nanos went backwards: {} then {}", last, now);
+            last = now;
+        }
+
+        sleep(&mut ctx, &[NanBoxedValue::integer(1)]).unwrap();
+        let after_sleep = clock_nanos(&mut ctx, &[]).unwrap().as_number();
+        assert!(after_sleep - last >= 1_000_000.0);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+
+        let start = clock(&mut ctx, &[]).unwrap().as_number();
+        let value = sleep(&mut ctx, &[NanBoxedValue::integer(20)]).unwrap();
+        let elapsed = clock(&mut ctx, &[]).unwrap().as_number() - start;
+        assert!(value.is_null());
+        assert!(elapsed >= 20.0, "slept for only {}ms", elapsed);
+
+        // Nonsense delays are treated as zero rather than erroring
+        assert!(sleep(&mut ctx, &[NanBoxedValue::number(-5.0)]).is_ok());
+        assert!(sleep(&mut ctx, &[NanBoxedValue::number(f64::NAN)]).is_ok());
+        assert!(matches!(sleep(&mut ctx, &[NanBoxedValue::null()]), Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_read_line_eof_is_null() {
+        let mut reader = Cursor::new("");
+        let value = read_line_from(&mut reader).unwrap();
+        assert!(value.is_null());
+    }
+
+    #[test]
+    fn test_callback_natives_fail_outside_the_vm() {
+        let mut arrays = ArrayArena::new();
+        let mut maps = MapArena::new();
+        let mut strings = StringPool::new();
+        let mut ctx = NativeContext::new(&mut arrays, &mut maps, &mut strings);
+        for fn_type in [NativeFnType::Map, NativeFnType::Filter, NativeFnType::Reduce] {
+            let native = NativeFn::get(fn_type);
+            assert!(native.name.calls_back());
+            let res = (native.func)(&mut ctx, &[NanBoxedValue::null(), NanBoxedValue::null()]);
+            assert!(matches!(res, Err(VMError::RuntimeError { .. })), "{}", native.name);
+        }
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let native = NativeFn::get(NativeFnType::Exit);
+        assert_eq!(native.arity, 1);
+        assert_eq!(native.name.to_string(), "exit");
+
+        assert_eq!(exit_status(NanBoxedValue::integer(3)).unwrap(), 3);
+        assert_eq!(exit_status(NanBoxedValue::number(0.0)).unwrap(), 0);
+        assert_eq!(exit_status(NanBoxedValue::integer(-1)).unwrap(), -1);
+        for bad in [NanBoxedValue::number(1.5), NanBoxedValue::number(f64::NAN), NanBoxedValue::integer(1 << 40), NanBoxedValue::null()] {
+            assert!(exit_status(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::weave::vm::types::weave_string::hash_str;
+use crate::weave::vm::types::NanBoxedValue;
+use std::collections::HashMap;
+
+/// Interns the VM's strings, so equal strings share one allocation and compare by identity
+#[derive(Debug, Default)]
+pub struct StringPool {
+    /// Every interned string, bucketed by hashcode in case two strings collide
+    strings: HashMap<u64, Vec<NanBoxedValue>>,
+    /// The interned strings the garbage collector may free. The rest are chunk constants,
+    /// which live as long as their chunk.
+    collectable: Vec<NanBoxedValue>,
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool::default()
+    }
+
+    /// The interned string equal to `text`, if there is one
+    pub fn get(&self, text: &str) -> Option<NanBoxedValue> {
+        self.strings.get(&hash_str(text))?
+            .iter()
+            .find(|string| string.as_string() == text)
+            .copied()
+    }
+
+    /// Returns the interned string equal to `text`, allocating one if there isn't one yet.
+    /// The flag is true when a new string was allocated.
+    pub fn intern(&mut self, text: String) -> (NanBoxedValue, bool) {
+        match self.get(&text) {
+            Some(string) => (string, false),
+            None => self.adopt(NanBoxedValue::string(text)),
+        }
+    }
+
+    /// Interns a freshly allocated string, freeing it in favour of an equal one already interned.
+    /// The flag is true when `value` itself was kept.
+    pub fn adopt(&mut self, value: NanBoxedValue) -> (NanBoxedValue, bool) {
+        if let Some(string) = self.get(value.as_string()) {
+            // SAFETY: the caller handed over a string nothing else refers to yet
+            unsafe { value.deallocate(); }
+            return (string, false);
+        }
+        self.insert(value);
+        self.collectable.push(value);
+        (value, true)
+    }
+
+    /// Interns a string constant from a chunk. Constants aren't garbage collection roots, so
+    /// the string is kept for good - including an equal runtime string it is replaced with.
+    /// The constant itself is never freed: clones of its chunk may still point at it.
+    pub fn adopt_constant(&mut self, value: NanBoxedValue) -> NanBoxedValue {
+        match self.get(value.as_string()) {
+            Some(string) => {
+                self.collectable.retain(|s| s.bits() != string.bits());
+                string
+            }
+            None => {
+                self.insert(value);
+                value
+            }
+        }
+    }
+
+    /// How many strings the garbage collector is responsible for
+    pub fn len(&self) -> usize {
+        self.collectable.len()
+    }
+
+    /// Frees every collectable string `live` rejects, returning how many were freed
+    pub fn sweep(&mut self, live: impl Fn(NanBoxedValue) -> bool) -> usize {
+        let before = self.collectable.len();
+        let strings = &mut self.strings;
+        self.collectable.retain(|string| {
+            if live(*string) {
+                return true;
+            }
+            let hash = string.as_weave_string().hashcode();
+            if let Some(bucket) = strings.get_mut(&hash) {
+                bucket.retain(|s| s.bits() != string.bits());
+                if bucket.is_empty() {
+                    strings.remove(&hash);
+                }
+            }
+            // SAFETY: the string was unreachable, and it is no longer interned
+            unsafe { string.deallocate(); }
+            false
+        });
+        before - self.collectable.len()
+    }
+
+    fn insert(&mut self, value: NanBoxedValue) {
+        let hash = value.as_weave_string().hashcode();
+        self.strings.entry(hash).or_default().push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal_strings_share_an_allocation() {
+        let mut pool = StringPool::new();
+        let (first, allocated) = pool.intern("abc".to_string());
+        assert!(allocated);
+        let (second, allocated) = pool.intern("abc".to_string());
+        assert!(!allocated);
+        assert_eq!(first.bits(), second.bits());
+
+        let (adopted, kept) = pool.adopt(NanBoxedValue::string("abc".to_string()));
+        assert!(!kept);
+        assert_eq!(adopted.bits(), first.bits());
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_sweep_forgets_dead_strings() {
+        let mut pool = StringPool::new();
+        let (keep, _) = pool.intern("keep".to_string());
+        pool.intern("drop".to_string());
+
+        assert_eq!(pool.sweep(|s| s.bits() == keep.bits()), 1);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get("drop").is_none());
+        assert_eq!(pool.get("keep").map(|s| s.bits()), Some(keep.bits()));
+    }
+
+    #[test]
+    fn test_constants_are_never_collected() {
+        let mut pool = StringPool::new();
+        let (runtime, _) = pool.intern("name".to_string());
+        // An equal constant takes over the runtime string, which becomes permanent
+        let constant = pool.adopt_constant(NanBoxedValue::string("name".to_string()));
+        assert_eq!(constant.bits(), runtime.bits());
+        let other = pool.adopt_constant(NanBoxedValue::string("other".to_string()));
+
+        assert_eq!(pool.sweep(|_| false), 0);
+        assert_eq!(pool.len(), 0);
+        assert_eq!(pool.get("other").map(|s| s.bits()), Some(other.bits()));
+
+        // A duplicate constant is swapped for the interned one but left intact for its owner
+        let duplicate = NanBoxedValue::string("other".to_string());
+        assert_eq!(pool.adopt_constant(duplicate).bits(), other.bits());
+        assert_eq!(duplicate.as_string(), "other");
+    }
+}
@@ -0,0 +1,60 @@
+use crate::weave::vm::types::NanBoxedValue;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A Weave value copied out of the VM. Unlike a `NanBoxedValue`, it owns its contents, so it
+/// stays valid however long it is kept and whatever the VM runs next.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    /// An array or map that contains itself is cut off where it repeats, leaving Null
+    Array(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    /// Functions can't leave the VM, so only their description comes along, e.g. `<fn sq(1)>`
+    Function(String),
+}
+
+impl Value {
+    /// The value as an f64, if it is an integer or a float
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Integer(n) => Some(*n as f64),
+            Value::Float(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Shows the value the way `puts` would
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(n) => write!(f, "{}", NanBoxedValue::integer(*n)),
+            Value::Float(n) => write!(f, "{}", NanBoxedValue::number(*n)),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Null => write!(f, "null"),
+            Value::String(text) | Value::Function(text) => write!(f, "{}", text),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries.iter()
+                    .map(|(key, item)| format!("{}: {}", key, item))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::weave::vm::types::{ArrayArena, MapArena, NanBoxedValue};
+
+#[derive(Clone, Debug, Default)]
+pub struct WeaveArray {
+    items: Vec<NanBoxedValue>,
+}
+
+impl WeaveArray {
+    pub fn new(items: Vec<NanBoxedValue>) -> Self {
+        WeaveArray { items }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<NanBoxedValue> {
+        self.items.get(idx).copied()
+    }
+
+    /// Overwrites the element at `idx`, returning false if it is out of range
+    pub fn set(&mut self, idx: usize, value: NanBoxedValue) -> bool {
+        match self.items.get_mut(idx) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn items(&self) -> &[NanBoxedValue] {
+        &self.items
+    }
+}
+
+impl From<Vec<NanBoxedValue>> for WeaveArray {
+    fn from(items: Vec<NanBoxedValue>) -> Self {
+        WeaveArray::new(items)
+    }
+}
+
+/// Renders a value for display, following array and map handles into their arenas
+pub fn format_value(arrays: &ArrayArena, maps: &MapArena, value: NanBoxedValue) -> String {
+    let mut seen = vec![];
+    format_value_inner(arrays, maps, value, &mut seen)
+}
+
+fn format_value_inner(arrays: &ArrayArena, maps: &MapArena, value: NanBoxedValue, seen: &mut Vec<u64>) -> String {
+    if value.is_array() {
+        // Arrays can contain themselves - don't recurse forever
+        if seen.contains(&value.bits()) {
+            return "[...]".to_string();
+        }
+        if let Some(array) = arrays.get(value.as_array_handle()) {
+            seen.push(value.bits());
+            let items: Vec<String> = array.items().iter()
+                .map(|item| format_value_inner(arrays, maps, *item, seen))
+                .collect();
+            seen.pop();
+            return format!("[{}]", items.join(", "));
+        }
+    } else if value.is_map() {
+        if seen.contains(&value.bits()) {
+            return "{...}".to_string();
+        }
+        if let Some(map) = maps.get(value.as_map_handle()) {
+            seen.push(value.bits());
+            let entries: Vec<String> = map.sorted_entries().into_iter()
+                .map(|(key, item)| format!("{}: {}", key, format_value_inner(arrays, maps, item, seen)))
+                .collect();
+            seen.pop();
+            return format!("{{{}}}", entries.join(", "));
+        }
+    }
+    format!("{}", value)
+}
@@ -1,8 +1,10 @@
 use std::fmt::{Debug, Display};
+use std::io::Write;
 use std::rc::Rc;
-use crate::weave::Chunk;
+use crate::weave::{Chunk, Op};
+use crate::weave::vm::traits::disassemble::Disassemble;
 use crate::weave::vm::types::{UpvalueHandle};
-use crate::weave::vm::types::NanBoxedValue;
+use crate::weave::vm::types::{NanBoxedValue, PointerTag};
 
 #[derive(Clone)]
 pub struct WeaveFn {
@@ -11,12 +13,23 @@ pub struct WeaveFn {
     pub arity: usize,
     pub upvalue_count: u8,
     params: Vec<FnParam>,
+    required: usize,
+    body_start: usize,
+    /// The program text this function was compiled from, shared by every function in it
+    pub source: Option<Rc<str>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct FnParam {
     name: String,
-    default: Option<NanBoxedValue>,
+    /// Chunk offset of the prologue code that computes this param's default value
+    default: Option<usize>,
+}
+
+impl FnParam {
+    pub fn new(name: String, default: Option<usize>) -> FnParam {
+        FnParam { name, default }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -94,7 +107,90 @@ impl WeaveFn {
         let chunk = Chunk::new();
         let arity = params.len();
         let upvalue_count = 0;
-        WeaveFn { name, chunk, params, upvalue_count, arity }
+        let required = params.iter().take_while(|p| p.default.is_none()).count();
+        WeaveFn { name, chunk, params, upvalue_count, arity, required, body_start: 0, source: None }
+    }
+
+    pub fn add_param(&mut self, param: FnParam) {
+        if param.default.is_none() {
+            self.required = self.params.len() + 1;
+        }
+        self.params.push(param);
+        self.arity = self.params.len();
+    }
+
+    /// Marks the end of the default-value prologue - calls supplying every argument start here
+    pub fn start_body(&mut self) {
+        self.body_start = self.chunk.code.len();
+    }
+
+    /// How many arguments a caller must supply; the rest fall back to their defaults
+    pub fn required_arity(&self) -> usize {
+        self.required
+    }
+
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        self.required <= arg_count && arg_count <= self.arity
+    }
+
+    /// Offset at which a call with `arg_count` arguments begins executing.
+    /// Each default leaves its value on the stack in its param's slot, so a call skips straight
+    /// to the first default it needs and runs the rest of the prologue from there.
+    pub fn entry_point(&self, arg_count: usize) -> usize {
+        self.params.get(arg_count)
+            .and_then(|p| p.default)
+            .unwrap_or(self.body_start)
+    }
+
+    /// Where each call this function accepts starts running, with how many values are on its
+    /// stack at that point: the callee itself and its arguments
+    pub fn entry_points(&self) -> Vec<(usize, usize)> {
+        (self.required..=self.arity).map(|args| (self.entry_point(args), 1 + args)).collect()
+    }
+
+    /// Writes a bytecode listing with each run of instructions headed by the source line it came from
+    pub fn disassemble_to(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "== {:?} ==", self)?;
+        let chunk = &self.chunk;
+        let mut offset = 0;
+        let mut last_line = None;
+        while offset < chunk.code.len() {
+            let line = chunk.line_number_at(offset);
+            if last_line != Some(line) {
+                let text = self.source.as_ref()
+                    .and_then(|src| src.lines().nth(line.wrapping_sub(1)))
+                    .unwrap_or("");
+                writeln!(out, "{:4} | {}", line, text.trim_end())?;
+                last_line = Some(line);
+            }
+
+            let mut text = String::new();
+            offset = Op::at(chunk.code[offset]).disassemble(offset, chunk, &mut text);
+            write!(out, "{}", text)?;
+        }
+        Ok(())
+    }
+
+    /// Disassembles this function followed by every function defined inside it
+    pub fn disassemble_all(&self) -> String {
+        let mut listing = self.chunk.disassemble_to_string(&format!("{:?}", self));
+        for constant in &self.chunk.constants {
+            if constant.is_pointer() && constant.as_pointer().1 == PointerTag::Closure {
+                let closure = unsafe { &*(constant.as_pointer().0 as *const FnClosure) };
+                listing.push('\n');
+                listing.push_str(&closure.func.disassemble_all());
+            }
+        }
+        listing
+    }
+
+    /// Describes the accepted argument count for arity errors, e.g. "2" or "1 to 3"
+    pub fn arity_str(&self) -> String {
+        if self.required == self.arity {
+            format!("{}", self.arity)
+        } else {
+            format!("{} to {}", self.required, self.arity)
+        }
     }
 }
 
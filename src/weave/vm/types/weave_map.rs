@@ -0,0 +1,44 @@
+use crate::weave::vm::types::{NanBoxedValue, WeaveString};
+use std::collections::HashMap;
+
+/// A dictionary from string keys to values
+#[derive(Clone, Debug, Default)]
+pub struct WeaveMap {
+    entries: HashMap<WeaveString, NanBoxedValue>,
+}
+
+impl WeaveMap {
+    pub fn new() -> Self {
+        WeaveMap::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &str) -> Option<NanBoxedValue> {
+        self.entries.get(key).copied()
+    }
+
+    /// Stores `value` under `key`, replacing any value already there
+    pub fn set(&mut self, key: &str, value: NanBoxedValue) {
+        self.entries.insert(WeaveString::from(key), value);
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &NanBoxedValue> {
+        self.entries.values()
+    }
+
+    /// The entries in key order, so a map always displays the same way
+    pub fn sorted_entries(&self) -> Vec<(&str, NanBoxedValue)> {
+        let mut entries: Vec<_> = self.entries.iter()
+            .map(|(key, value)| (key.as_str(), *value))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+}
@@ -1,6 +1,5 @@
 use std::fmt::Display;
 use std::ops::{Add, Div, Mul, Neg, Sub};
-use crate::log_debug;
 
 #[derive(Debug, Clone)]
 pub enum WeaveNumber{
@@ -49,7 +48,8 @@ impl From<i64> for WeaveNumber{
 impl Display for WeaveNumber {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            WeaveNumber::Float(n) => write!(f, "{:.2}", n),
+            // Shortest text that parses back to the same float, matching how the VM prints numbers
+            WeaveNumber::Float(n) => write!(f, "{}", n),
             WeaveNumber::Int(n) => write!(f, "{}", n),
             WeaveNumber::UInt(n) => write!(f, "{}", n),
         }
@@ -118,8 +118,6 @@ impl Sub for &WeaveNumber {
     fn sub(self, rhs: Self) -> Self::Output {
         let a = self.to_shared_type(&rhs);
         let b = rhs.to_shared_type(&self);
-        
-        log_debug!("WeaveNumber subtraction", left = format!("{:?}", a).as_str(), right = format!("{:?}", b).as_str());
 
         match (&a, &b) {
             (WeaveNumber::UInt(a), WeaveNumber::UInt(b)) => WeaveNumber::UInt(a - b),
@@ -161,3 +159,61 @@ impl Div for &WeaveNumber {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sub() {
+        // Subtraction used to trace both operands on every call - it should just compute
+        assert_eq!(&WeaveNumber::Int(7) - &WeaveNumber::Int(10), WeaveNumber::Int(-3));
+        assert_eq!(&WeaveNumber::UInt(7) - &WeaveNumber::Int(2), WeaveNumber::Int(5));
+        assert_eq!(&WeaveNumber::Int(3) - &WeaveNumber::Float(0.5), WeaveNumber::Float(2.5));
+    }
+
+    #[test]
+    fn test_mixed_arithmetic_promotes() {
+        assert_eq!(&WeaveNumber::UInt(2) + &WeaveNumber::Float(0.5), WeaveNumber::Float(2.5));
+        assert_eq!(&WeaveNumber::Int(-2) * &WeaveNumber::Int(4), WeaveNumber::Int(-8));
+        assert_eq!(&WeaveNumber::Float(1.0) / &WeaveNumber::Int(4), WeaveNumber::Float(0.25));
+        assert_eq!(-WeaveNumber::UInt(4), WeaveNumber::Int(-4));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_display_keeps_full_precision() {
+        let pi = WeaveNumber::Float(3.14159);
+        assert_eq!(pi.to_string(), "3.14159");
+        assert_eq!(pi.to_string().parse::<f64>().unwrap(), 3.14159);
+
+        let third = &WeaveNumber::Float(1.0) / &WeaveNumber::Float(3.0);
+        assert_eq!(third.to_string().parse::<f64>().unwrap(), 1.0 / 3.0);
+        assert_eq!(WeaveNumber::Float(0.5).to_string(), "0.5");
+
+        // Integers never grow a decimal point
+        assert_eq!(WeaveNumber::Int(-42).to_string(), "-42");
+        assert_eq!(WeaveNumber::UInt(7).to_string(), "7");
+    }
+
+    #[test]
+    fn test_nan_boxed_round_trip() {
+        use crate::weave::vm::types::NanBoxedValue;
+
+        for number in [WeaveNumber::Int(-42), WeaveNumber::Float(2.5), WeaveNumber::Float(-0.0)] {
+            let value = NanBoxedValue::from(number.clone());
+            assert_eq!(value.to_weave_number(), Some(number));
+        }
+        // Integers stay integers, and floats stay floats
+        assert!(matches!(NanBoxedValue::from(WeaveNumber::Int(3)).to_weave_number(), Some(WeaveNumber::Int(3))));
+        assert!(matches!(NanBoxedValue::number(3.0).to_weave_number(), Some(WeaveNumber::Float(_))));
+        assert!(matches!(NanBoxedValue::from(WeaveNumber::UInt(7)).to_weave_number(), Some(WeaveNumber::Int(7))));
+        // ...unless they're too wide to box
+        let wide = NanBoxedValue::from(WeaveNumber::UInt(u64::MAX));
+        assert_eq!(wide.to_weave_number(), Some(WeaveNumber::Float(u64::MAX as f64)));
+
+        for value in [NanBoxedValue::boolean(true), NanBoxedValue::null()] {
+            assert!(value.to_weave_number().is_none());
+        }
+    }
+}
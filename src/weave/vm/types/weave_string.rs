@@ -1,3 +1,4 @@
+use std::borrow::Borrow;
 use std::fmt::Display;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::ops::Add;
@@ -8,7 +9,7 @@ pub struct WeaveString {
     value: Box<String>
 }
 
-fn hash_str(key: &str) -> u64 {
+pub(crate) fn hash_str(key: &str) -> u64 {
     let mut s = DefaultHasher::new();
     key.hash(&mut s);
     s.finish()
@@ -33,15 +34,34 @@ impl WeaveString {
     pub fn as_str(&self) -> &str {
         &self.value
     }
+
+    pub fn hashcode(&self) -> u64 {
+        self.hashcode
+    }
 }
 
 impl PartialEq for WeaveString {
     fn eq(&self, other: &Self) -> bool {
-        // TODO: Intern strings to improve performance?
+        // The VM interns its strings (see StringPool), so there they compare by identity instead
         self.hashcode == other.hashcode && self.value == other.value
     }
 }
 
+impl Eq for WeaveString {}
+
+// Hashes like the underlying str, so maps keyed by WeaveString can be looked up by &str
+impl Hash for WeaveString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.as_str().hash(state);
+    }
+}
+
+impl Borrow<str> for WeaveString {
+    fn borrow(&self) -> &str {
+        &self.value
+    }
+}
+
 impl Display for WeaveString {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.value)
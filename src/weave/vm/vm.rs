@@ -1,28 +1,106 @@
-use crate::weave::compiler::Compiler;
+use crate::weave::compiler::{CompileError, Compiler};
+use crate::weave::vm::arena::ArenaStats;
 use crate::weave::vm::instruction_pointer::IP;
-use crate::weave::vm::types::{FnClosure, NanBoxedValue, NativeFn, NativeFnType, PointerTag, Upvalue, WeaveUpvalue};
-use crate::weave::{Op};
-use std::collections::HashMap;
+use crate::weave::vm::types::{format_value, type_name, ArrayHandle, ClosureArena, ClosureHandle, FnClosure, Globals, NanBoxedValue, NativeContext, NativeFn, NativeFnType, PointerTag, StringPool, Upvalue, UpvalueHandle, Value, WeaveArray, WeaveFn, WeaveMap, WeaveUpvalue};
+use crate::weave::{Chunk, Op};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::rc::Rc;
-use crate::weave::color::green;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::weave::color::{green, stdout_supports_color};
 use crate::{log_debug, log_error};
 
+/// Number of heap allocations the VM makes before the first garbage collection
+const GC_INITIAL_THRESHOLD: usize = 1024;
+
+/// Default cap on how many values the VM stack may hold
+pub const DEFAULT_MAX_STACK_SIZE: usize = 1 << 20;
+
 pub struct VM {
+    /// Tells this VM's cached global slots apart from other VMs' in shared chunks
+    id: u64,
     call_stack: CallStack,
     stack: Vec<NanBoxedValue>,
-    globals: HashMap<String, NanBoxedValue>,
+    globals: Globals,
+    const_globals: HashSet<String>,  // Globals declared `const` by any script run so far
     last_value: NanBoxedValue,
-    
+    debug_mode: bool,
+    output: Box<dyn Write>,
+    color_output: bool,
+
     // Arena allocators for memory management
     closure_arena: crate::weave::vm::types::ClosureArena,
     upvalue_arena: crate::weave::vm::types::UpvalueArena,
+    open_upvalues: Vec<UpvalueHandle>,  // Upvalues still pointing into the stack, ordered by slot
+    array_arena: crate::weave::vm::types::ArrayArena,
+    map_arena: crate::weave::vm::types::MapArena,
+
+    // Garbage collection bookkeeping
+    strings: StringPool,  // Every string the VM has seen, interned
+    allocations: usize,
+    next_gc: usize,
+
+    max_stack_size: usize,
+    script_args: Vec<String>,
+
+    // Opcode timings, only gathered while profiling
+    profile: bool,
+    opcode_times: HashMap<String, (u64, u64)>, // (total_ns, count)
+
+    // How many open upvalues close_upvalues has looked at
+    #[cfg(test)]
+    upvalues_visited: usize,
+    // How many times a global's slot was looked up by name, rather than read from a chunk's cache
+    #[cfg(test)]
+    global_lookups: usize,
 }
 
 #[derive(Debug, Clone)]
 pub enum VMError {
     InvalidChunk,
-    CompilationError(String),
-    RuntimeError { line: usize, msg: String },
+    /// `line` and `column` locate the token the compiler stopped at, when the error came from source
+    CompilationError { line: Option<usize>, column: Option<usize>, msg: String },
+    /// `backtrace` lists (function name, line) for each active call, innermost first
+    RuntimeError { line: usize, msg: String, backtrace: Vec<(String, usize)> },
+    /// The script called `exit` with this status. The VM is left ready to run more code;
+    /// ending the process is up to the embedder.
+    Exit(i32),
+}
+
+/// How much memory the VM is holding on to, from `VM::memory_report`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryReport {
+    pub stack_len: usize,
+    pub stack_capacity: usize,
+    pub frames: usize,
+    pub globals: usize,
+    pub strings: usize,
+    pub closures: ArenaStats,
+    pub upvalues: ArenaStats,
+    pub arrays: ArenaStats,
+    pub maps: ArenaStats,
+}
+
+impl std::fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "stack:    {} values (capacity {}), {} frames", self.stack_len, self.stack_capacity, self.frames)?;
+        writeln!(f, "globals:  {}", self.globals)?;
+        writeln!(f, "strings:  {}", self.strings)?;
+        writeln!(f, "closures: {}", self.closures)?;
+        writeln!(f, "upvalues: {}", self.upvalues)?;
+        writeln!(f, "arrays:   {}", self.arrays)?;
+        write!(f, "maps:     {}", self.maps)
+    }
+}
+
+#[cfg(feature = "vm-profiling")]
+fn print_memory_growth(samples: &[(usize, MemoryReport)]) {
+    if let (Some((first_at, first)), Some((last_at, last))) = (samples.first(), samples.last()) {
+        eprintln!("Memory Usage Analysis ({} samples):", samples.len());
+        eprintln!("  At iteration {}:\n{}", first_at, first);
+        eprintln!("  At iteration {}:\n{}", last_at, last);
+        eprintln!();
+    }
 }
 
 struct CallStack  {
@@ -32,28 +110,28 @@ struct CallStack  {
 }
 
 pub struct CallFrame {
-    pub closure: *const FnClosure,
+    pub closure: ClosureHandle,
     pub slot: usize,
     ip: IP,
 }
 
 impl CallFrame {
-    pub fn new(closure_ptr: *const FnClosure, slot: usize) -> CallFrame {
-        let closure = unsafe { &*closure_ptr };
+    pub fn new(handle: ClosureHandle, closure: &FnClosure, slot: usize) -> CallFrame {
         let ip = IP::new(&closure.func.chunk.code);
-        CallFrame { closure: closure_ptr, ip, slot}
+        CallFrame { closure: handle, ip, slot}
     }
     
     /// Reuse this frame for a new function call (avoids allocation)
-    pub fn reset(&mut self, closure_ptr: *const FnClosure, slot: usize) {
-        let closure = unsafe { &*closure_ptr };
-        self.closure = closure_ptr;
+    pub fn reset(&mut self, handle: ClosureHandle, closure: &FnClosure, slot: usize) {
+        self.closure = handle;
         self.slot = slot;
         self.ip = IP::new(&closure.func.chunk.code);
     }
 
+    /// The absolute stack slot of local `idx`. Malformed bytecode can't wrap this around;
+    /// it saturates instead, and `ensure_slot` rejects the result against the stack limit.
     pub fn i(&self, idx: usize) -> usize {
-        self.slot + idx
+        self.slot.saturating_add(idx)
     }
 }
 
@@ -65,14 +143,15 @@ impl CallStack {
         }
     }
     
-    pub fn push(&mut self, closure_ptr: *const FnClosure, slot: usize) {
+    pub fn push(&mut self, arena: &ClosureArena, handle: ClosureHandle, slot: usize) {
+        let closure = arena.get(handle.clone()).expect("Called a closure that was already freed");
         // Try to reuse a frame from the pool first
         if let Some(mut frame) = self.frame_pool.pop() {
-            frame.reset(closure_ptr, slot);
+            frame.reset(handle, closure, slot);
             self.frames.push(frame);
         } else {
             // Create new frame only if pool is empty
-            let frame = CallFrame::new(closure_ptr, slot);
+            let frame = CallFrame::new(handle, closure, slot);
             self.frames.push(frame);
         }
     }
@@ -88,14 +167,18 @@ impl CallStack {
         }
     }
     
-    pub fn disassemble(&self, name: &str) {
-        let closure = unsafe { &*self.frames.last().unwrap().closure };
-        closure.func.chunk.disassemble(name).unwrap();
+    /// The closure running in the innermost frame
+    pub fn closure<'a>(&self, arena: &'a ClosureArena) -> &'a FnClosure {
+        let handle = self.frames.last().unwrap().closure.clone();
+        arena.get(handle).expect("Call frame closure was freed while running")
+    }
+
+    pub fn disassemble(&self, arena: &ClosureArena, name: &str) {
+        self.closure(arena).func.chunk.disassemble(name).unwrap();
     }
     
-    pub fn constants(&self) -> &Vec<NanBoxedValue> {
-        let closure = unsafe { &*self.frames.last().unwrap().closure };
-        &closure.func.chunk.constants
+    pub fn constants<'a>(&self, arena: &'a ClosureArena) -> &'a Vec<NanBoxedValue> {
+        &self.closure(arena).func.chunk.constants
     }
 
     pub fn next_op(&mut self) -> Op {
@@ -110,29 +193,27 @@ impl CallStack {
         self.cur_frame().ip.next()
     }
     
+    /// Reads a local's slot operand, as an absolute stack slot
     pub fn next_slot(&mut self) -> usize {
         let relative_slot = self.next_byte() as usize;
-        let absolute_slot = self.cur_frame().i(relative_slot);
-        absolute_slot
+        self.cur_frame().i(relative_slot)
     }
     
-    pub fn jump(&mut self, offset: u16) {
-        self.cur_frame().ip.jump(offset);
+    pub fn jump(&mut self, offset: u16) -> Result<(), VMError> {
+        self.cur_frame().ip.jump(offset)
     }
     
-    pub fn jump_back(&mut self, offset: u16) {
-        self.cur_frame().ip.jump_back(offset);
+    pub fn jump_back(&mut self, offset: u16) -> Result<(), VMError> {
+        self.cur_frame().ip.jump_back(offset)
     }
 
-    pub fn line_number_at(&mut self, offset: isize) -> usize {
+    pub fn line_number_at(&mut self, arena: &ClosureArena, offset: isize) -> usize {
         let point = self.cur_frame().ip.idx(offset);
-        let closure = unsafe { &*self.cur_frame().closure };
-        closure.func.chunk.line_number_at(point)
+        self.closure(arena).func.chunk.line_number_at(point)
     }
 
-    pub fn get_constant(&mut self, idx: usize) -> NanBoxedValue {
-        let closure = unsafe { &*self.cur_frame().closure };
-        closure.func.chunk.get_constant(idx)
+    pub fn get_constant(&mut self, arena: &ClosureArena, idx: usize) -> NanBoxedValue {
+        self.closure(arena).func.chunk.get_constant(idx)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -152,28 +233,111 @@ impl CallStack {
     }
 }
 
+impl std::fmt::Display for VMError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VMError::InvalidChunk => write!(f, "Invalid chunk"),
+            VMError::CompilationError { line: Some(line), msg, .. } => write!(f, "Compilation error: [line {}] {}", line, msg),
+            VMError::CompilationError { msg, .. } => write!(f, "Compilation error: {}", msg),
+            VMError::RuntimeError { line, msg, backtrace } => {
+                write!(f, "[line {}] {}", line, msg)?;
+                for (name, line) in backtrace {
+                    let name = if name.is_empty() { "<script>" } else { name.as_str() };
+                    write!(f, "\n    at {} (line {})", name, line)?;
+                }
+                Ok(())
+            }
+            VMError::Exit(status) => write!(f, "Exited with status {}", status),
+        }
+    }
+}
+
+impl From<CompileError> for VMError {
+    fn from(e: CompileError) -> Self {
+        VMError::CompilationError { line: Some(e.line), column: Some(e.column), msg: e.msg }
+    }
+}
+
 impl VMError {
     pub fn exit_code(&self) -> i32 {
         match self {
             VMError::InvalidChunk => 60,
-            VMError::CompilationError(_) => 70,
+            VMError::CompilationError { .. } => 70,
             // Probably unnecessary to exit from RuntimeErrors, but here's the code if you want
             VMError::RuntimeError { .. } => 80,
+            VMError::Exit(status) => *status,
+        }
+    }
+
+    /// A short machine-readable name for the kind of error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VMError::InvalidChunk => "invalid_chunk",
+            VMError::CompilationError { .. } => "compile",
+            VMError::RuntimeError { .. } => "runtime",
+            VMError::Exit(_) => "exit",
         }
     }
+
+    /// The error as a JSON object with `kind`, `line`, `column`, `message` and `backtrace` fields,
+    /// for editors and CI to parse. `line` and `column` are null when the error doesn't have them.
+    pub fn to_json(&self) -> serde_json::Value {
+        let (line, column, message, backtrace) = match self {
+            VMError::InvalidChunk => (None, None, "Invalid chunk".to_string(), &[][..]),
+            VMError::CompilationError { line, column, msg } => (*line, *column, msg.clone(), &[][..]),
+            VMError::RuntimeError { line, msg, backtrace } => (Some(*line), None, msg.clone(), &backtrace[..]),
+            VMError::Exit(_) => (None, None, self.to_string(), &[][..]),
+        };
+        let backtrace: Vec<_> = backtrace.iter()
+            .map(|(name, line)| {
+                let name = if name.is_empty() { "<script>" } else { name.as_str() };
+                serde_json::json!({ "function": name, "line": line })
+            })
+            .collect();
+        serde_json::json!({
+            "kind": self.kind(),
+            "line": line,
+            "column": column,
+            "message": message,
+            "backtrace": backtrace,
+        })
+    }
 }
 
 pub type VMResult = Result<NanBoxedValue, VMError>;
 
+static NEXT_VM_ID: AtomicU64 = AtomicU64::new(0);
+
 impl VM {
-    pub fn new() -> VM {
+    /// Creates a VM. With `debug_mode` set, the compiled bytecode and every stack
+    /// transition are logged at debug level.
+    pub fn new(debug_mode: bool) -> VM {
         let mut vm = VM {
+            id: NEXT_VM_ID.fetch_add(1, Ordering::Relaxed),
             call_stack: CallStack::new(),
             stack: Vec::with_capacity(255),
-            globals: HashMap::new(),
+            globals: Globals::new(),
+            const_globals: HashSet::new(),
             last_value: NanBoxedValue::null(),
+            debug_mode,
+            output: Box::new(std::io::stdout()),
+            color_output: stdout_supports_color(),
             closure_arena: crate::weave::vm::types::ClosureArena::with_capacity(64),
             upvalue_arena: crate::weave::vm::types::UpvalueArena::with_capacity(128),
+            open_upvalues: Vec::new(),
+            array_arena: crate::weave::vm::types::ArrayArena::with_capacity(64),
+            map_arena: crate::weave::vm::types::MapArena::with_capacity(16),
+            strings: StringPool::new(),
+            allocations: 0,
+            next_gc: GC_INITIAL_THRESHOLD,
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
+            script_args: Vec::new(),
+            profile: cfg!(feature = "vm-profiling"),
+            opcode_times: HashMap::new(),
+            #[cfg(test)]
+            upvalues_visited: 0,
+            #[cfg(test)]
+            global_lookups: 0,
         };
 
         NativeFnType::variants().iter().for_each(|fn_type| {
@@ -185,40 +349,94 @@ impl VM {
 
 
     pub fn interpret(&mut self, source: &str) -> VMResult {
-        let mut compiler = Compiler::new(source, false);
+        let func = self.compile(source)?;
+        self.run_script(func)
+    }
+
+    /// Compiles `source` and gets it ready to run one instruction at a time with `step`
+    pub fn load(&mut self, source: &str) -> Result<(), VMError> {
+        let func = self.compile(source)?;
+        self.load_script(func);
+        Ok(())
+    }
+
+    /// Runs the next instruction of a program set up by `load`. Returns whether there is
+    /// anything left to run.
+    pub fn step(&mut self) -> Result<bool, VMError> {
+        if self.call_stack.is_at_end() {
+            return Ok(false);
+        }
+        match self.execute_one(0) {
+            Ok(Some(_)) => Ok(false),
+            Ok(None) => Ok(!self.call_stack.is_at_end()),
+            Err(e) => Err(self.with_backtrace(e)),
+        }
+    }
+
+    /// A copy of the value stack, bottom first
+    pub fn stack_snapshot(&self) -> Vec<NanBoxedValue> {
+        self.stack.clone()
+    }
+
+    /// The source line of the next instruction to run, or 0 when nothing is running
+    pub fn current_line(&self) -> usize {
+        let Some(frame) = self.call_stack.frames.last() else { return 0 };
+        self.closure_arena.get(frame.closure.clone())
+            .map_or(0, |closure| closure.func.chunk.line_number_at(frame.ip.idx(0)))
+    }
+
+    fn compile(&mut self, source: &str) -> Result<WeaveFn, VMError> {
+        let mut compiler = Compiler::new(source, self.debug_mode)
+            .with_const_globals(self.const_globals.clone());
         self.debug(&format!("Compiling...\n{}", source));
-        let func = match compiler.compile() {
-            Ok(c) => c,
-            Err(msg) => return Err(VMError::CompilationError(msg)),
-        };
-        
+        let func = compiler.compile()?;
+        self.const_globals = compiler.const_globals().clone();
+        Ok(func)
+    }
+
+    /// Runs an already compiled top-level chunk, e.g. one loaded from a `.weavec` file.
+    /// Chunks that fail `Chunk::verify` are rejected with `InvalidChunk`.
+    pub fn interpret_chunk(&mut self, chunk: Chunk) -> VMResult {
+        if let Err(msg) = chunk.verify() {
+            log_error!("Rejected invalid chunk", message = msg.as_str());
+            return Err(VMError::InvalidChunk);
+        }
+        let mut func = WeaveFn::new(String::new(), vec![]);
+        func.chunk = chunk;
+        self.run_script(func)
+    }
+
+    fn run_script(&mut self, func: WeaveFn) -> VMResult {
+        self.load_script(func);
+        self.debug("Interpreting...");
+        self.run().map_err(|e| self.with_backtrace(e))
+    }
+
+    /// Pushes the top-level frame for `func`, ready to run
+    fn load_script(&mut self, mut func: WeaveFn) {
+        self.intern_constants(&mut func.chunk);
         let top_frame = FnClosure::new(Rc::new(func));
+        self.allocations += 1;
 
         // Store closure in arena and create handle
         let closure_handle = self.closure_arena.insert(top_frame);
         let closure_nan_boxed = NanBoxedValue::closure_handle(closure_handle.clone());
         self.stack.push(closure_nan_boxed);
-        
-        // TODO: Update CallStack to use handles instead of raw pointers
-        // For now, we need to get a raw pointer for compatibility
-        let closure_ref = self.closure_arena.get(closure_handle).unwrap();
-        let closure_ptr = closure_ref as *const FnClosure;
-        self.call_stack.push(closure_ptr, 0);
+        self.call_stack.push(&self.closure_arena, closure_handle, 0);
+    }
 
-        self.debug("Interpreting...");
-        
-        match self.run() {
-            Ok(v) => Ok(v),
-            Err(e) => {
-                match &e {
-                    VMError::RuntimeError { line, msg } => {
-                        self.runtime_error(*line, &msg);
-                    }
-                    _ => {}
-                }
-                Err(e)
+    /// Fills in the backtrace of a runtime error, resetting the VM. An `exit` resets it too,
+    /// and flushes what the script printed so it isn't lost if the process ends next.
+    fn with_backtrace(&mut self, mut e: VMError) -> VMError {
+        match &mut e {
+            VMError::RuntimeError { msg, backtrace, .. } => *backtrace = self.runtime_error(msg),
+            VMError::Exit(_) => {
+                self.reset_stack();
+                let _ = self.output.flush();
             }
+            _ => {}
         }
+        e
     }
     
     pub fn get_stack_value(&self, slot: usize) -> NanBoxedValue {
@@ -244,25 +462,32 @@ impl VM {
         let current_frame_slot = current_frame.slot;
         let absolute_slot = current_frame_slot + uv.idx as usize;
         
-        // Check if we already have an open upvalue for this stack slot
-        // We'll scan the stack for upvalue NanBoxedValues that might reference this slot
-        // let mut existing_handle = None;
-        
-        // For now, we'll create a new upvalue in the arena
-        // TODO: Implement upvalue sharing/deduplication if needed
-        
-        // Create new upvalue and store it in the arena
-        let new_upvalue = WeaveUpvalue::open(absolute_slot);
-        let upvalue_handle = self.upvalue_arena.insert(new_upvalue);
-        
-        
+        // Reuse an open upvalue for this stack slot if one exists, so that every closure
+        // capturing the same variable shares a single cell and sees the others' writes
+        let arena = &self.upvalue_arena;
+        let position = self.open_upvalues.partition_point(|handle| {
+            arena.get(handle.clone()).is_some_and(|upvalue| upvalue.get_stack_index() < absolute_slot)
+        });
+        let existing_handle = self.open_upvalues.get(position)
+            .filter(|handle| arena.get((*handle).clone()).is_some_and(|upvalue| upvalue.get_stack_index() == absolute_slot))
+            .cloned();
+
+        let upvalue_handle = match existing_handle {
+            Some(handle) => handle,
+            None => {
+                let handle = self.upvalue_arena.insert(WeaveUpvalue::open(absolute_slot));
+                self.open_upvalues.insert(position, handle.clone());
+                handle
+            }
+        };
+
         // Store the arena handle in the closure
         closure.upvalues.push(upvalue_handle.clone());
     }
     
     pub fn add_remote_upvalue(&mut self, closure: &mut FnClosure, uv: Upvalue) {
         // Remote upvalues reference an upvalue from the current frame's closure
-        let current_closure = unsafe { &*self.current_frame().closure };
+        let current_closure = self.call_stack.closure(&self.closure_arena);
         let current_upvalues = &current_closure.upvalues;
         
         // Bounds check
@@ -278,38 +503,369 @@ impl VM {
     
 
     pub fn close_upvalues(&mut self, last_slot: usize) {
-        // Close all upvalues that reference stack slots >= last_slot
-        // We need to collect handles and their current values first to avoid borrow checker issues
-        let mut upvalues_to_close = Vec::new();
-        
+        // Close all upvalues that reference stack slots >= last_slot. They sit at the end of
+        // open_upvalues, so only the upvalues being closed are visited.
         log_debug!("CLOSE_UPVALUES DEBUG", last_slot = last_slot, stack_len = self.stack.len());
+
+        while let Some(handle) = self.open_upvalues.last().cloned() {
+            #[cfg(test)]
+            { self.upvalues_visited += 1; }
+            let Some(upvalue) = self.upvalue_arena.get(handle) else {
+                // Collected along with every closure that captured it
+                self.open_upvalues.pop();
+                continue;
+            };
+            let slot = upvalue.get_stack_index();
+            if slot < last_slot {
+                break;
+            }
+            self.open_upvalues.pop();
+            log_debug!("UPVALUE TO CLOSE", slot = slot, stack_len = self.stack.len());
+
+            if slot >= self.stack.len() {
+                log_debug!("UPVALUE SLOT OUT OF BOUNDS", slot = slot, stack_len = self.stack.len());
+                // Skip this upvalue - it's already invalid
+                continue;
+            }
+            upvalue.close_with_value(self.stack[slot]);
+        }
+    }
+
+    /// Renders a value for display, following array handles into the array arena
+    pub fn format_value(&self, value: NanBoxedValue) -> String {
+        format_value(&self.array_arena, &self.map_arena, value)
+    }
+
+    /// Copies a value out of the VM, so it outlives whatever the garbage collector does next
+    pub fn export_value(&self, value: NanBoxedValue) -> Value {
+        self.export_inner(value, &mut vec![])
+    }
+
+    fn export_inner(&self, value: NanBoxedValue, seen: &mut Vec<u64>) -> Value {
+        if value.is_int() {
+            return Value::Integer(value.as_int());
+        } else if value.is_number() {
+            return Value::Float(value.as_number());
+        } else if value.is_boolean() {
+            return Value::Boolean(value.as_boolean());
+        } else if value.is_string() {
+            return Value::String(value.as_string().to_string());
+        }
+        // Arrays and maps can contain themselves - don't recurse forever
+        if seen.contains(&value.bits()) {
+            return Value::Null;
+        }
+        if value.is_array() {
+            let Some(array) = self.array_arena.get(value.as_array_handle()) else { return Value::Null };
+            seen.push(value.bits());
+            let items = array.items().iter().map(|item| self.export_inner(*item, seen)).collect();
+            seen.pop();
+            Value::Array(items)
+        } else if value.is_map() {
+            let Some(map) = self.map_arena.get(value.as_map_handle()) else { return Value::Null };
+            seen.push(value.bits());
+            let entries = map.sorted_entries().into_iter()
+                .map(|(key, item)| (key.to_string(), self.export_inner(item, seen)))
+                .collect();
+            seen.pop();
+            Value::Map(entries)
+        } else if value.is_closure_handle() {
+            match self.closure_arena.get(value.as_closure_handle()) {
+                Some(closure) => Value::Function(closure.to_string()),
+                None => Value::Null,
+            }
+        } else if value.is_pointer() && value.as_pointer().1 == PointerTag::NativeFn {
+            // Natives are boxed when the VM defines them and never freed
+            let native_fn = unsafe { &*(value.as_pointer().0 as *const Rc<NativeFn>) };
+            Value::Function(native_fn.to_string())
+        } else {
+            Value::Null
+        }
+    }
+
+    /// Natives can't see where they were called from, so their errors get the call's line here
+    fn at_call_site(&mut self, mut error: VMError) -> VMError {
+        if let VMError::RuntimeError { line, .. } = &mut error && *line == 0 {
+            *line = self.call_stack.line_number_at(&self.closure_arena, -1);
+        }
+        error
+    }
+
+    /// Calls the function sitting below `arg_count` arguments on the stack. A Weave function
+    /// gets a new frame to run in; a native runs right away, replacing itself and its
+    /// arguments with the result.
+    fn call(&mut self, arg_count: usize) -> Result<(), VMError> {
+        let func_slot = self.stack.len().checked_sub(arg_count + 1).ok_or(VMError::InvalidChunk)?;
+        let func_nan_boxed = self.stack[func_slot];
         
-        for (handle, upvalue) in self.upvalue_arena.iter() {
-            if upvalue.is_open() && upvalue.get_stack_index() >= last_slot {
-                let slot = upvalue.get_stack_index();
-                log_debug!("UPVALUE TO CLOSE", slot = slot, stack_len = self.stack.len());
-                
-                if slot >= self.stack.len() {
-                    log_debug!("UPVALUE SLOT OUT OF BOUNDS", slot = slot, stack_len = self.stack.len());
-                    // Skip this upvalue - it's already invalid
-                    continue;
+        #[cfg(feature = "vm-debug")]
+        log_debug!("CALL DEBUG", is_closure_handle = func_nan_boxed.is_closure_handle(), is_pointer = func_nan_boxed.is_pointer(), func_value = format!("{:?}", func_nan_boxed).as_str());
+        
+        if func_nan_boxed.is_closure_handle() {
+            // New arena-based closure handle
+            let closure_handle = func_nan_boxed.as_closure_handle();
+            let closure = self.closure_arena.get(closure_handle.clone()).unwrap();
+            
+            // Inline validation
+            if !closure.func.accepts(arg_count) {
+                return Err(VMError::RuntimeError { 
+                    line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                    msg: format!("{} Expected {} arguments but got {}", closure.func.name, closure.func.arity_str(), arg_count),
+                    backtrace: vec![], 
+                });
+            }
+            if self.call_stack.frames.len() > 100 {
+                return Err(VMError::RuntimeError { 
+                    line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                    msg: "Stack overflow".to_string(),
+                    backtrace: vec![], 
+                });
+            }
+            
+            let entry = closure.func.entry_point(arg_count);
+            self.call_stack.push(&self.closure_arena, closure_handle, func_slot);
+            self.call_stack.cur_frame().ip.ip = entry;
+        } else if func_nan_boxed.is_pointer() {
+            let (ptr, tag) = func_nan_boxed.as_pointer();
+            match tag {
+                PointerTag::Closure => {
+                    // Legacy closure pointer (during transition)
+                    let closure = unsafe { &*(ptr as *const FnClosure) };
+                    
+                    // Inline validation to eliminate double cloning
+                    if !closure.func.accepts(arg_count) {
+                        return Err(VMError::RuntimeError { 
+                            line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                            msg: format!("{} Expected {} arguments but got {}", closure.func.name, closure.func.arity_str(), arg_count),
+                            backtrace: vec![], 
+                        });
+                    }
+                    if self.call_stack.frames.len() > 100 {
+                        return Err(VMError::RuntimeError { 
+                            line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                            msg: "Stack overflow".to_string(),
+                            backtrace: vec![], 
+                        });
+                    }
+                    
+                    // Frames only hold arena handles, so adopt a copy of the closure.
+                    // Swapping it into the function's slot keeps it rooted for the GC.
+                    let entry = closure.func.entry_point(arg_count);
+                    let closure_handle = self.closure_arena.insert(closure.clone());
+                    self.allocations += 1;
+                    self.stack[func_slot] = NanBoxedValue::closure_handle(closure_handle.clone());
+                    self.call_stack.push(&self.closure_arena, closure_handle, func_slot);
+                    self.call_stack.cur_frame().ip.ip = entry;
                 }
-                
-                let value = self.stack[slot]; // Copy the current stack value
-                upvalues_to_close.push((handle.clone(), value));
+                PointerTag::NativeFn => {
+                    // Cast pointer back to NativeFn
+                    let native_fn = unsafe { &*(ptr as *const Rc<NativeFn>) };
+                    if !native_fn.accepts(arg_count) {
+                        return Err(VMError::RuntimeError { 
+                            line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                            msg: format!("{} Expected {} arguments but got {}", native_fn.name, native_fn.arity_str(), arg_count),
+                            backtrace: vec![], 
+                        });
+                    }
+                    if native_fn.name.calls_back() {
+                        let args = self.stack[func_slot + 1..].to_vec();
+                        let result = self.call_back_native(&native_fn.name, &args)
+                            .map_err(|e| self.at_call_site(e))?;
+                        self.stack.truncate(func_slot);
+                        self.stack.push(result);
+                        return Ok(());
+                    }
+                    
+                    // Call native function directly with NanBoxedValue args
+                    let mut ctx = NativeContext::new(&mut self.array_arena, &mut self.map_arena, &mut self.strings)
+                        .with_script_args(&self.script_args)
+                        .with_globals(&self.globals);
+                    // Args sit directly above the function on the stack
+                    let first_arg = self.stack.len() - arg_count;
+                    let result = (native_fn.func)(&mut ctx, &self.stack[first_arg..]);
+                    self.allocations += ctx.allocated();
+                    let result = result.map_err(|e| self.at_call_site(e))?;
+                    
+                    // Pop function and args from stack, push result
+                    for _ in 0..=arg_count {
+                        self.stack.pop();
+                    }
+                    self.stack.push(result);
+                }
+                _ => return Err(self.not_callable(func_nan_boxed)),
             }
+        } else {
+            return Err(self.not_callable(func_nan_boxed));
         }
-        
-        // Now close each upvalue using the copied values
-        for (handle, value) in upvalues_to_close {
-            if let Some(upvalue) = self.upvalue_arena.get(handle) {
-                upvalue.close_with_value(value);
+        Ok(())
+    }
+
+    /// Calls `func` with `args` and waits for the result, running it above the current frames
+    fn call_function(&mut self, func: NanBoxedValue, args: &[NanBoxedValue]) -> VMResult {
+        self.stack.push(func);
+        self.stack.extend_from_slice(args);
+        let depth = self.call_stack.frames.len();
+        self.call(args.len())?;
+        if self.call_stack.frames.len() > depth {
+            self.execute(depth)?;
+        }
+        Ok(self.stack.pop().unwrap_or(NanBoxedValue::null()))
+    }
+
+    /// Runs `map`, `filter` or `reduce`, calling a Weave function for each item of an array.
+    /// Items are read from the array as they're reached, and values kept for the result wait
+    /// on the stack, so the garbage collector sees them all while Weave code runs.
+    fn call_back_native(&mut self, native: &NativeFnType, args: &[NanBoxedValue]) -> VMResult {
+        let name = native.to_string();
+        if !args[0].is_array() {
+            return Err(VMError::RuntimeError {
+                line: 0,
+                msg: format!("{} expects an array, got {}", name, type_name(args[0])),
+                backtrace: vec![],
+            });
+        }
+        if !args[1].is_function() {
+            return Err(VMError::RuntimeError {
+                line: 0,
+                msg: format!("{} expects a function, got {}", name, type_name(args[1])),
+                backtrace: vec![],
+            });
+        }
+        let (array, func) = (args[0].as_array_handle(), args[1]);
+        let item = |vm: &VM, idx: usize| vm.array_arena.get(array.clone()).and_then(|a| a.get(idx));
+
+        match native {
+            NativeFnType::Map | NativeFnType::Filter => {
+                let base = self.stack.len();
+                let mut idx = 0;
+                while let Some(value) = item(self, idx) {
+                    let result = self.call_function(func, &[value])?;
+                    match native {
+                        NativeFnType::Map => self.stack.push(result),
+                        _ if result.is_truthy() => self.stack.push(value),
+                        _ => {}
+                    }
+                    idx += 1;
+                }
+                let items = self.stack.split_off(base);
+                let handle = self.array_arena.insert(WeaveArray::new(items));
+                self.allocations += 1;
+                Ok(NanBoxedValue::array_handle(handle))
+            }
+            NativeFnType::Reduce => {
+                // Without an initial value, the first item starts things off
+                let (mut acc, mut idx) = match args.get(2) {
+                    Some(&initial) => (initial, 0),
+                    None => match item(self, 0) {
+                        Some(first) => (first, 1),
+                        None => return Err(VMError::RuntimeError {
+                            line: 0,
+                            msg: "reduce needs an initial value for an empty array".to_string(),
+                            backtrace: vec![],
+                        }),
+                    },
+                };
+                while let Some(value) = item(self, idx) {
+                    acc = self.call_function(func, &[acc, value])?;
+                    idx += 1;
+                }
+                Ok(acc)
+            }
+            _ => unreachable!("{} doesn't call back into Weave", name),
+        }
+    }
+
+    fn not_callable(&mut self, value: NanBoxedValue) -> VMError {
+        VMError::RuntimeError {
+            line: self.call_stack.line_number_at(&self.closure_arena, -1),
+            msg: format!("Cannot call {} '{}' — only functions are callable", type_name(value), self.format_value(value)),
+            backtrace: vec![],
+        }
+    }
+
+    fn array_index(&mut self, target: NanBoxedValue, index: NanBoxedValue) -> Result<(ArrayHandle, usize), VMError> {
+        if !target.is_array() {
+            return Err(self.index_error(format!("Can only index arrays, not {}", target)));
+        }
+        let handle = target.as_array_handle();
+        let len = self.array_arena.get(handle.clone()).map(|a| a.len()).unwrap_or(0);
+        let idx = self.checked_index(index, len, "array")?;
+        Ok((handle, idx))
+    }
+
+    /// Validates `key` as a map key. Only strings can be keys.
+    fn map_key(&mut self, key: NanBoxedValue) -> Result<&'static str, VMError> {
+        if key.is_string() {
+            Ok(key.as_string())
+        } else {
+            Err(self.index_error(format!("Map keys must be strings, got {}", key)))
+        }
+    }
+
+    /// Validates `index` as an integer position within a `kind` of length `len`
+    fn checked_index(&mut self, index: NanBoxedValue, len: usize, kind: &str) -> Result<usize, VMError> {
+        if !index.is_number() || index.as_number().fract() != 0.0 {
+            return Err(self.index_error(format!("Index into {} must be an integer, got {}", kind, index)));
+        }
+        let idx = index.as_number();
+        if idx < 0.0 || idx >= len as f64 {
+            return Err(self.index_error(format!("Index {} out of bounds for {} of length {}", idx, kind, len)));
+        }
+        Ok(idx as usize)
+    }
+
+    /// Resolves the bounds of a `[start:end]` slice of a `kind` of length `len`.
+    /// A null bound means the start or end of the whole thing.
+    fn slice_bounds(&mut self, start: NanBoxedValue, end: NanBoxedValue, len: usize, kind: &str) -> Result<(usize, usize), VMError> {
+        let bound = |vm: &mut VM, value: NanBoxedValue, default: usize| {
+            if value.is_null() {
+                return Ok(default);
             }
+            if !value.is_number() || value.as_number().fract() != 0.0 {
+                return Err(vm.index_error(format!("Slice bounds must be integers, got {}", value)));
+            }
+            let idx = value.as_number();
+            if idx < 0.0 || idx > len as f64 {
+                return Err(vm.index_error(format!("Slice bound {} out of range for {} of length {}", idx, kind, len)));
+            }
+            Ok(idx as usize)
+        };
+        let start = bound(self, start, 0)?;
+        let end = bound(self, end, len)?;
+        if start > end {
+            return Err(self.index_error(format!("Slice start {} is after its end {}", start, end)));
+        }
+        Ok((start, end))
+    }
+
+    fn index_error(&mut self, msg: String) -> VMError {
+        VMError::RuntimeError {
+            line: self.call_stack.line_number_at(&self.closure_arena, -1),
+            msg,
+            backtrace: vec![],
+        }
+    }
+
+    /// Comparison for operands the numeric fast path can't handle.
+    /// Strings compare lexicographically; other incomparable types are simply false.
+    fn compare_slow(&mut self, a: NanBoxedValue, b: NanBoxedValue, wanted: fn(std::cmp::Ordering) -> bool) -> Result<NanBoxedValue, VMError> {
+        if a.is_string() && b.is_string() {
+            let ordering = a.as_weave_string().partial_cmp(b.as_weave_string());
+            Ok(NanBoxedValue::boolean(ordering.is_some_and(wanted)))
+        } else if (a.is_string() && b.is_number()) || (a.is_number() && b.is_string()) {
+            Err(VMError::RuntimeError {
+                line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                msg: format!("Cannot compare {} and {}: {} and {}", type_name(a), type_name(b), self.format_value(a), self.format_value(b)),
+                backtrace: vec![],
+            })
+        } else {
+            // For other non-numeric comparisons, return false
+            Ok(NanBoxedValue::boolean(false))
         }
     }
 
     fn _read_constant(&mut self, idx: usize) -> NanBoxedValue {
-        self.call_stack.get_constant(idx)
+        self.call_stack.get_constant(&self.closure_arena, idx)
     }
 
 
@@ -318,691 +874,2815 @@ impl VM {
 
         self.debug("Executing...");
         log_debug!("Starting VM execution", function = "main");
+        self.execute(0)
+    }
 
+    /// Runs instructions until a return leaves `base_depth` frames on the call stack. The
+    /// script runs down to an empty stack; a function called from a native stops at its caller.
+    fn execute(&mut self, base_depth: usize) -> VMResult {
         #[cfg(feature = "vm-profiling")]
-        let mut opcode_times: std::collections::HashMap<String, (u64, u64)> = std::collections::HashMap::new(); // (total_ns, count)
-        
-        #[cfg(feature = "vm-profiling")]
-        let mut memory_samples: Vec<(usize, usize, usize, usize, usize, usize)> = Vec::new(); // (iteration, stack_len, upvalues_len, arena_len, globals_len, frames_len)
+        let mut memory_samples: Vec<(usize, MemoryReport)> = Vec::new(); // (iteration, report)
         
         #[cfg(feature = "vm-profiling")]
         let mut iteration_count = 0;
         while !self.call_stack.is_at_end() {
-            // until ip offset > chunk size
-            let op = self.call_stack.next_op();
-
             #[cfg(feature = "vm-profiling")]
             {
                 iteration_count += 1;
                 // Sample memory usage every 100 iterations to avoid overhead
                 if iteration_count % 100 == 0 {
-                    memory_samples.push((
-                        iteration_count,
-                        self.stack.len(),
-                        self.upvalue_arena.len(),
-                        self.closure_arena.len(),
-                        self.globals.len(),
-                        self.call_stack.frames.len(),
-                    ));
+                    memory_samples.push((iteration_count, self.memory_report()));
                 }
             }
 
-            #[cfg(feature = "vm-profiling")]
-            let start_time = std::time::Instant::now();
+            if let Some(result) = self.execute_one(base_depth)? {
+                #[cfg(feature = "vm-profiling")]
+                {
+                    if self.call_stack.is_empty() {
+                        self.print_profile();
+                        print_memory_growth(&memory_samples);
+                    }
+                }
+                return Ok(result);
+            }
+        }
+
+        #[cfg(feature = "vm-profiling")]
+        {
+            self.print_profile();
+            print_memory_growth(&memory_samples);
+        }
 
-            // self.debug(&format!("EVAL({:?})", op));
-            match op {
-                Op::INVALID(_) => {
-                    return Err(VMError::InvalidChunk);
+        // Return the top value on the stack as the result
+        Ok(self.stack.last().copied().unwrap_or(NanBoxedValue::null()))
+    }
+
+    /// Runs the next instruction. Gives back the result once a return leaves `base_depth` frames
+    /// on the call stack, or None while there is more to run.
+    // Inlined into the interpreter loop, which would otherwise pay for a call per instruction
+    #[inline(always)]
+    fn execute_one(&mut self, base_depth: usize) -> Result<Option<NanBoxedValue>, VMError> {
+        // Between instructions every live value is reachable from the roots
+        if self.allocations >= self.next_gc {
+            self.collect_garbage();
+        }
+
+        // until ip offset > chunk size
+        let op = self.call_stack.next_op();
+
+        let start_time = self.profile.then(std::time::Instant::now);
+
+        // self.debug(&format!("EVAL({:?})", op));
+        match op {
+            Op::INVALID(_) => {
+                return Err(VMError::InvalidChunk);
+            }
+            Op::RETURN => {
+                let result = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                // Close upvalues before cleaning up the stack
+                let current_frame_slot = self.current_frame().slot;
+                self.close_upvalues(current_frame_slot);
+                
+                // Now we can clean up the stack - remove everything from the frame slot onwards
+                let old_len = self.stack.len();
+                self.stack.truncate(current_frame_slot);
+                if old_len != current_frame_slot {
+                    log_debug!("STACK TRUNCATE", old_len = old_len, new_len = current_frame_slot, opcode = "RETURN", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
                 }
-                Op::RETURN => {
-                    let result = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    // Close upvalues before cleaning up the stack
-                    let current_frame_slot = self.current_frame().slot;
-                    self.close_upvalues(current_frame_slot);
-                    
-                    // Now we can clean up the stack - remove everything from the frame slot onwards
-                    let old_len = self.stack.len();
-                    self.stack.truncate(current_frame_slot);
-                    if old_len != current_frame_slot {
-                        log_debug!("STACK TRUNCATE", old_len = old_len, new_len = current_frame_slot, opcode = "RETURN", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
-                    }
-                    
-                    // TODO: Implement proper closure cleanup to prevent memory leaks
-                    
-                    self.call_stack.pop();
-                    if self.call_stack.is_empty() {
-                        #[cfg(feature = "vm-profiling")]
-                        {
-                            // Track the final opcode before early return
-                            let elapsed = start_time.elapsed().as_nanos() as u64;
-                            let opcode_name = format!("{:?}", op);
-                            let entry = opcode_times.entry(opcode_name).or_insert((0, 0));
-                            entry.0 += elapsed;
-                            entry.1 += 1;
-                            // Print profiling before early return
-                            eprintln!("VM execution completed (early return). Opcodes tracked: {}", opcode_times.len());
-                            if !opcode_times.is_empty() {
-                                eprintln!("Opcode Performance Profile:");
-                                let mut sorted_opcodes: Vec<_> = opcode_times.iter().collect();
-                                sorted_opcodes.sort_by(|a, b| b.1.0.cmp(&a.1.0)); // Sort by total time desc
-                                for (opcode, (total_ns, count)) in sorted_opcodes.iter().take(10) {
-                                    let avg_ns = *total_ns / *count;
-                                    eprintln!("  {:15} {:8} calls, {:10} ns total, {:6} ns avg", 
-                                             opcode, count, total_ns, avg_ns);
-                                }
-                                eprintln!();
-                            }
+                
+                // Closures left unreachable by this return are reclaimed by collect_garbage
+                
+                self.call_stack.pop();
+                if self.call_stack.is_empty() {
+                    // Track the final opcode before early return
+                    self.record_op_time(&op, start_time);
+                    // Keep the result alive for the caller until the next collection
+                    self.last_value = result;
+                    // Don't pop from empty stack
+                    return Ok(Some(result));
+                }
+                
+                // Place return value by pushing it onto the truncated stack
+                // The stack was truncated to function_slot, so pushing the result
+                // places it where the function call was (replacing the closure)
+                self.stack.push(result);
+                log_debug!("STACK PUSH", value = format!("{:?}", result).as_str(), stack_len = self.stack.len(), opcode = "RETURN", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
+                if self.call_stack.frames.len() == base_depth {
+                    // Back in the native that called this function
+                    self.record_op_time(&op, start_time);
+                    return Ok(Some(result));
+                }
+            },
+            Op::POP => { 
+                if let Some(value) = self.stack.pop() {
+                    self.last_value = value;
+                    log_debug!("STACK POP", value = format!("{:?}", value).as_str(), stack_len = self.stack.len(), opcode = "POP", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
+                }
+            },
+            Op::DUP => {
+                let Some(&value) = self.stack.last() else {
+                    return Err(VMError::RuntimeError {
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                        msg: "Cannot DUP an empty stack".to_string(),
+                        backtrace: vec![],
+                    });
+                };
+                self.stack.push(value);
+            },
+            Op::SWAP => {
+                let len = self.stack.len();
+                if len < 2 {
+                    return Err(VMError::RuntimeError {
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                        msg: "SWAP needs two values on the stack".to_string(),
+                        backtrace: vec![],
+                    });
+                }
+                self.stack.swap(len - 1, len - 2);
+            },
+            Op::OVER => {
+                // Copies the value under the top: [a, b] -> [a, b, a]
+                let len = self.stack.len();
+                if len < 2 {
+                    return Err(VMError::RuntimeError {
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                        msg: "OVER needs two values on the stack".to_string(),
+                        backtrace: vec![],
+                    });
+                }
+                self.stack.push(self.stack[len - 2]);
+            },
+            Op::CloseUpvalues => {
+                let slot = self.call_stack.next_byte() as usize;
+                self.close_upvalues(slot);
+            },
+            Op::CONSTANT => {
+                let idx = self.call_stack.next_u16() as usize;
+                #[cfg(debug_assertions)]
+                self.debug(&format!("Reading constant @ {:0x}", idx));
+                // Push constant directly - NanBoxedValue is Copy, no clone needed!
+                let constant = self.call_stack.get_constant(&self.closure_arena, idx);
+                self.stack.push(constant);
+                log_debug!("STACK PUSH", value = format!("{:?}", constant).as_str(), stack_len = self.stack.len(), opcode = "CONSTANT", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
+            }
+            Op::ConstantByte => {
+                let idx = self.call_stack.next_byte() as usize;
+                let constant = self.call_stack.get_constant(&self.closure_arena, idx);
+                self.stack.push(constant);
+                log_debug!("STACK PUSH", value = format!("{:?}", constant).as_str(), stack_len = self.stack.len(), opcode = "ConstantByte", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
+            }
+            Op::Closure => {
+                let idx = self.call_stack.next_u16() as usize;
+                self.debug(&format!("Reading closure @ {:0x}", idx));
+                let val = self._read_constant(idx);
+                
+                if val.is_pointer() {
+                    let (ptr, tag) = val.as_pointer();
+                    match tag {
+                        PointerTag::Closure => {
+                            // Cast pointer back to FnClosure and clone it for modification
+                            let closure_ref = unsafe { &*(ptr as *const FnClosure) };
+                            let mut closure = closure_ref.clone();
                             
-                            // Memory usage analysis
-                            if !memory_samples.is_empty() {
-                                eprintln!("Memory Usage Analysis:");
-                                eprintln!("  Total samples: {}", memory_samples.len());
+                            // Process upvalues that follow the closure constant
+                            for _ in 0..closure.func.upvalue_count {
+                                let bytecode = &self.call_stack.closure(&self.closure_arena).func.chunk.code;
+                                let offset = self.call_stack.cur_frame().ip.ip;
+                                let upvalue = Upvalue::from_bytes(bytecode, offset);
+                                // Skip the upvalue bytes we just read
+                                self.call_stack.cur_frame().ip.ip += 2;
                                 
-                                if memory_samples.len() > 1 {
-                                    let first = &memory_samples[0];
-                                    let last = &memory_samples[memory_samples.len() - 1];
-                                    
-                                    eprintln!("  Growth from iteration {} to {}:", first.0, last.0);
-                                    eprintln!("    Stack:      {} -> {} (+{})", first.1, last.1, last.1 as i32 - first.1 as i32);
-                                    eprintln!("    Upvalues:   {} -> {} (+{})", first.2, last.2, last.2 as i32 - first.2 as i32);
-                                    eprintln!("    Arena:      {} -> {} (+{})", first.3, last.3, last.3 as i32 - first.3 as i32);
-                                    eprintln!("    Globals:    {} -> {} (+{})", first.4, last.4, last.4 as i32 - first.4 as i32);
-                                    eprintln!("    Frames:     {} -> {} (+{})", first.5, last.5, last.5 as i32 - first.5 as i32);
-                                    
-                                    // Find the component with the highest growth
-                                    let stack_growth = last.1 as i32 - first.1 as i32;
-                                    let upvalues_growth = last.2 as i32 - first.2 as i32;
-                                    let arena_growth = last.3 as i32 - first.3 as i32;
-                                    let globals_growth = last.4 as i32 - first.4 as i32;
-                                    let frames_growth = last.5 as i32 - first.5 as i32;
-                                    
-                                    let growths = [stack_growth, upvalues_growth, arena_growth, globals_growth, frames_growth];
-                                    let max_growth = *growths.iter().max().unwrap();
-                                    
-                                    if max_growth > 0 {
-                                        eprintln!("  Largest growth component: {}", 
-                                            if stack_growth == max_growth { "Stack" }
-                                            else if upvalues_growth == max_growth { "Upvalues" }
-                                            else if arena_growth == max_growth { "Arena" }
-                                            else if globals_growth == max_growth { "Globals" }
-                                            else { "Frames" }
-                                        );
-                                    }
+                                if upvalue.is_local {
+                                    // Create upvalue from local variable in current frame
+                                    self.add_local_upvalue(&mut closure, upvalue);
+                                } else {
+                                    // Copy upvalue from parent frame
+                                    self.add_remote_upvalue(&mut closure, upvalue);
                                 }
-                                eprintln!();
                             }
+                            
+                            // Store the modified closure in arena
+                            let closure_handle = self.closure_arena.insert(closure);
+                            self.allocations += 1;
+                            #[cfg(feature = "vm-debug")]
+                            let debug_handle = closure_handle.clone();
+                            let closure_nan_boxed = NanBoxedValue::closure_handle(closure_handle);
+                            #[cfg(feature = "vm-debug")]
+                            log_debug!("CLOSURE CREATED WITH UPVALUES", handle = format!("{:?}", debug_handle).as_str(), is_closure_handle = closure_nan_boxed.is_closure_handle());
+                            self.stack.push(closure_nan_boxed);
+                        }
+                        _ => {
+                            return Err(VMError::CompilationError { line: None, column: None, msg: format!("Expected closure pointer, found {:?} pointer", tag) });
                         }
-                        // Don't pop from empty stack
-                        return Ok(result);
                     }
+                } else {
+                    return Err(VMError::CompilationError { line: None, column: None, msg: "Expected closure pointer, found non-pointer value".to_string() });
+                }
+            }
+            Op::Call => {
+                let arg_count = self.call_stack.next_byte() as usize;
+                self.call(arg_count)?;
+            }
+            Op::SetLocal => {
+                let slot = self.call_stack.next_slot();
+                let value = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                #[cfg(feature = "vm-debug")]
+                log_debug!("SET LOCAL", slot = slot, value = format!("{:?}", nan_boxed_value).as_str());
+                self.ensure_slot(slot)?;
+                self.stack[slot] = value;
+                // Value stays on stack since assignments are expressions in Weave
+                self.stack.push(value);
+            }
+            Op::GetLocal => {
+                let slot = self.call_stack.next_slot();
+                self.ensure_slot(slot)?;
+                // Use reference to avoid cloning during push
+                let value = self.stack[slot];
+                #[cfg(feature = "vm-debug")]
+                log_debug!("GET LOCAL", slot = slot, value = format!("{:?}", value).as_str());
+                self.stack.push(value);
+            }
+            Op::GetUpvalue => {
+                let slot = self.call_stack.next_byte() as usize;
+                // Get upvalue from arena using the handle
+                let closure = self.call_stack.closure(&self.closure_arena);
+                
+                // DEBUG: Check the bounds
+                if slot >= closure.upvalues.len() {
+                    panic!("GetUpvalue: slot {} out of bounds, upvalues.len() = {}, expected upvalue_count = {}", 
+                           slot, closure.upvalues.len(), closure.func.upvalue_count);
+                }
+                
+                let upvalue_handle = &closure.upvalues[slot];
+                let upvalue = self.upvalue_arena.get(upvalue_handle.clone()).unwrap();
+                let nan_boxed_value = upvalue.get_fast(self);
+                self.stack.push(nan_boxed_value);
+            }
+            Op::SetUpvalue => {
+                let slot = self.call_stack.next_byte() as usize;
+                // Set upvalue using the arena handle
+                let nan_boxed_value = self.stack[self.stack.len() - 1]; // peek top of stack
+                let closure = self.call_stack.closure(&self.closure_arena);
+                let upvalue_handle = closure.upvalues[slot].clone();
+                
+                // We need to work around the borrow checker here
+                // The issue is that set_fast needs &mut self, but we also have a mutable borrow from upvalue_arena
+                // Solution: Get the upvalue, check if it's open, and handle accordingly
+                let upvalue = self.upvalue_arena.get(upvalue_handle.clone()).unwrap();
+                if upvalue.is_open() {
+                    let stack_index = upvalue.get_stack_index();
+                    self.stack[stack_index] = nan_boxed_value;
+                } else {
+                    // For closed upvalues, we need to update the stored value
+                    // Use the same approach as close_upvalues to avoid borrow checker issues
+                    let upvalue_handle_clone = upvalue_handle.clone();
+                    drop(upvalue); // Release immutable borrow
                     
-                    // Place return value by pushing it onto the truncated stack
-                    // The stack was truncated to function_slot, so pushing the result
-                    // places it where the function call was (replacing the closure)
-                    self.stack.push(result);
-                    log_debug!("STACK PUSH", value = format!("{:?}", result).as_str(), stack_len = self.stack.len(), opcode = "RETURN", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
-                },
-                Op::POP => { 
-                    if let Some(value) = self.stack.pop() {
-                        self.last_value = value;
-                        log_debug!("STACK POP", value = format!("{:?}", value).as_str(), stack_len = self.stack.len(), opcode = "POP", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
+                    if let Some(upvalue) = self.upvalue_arena.get(upvalue_handle_clone) {
+                        upvalue.close_with_value(nan_boxed_value);
                     }
-                },
-                Op::CloseUpvalues => {
-                    let slot = self.call_stack.next_byte() as usize;
-                    self.close_upvalues(slot);
-                },
-                Op::CONSTANT => {
-                    let idx = self.call_stack.next_u16() as usize;
-                    #[cfg(debug_assertions)]
-                    self.debug(&format!("Reading constant @ {:0x}", idx));
-                    // Push constant directly - NanBoxedValue is Copy, no clone needed!
-                    let constant = self.call_stack.get_constant(idx);
-                    self.stack.push(constant);
-                    log_debug!("STACK PUSH", value = format!("{:?}", constant).as_str(), stack_len = self.stack.len(), opcode = "CONSTANT", ip = format!("{:x}", self.call_stack.cur_frame().ip.ip).as_str());
                 }
-                Op::Closure => {
-                    let idx = self.call_stack.next_u16() as usize;
-                    self.debug(&format!("Reading closure @ {:0x}", idx));
-                    let val = self._read_constant(idx);
-                    
-                    if val.is_pointer() {
-                        let (ptr, tag) = val.as_pointer();
-                        match tag {
-                            PointerTag::Closure => {
-                                // Cast pointer back to FnClosure and clone it for modification
-                                let closure_ref = unsafe { &*(ptr as *const FnClosure) };
-                                let mut closure = closure_ref.clone();
-                                
-                                // Process upvalues that follow the closure constant
-                                for _ in 0..closure.func.upvalue_count {
-                                    let frame = self.call_stack.cur_frame();
-                                    let frame_closure = unsafe { &*frame.closure };
-                                    let bytecode = &frame_closure.func.chunk.code;
-                                    let offset = frame.ip.ip;
-                                    let upvalue = Upvalue::from_bytes(bytecode, offset);
-                                    // Skip the upvalue bytes we just read
-                                    let _ = frame; // Explicitly drop to release borrow
-                                    self.call_stack.cur_frame().ip.ip += 2;
-                                    
-                                    if upvalue.is_local {
-                                        // Create upvalue from local variable in current frame
-                                        self.add_local_upvalue(&mut closure, upvalue);
-                                    } else {
-                                        // Copy upvalue from parent frame
-                                        self.add_remote_upvalue(&mut closure, upvalue);
-                                    }
-                                }
-                                
-                                // Store the modified closure in arena
-                                let closure_handle = self.closure_arena.insert(closure);
-                                #[cfg(feature = "vm-debug")]
-                                let debug_handle = closure_handle.clone();
-                                let closure_nan_boxed = NanBoxedValue::closure_handle(closure_handle);
-                                #[cfg(feature = "vm-debug")]
-                                log_debug!("CLOSURE CREATED WITH UPVALUES", handle = format!("{:?}", debug_handle).as_str(), is_closure_handle = closure_nan_boxed.is_closure_handle());
-                                self.stack.push(closure_nan_boxed);
-                            }
-                            _ => {
-                                return Err(VMError::CompilationError(format!("Expected closure pointer, found {:?} pointer", tag)));
-                            }
-                        }
-                    } else {
-                        return Err(VMError::CompilationError(format!("Expected closure pointer, found non-pointer value")));
+            }
+            // Superseded by the ByIndex forms, which are all the compiler emits
+            Op::SetGlobal | Op::GetGlobal => {
+                return Err(VMError::InvalidChunk);
+            }
+            Op::SetGlobalByIndex => {
+                let idx = self.call_stack.next_u16() as usize;
+                let slot = self.global_slot(idx)?;
+                // The value stays on the stack since assignments are expressions
+                let Some(&val) = self.stack.last() else {
+                    return Err(VMError::RuntimeError {
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                        msg: "No value to assign to a global".to_string(),
+                        backtrace: vec![],
+                    });
+                };
+                self.globals.set_slot(slot, val);
+            }
+            Op::GetGlobalByIndex => {
+                let idx = self.call_stack.next_u16() as usize;
+                let slot = self.global_slot(idx)?;
+                match self.globals.get_slot(slot) {
+                    Some(v) => self.stack.push(v),
+                    None => {
+                        let name = self.call_stack.get_constant(&self.closure_arena, idx);
+                        let line = self.call_stack.line_number_at(&self.closure_arena, -1);
+                        return Err(VMError::RuntimeError { line, msg: format!("Undefined global {}", name.as_string()), backtrace: vec![] });
                     }
                 }
-                Op::Call => {
-                    let arg_count = self.call_stack.next_byte() as usize;
-                    let func_slot = (self.stack.len() - 1) - arg_count;
-                    let func_nan_boxed = *self.stack.get(func_slot).unwrap();
-                    
-                    #[cfg(feature = "vm-debug")]
-                    log_debug!("CALL DEBUG", is_closure_handle = func_nan_boxed.is_closure_handle(), is_pointer = func_nan_boxed.is_pointer(), func_value = format!("{:?}", func_nan_boxed).as_str());
-                    
-                    if func_nan_boxed.is_closure_handle() {
-                        // New arena-based closure handle
-                        let closure_handle = func_nan_boxed.as_closure_handle();
-                        let closure = self.closure_arena.get(closure_handle).unwrap();
-                        
-                        // Inline validation
-                        if closure.func.arity != arg_count {
-                            return Err(VMError::RuntimeError { 
-                                line: self.call_stack.line_number_at(-1), 
-                                msg: format!("{} Expected {} arguments but got {}", closure.func.name, closure.func.arity, arg_count) 
-                            });
-                        }
-                        if self.call_stack.frames.len() > 100 {
-                            return Err(VMError::RuntimeError { 
-                                line: self.call_stack.line_number_at(-1), 
-                                msg: "Stack overflow".to_string() 
-                            });
-                        }
-                        
-                        // Get raw pointer for CallStack compatibility (temporary)
-                        let closure_ptr = closure as *const FnClosure;
-                        self.call_stack.push(closure_ptr, func_slot);
-                    } else if func_nan_boxed.is_pointer() {
-                        let (ptr, tag) = func_nan_boxed.as_pointer();
-                        match tag {
-                            PointerTag::Closure => {
-                                // Legacy closure pointer (during transition)
-                                let closure_ptr = ptr as *const FnClosure;
-                                let closure = unsafe { &*closure_ptr };
-                                
-                                // Inline validation to eliminate double cloning
-                                if closure.func.arity != arg_count {
-                                    return Err(VMError::RuntimeError { 
-                                        line: self.call_stack.line_number_at(-1), 
-                                        msg: format!("{} Expected {} arguments but got {}", closure.func.name, closure.func.arity, arg_count) 
-                                    });
-                                }
-                                if self.call_stack.frames.len() > 100 {
-                                    return Err(VMError::RuntimeError { 
-                                        line: self.call_stack.line_number_at(-1), 
-                                        msg: "Stack overflow".to_string() 
-                                    });
-                                }
-                                
-                                // Pass closure pointer directly - NO CLONING!
-                                self.call_stack.push(closure_ptr, func_slot);
-                            }
-                            PointerTag::NativeFn => {
-                                // Cast pointer back to NativeFn
-                                let native_fn = unsafe { &*(ptr as *const Rc<NativeFn>) };
-                                
-                                // Call native function directly with NanBoxedValue args
-                                let result = if arg_count > 0 {
-                                    let last_arg = self.stack.len() - 1;
-                                    let first_arg = last_arg - arg_count;
-                                    let nan_boxed_args = &self.stack[first_arg..last_arg];
-                                    (native_fn.func)(nan_boxed_args)?
-                                } else {
-                                    (native_fn.func)(&[])?
-                                };
-                                
-                                // Pop function and args from stack, push result
-                                for _ in 0..=arg_count {
-                                    self.stack.pop();
-                                }
-                                self.stack.push(result);
-                            }
-                            _ => {
-                                return Err(VMError::RuntimeError { 
-                                    line: self.call_stack.line_number_at(-1), 
-                                    msg: "Only functions can be called".to_string() 
-                                })
-                            }
-                        }
+            }
+            Op::NEGATE => {
+                let v = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                if let Some(result) = v.fast_negate() {
+                    self.stack.push(result);
+                } else {
+                    return Err(VMError::RuntimeError { 
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                        msg: "Can only negate numbers".to_string(),
+                        backtrace: vec![], 
+                    });
+                }
+            }
+            Op::ADD => {
+                // Fast-path NaN-boxed arithmetic
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_add(b) {
+                    self.stack.push(result);
+                } else {
+                    // Handle string concatenation
+                    if a.is_string() && b.is_string() {
+                        let a_str = a.as_string();
+                        let b_str = b.as_string();
+                        let result = format!("{}{}", a_str, b_str);
+                        let result = self.alloc_string(result);
+                        self.stack.push(result);
+                    } else if a.is_string() || b.is_string() {
+                        // String + non-string = convert to string and concatenate
+                        let a_str = if a.is_string() { a.as_string().to_string() } else { format!("{}", a) };
+                        let b_str = if b.is_string() { b.as_string().to_string() } else { format!("{}", b) };
+                        let result = format!("{}{}", a_str, b_str);
+                        let result = self.alloc_string(result);
+                        self.stack.push(result);
                     } else {
                         return Err(VMError::RuntimeError { 
-                            line: self.call_stack.line_number_at(-1), 
-                            msg: "Only functions can be called".to_string() 
+                            line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                            msg: format!("Cannot add {} and {}", a, b),
+                            backtrace: vec![], 
                         });
                     }
                 }
-                Op::SetLocal => {
-                    let relative_slot = self.call_stack.next_byte() as usize;
-                    let slot = self.call_stack.cur_frame().i(relative_slot);
-                    let value = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    #[cfg(feature = "vm-debug")]
-                    log_debug!("SET LOCAL", slot = slot, value = format!("{:?}", nan_boxed_value).as_str());
-                    // Ensure stack is large enough for the slot - use exponential growth
-                    if self.stack.len() <= slot {
-                        // Use exponential growth strategy to avoid O(n²) resize behavior
-                        let new_size = std::cmp::max(slot + 1, self.stack.len() * 2);
-                        self.stack.resize(new_size, NanBoxedValue::null());
-                    }
-                    self.stack[slot] = value;
-                    // Value stays on stack since assignments are expressions in Weave
-                }
-                Op::GetLocal => {
-                    let relative_slot = self.call_stack.next_byte() as usize;
-                    let slot = self.call_stack.cur_frame().i(relative_slot);
-                    // Ensure stack is large enough for the slot - use exponential growth
-                    if self.stack.len() <= slot {
-                        // Use exponential growth strategy to avoid O(n²) resize behavior
-                        let new_size = std::cmp::max(slot + 1, self.stack.len() * 2);
-                        self.stack.resize(new_size, NanBoxedValue::null());
-                    }
-                    // Use reference to avoid cloning during push
-                    let value = self.stack[slot];
-                    #[cfg(feature = "vm-debug")]
-                    log_debug!("GET LOCAL", slot = slot, value = format!("{:?}", value).as_str());
-                    self.stack.push(value);
-                }
-                Op::GetUpvalue => {
-                    let slot = self.call_stack.next_byte() as usize;
-                    // Get upvalue from arena using the handle
-                    let closure = unsafe { &*self.call_stack.cur_frame().closure };
-                    
-                    // DEBUG: Check the bounds
-                    if slot >= closure.upvalues.len() {
-                        panic!("GetUpvalue: slot {} out of bounds, upvalues.len() = {}, expected upvalue_count = {}", 
-                               slot, closure.upvalues.len(), closure.func.upvalue_count);
-                    }
-                    
-                    let upvalue_handle = &closure.upvalues[slot];
-                    let upvalue = self.upvalue_arena.get(upvalue_handle.clone()).unwrap();
-                    let nan_boxed_value = upvalue.get_fast(self);
-                    self.stack.push(nan_boxed_value);
+            }
+            Op::SUB => {
+                // Fast-path NaN-boxed arithmetic
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_sub(b) {
+                    self.stack.push(result);
+                } else {
+                    return Err(VMError::RuntimeError { 
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                        msg: format!("Cannot subtract {} from {}", b, a),
+                        backtrace: vec![], 
+                    });
                 }
-                Op::SetUpvalue => {
-                    let slot = self.call_stack.next_byte() as usize;
-                    // Set upvalue using the arena handle
-                    let nan_boxed_value = self.stack[self.stack.len() - 1]; // peek top of stack
-                    let closure = unsafe { &*self.call_stack.frames.last().unwrap().closure };
-                    let upvalue_handle = closure.upvalues[slot].clone();
-                    
-                    // We need to work around the borrow checker here
-                    // The issue is that set_fast needs &mut self, but we also have a mutable borrow from upvalue_arena
-                    // Solution: Get the upvalue, check if it's open, and handle accordingly
-                    let upvalue = self.upvalue_arena.get(upvalue_handle.clone()).unwrap();
-                    if upvalue.is_open() {
-                        let stack_index = upvalue.get_stack_index();
-                        self.stack[stack_index] = nan_boxed_value;
-                    } else {
-                        // For closed upvalues, we need to update the stored value
-                        // Use the same approach as close_upvalues to avoid borrow checker issues
-                        let upvalue_handle_clone = upvalue_handle.clone();
-                        drop(upvalue); // Release immutable borrow
-                        
-                        if let Some(upvalue) = self.upvalue_arena.get(upvalue_handle_clone) {
-                            upvalue.close_with_value(nan_boxed_value);
-                        }
-                    }
+            }
+            Op::MUL => {
+                // Fast-path NaN-boxed arithmetic
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_mul(b) {
+                    self.stack.push(result);
+                } else {
+                    return Err(VMError::RuntimeError { 
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                        msg: format!("Cannot multiply {} and {}", a, b),
+                        backtrace: vec![], 
+                    });
                 }
-                Op::SetGlobal => {
-                    // Previous to this we should have processed an expression (val)
-                    // then pushed the name of the global we want to bind it to
-                    // and now we need to actually bind it.
-                    // So pop the name and value off the stack.
-                    let name = self.stack.pop().unwrap();
-                    let val = self.stack.pop().unwrap();
-                    
-                    if name.is_string() {
-                        let name_str = name.as_string();
-                        self.debug(&format!("Declaring global: {} = {}", name_str, val));
-                        self.globals.insert(name_str.to_string(), val);
-                        self.stack.push(val); // Push the assigned value back for expression semantics
-                    } else {
-                        unreachable!("Only strings can become globals - how did you get here?");
-                    }
+            }
+            Op::DIV => {
+                // Fast-path NaN-boxed arithmetic
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_div(b) {
+                    self.stack.push(result);
+                } else {
+                    return Err(VMError::RuntimeError { 
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                        msg: format!("Cannot divide {} by {}", a, b),
+                        backtrace: vec![], 
+                    });
                 }
-                Op::GetGlobal => {
-                    let name = self.stack.pop().unwrap();
-                    
-                    if name.is_string() {
-                        let name_str = name.as_string();
-                        match self.globals.get(name_str) {
-                            Some(v) => {
-                                self.stack.push(*v);
-                            }
-                            None => {
-                                let line = self.call_stack.line_number_at(-1);
-                                return Err(VMError::RuntimeError { line, msg: format!("Undefined global {}", name_str) });
-                            }
-                        }
-                    } else {
-                        unreachable!("Expected an Identifier: {:?}", name);
-                    }
+            }
+            Op::MOD => {
+                // Fast-path NaN-boxed arithmetic
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_mod(b) {
+                    self.stack.push(result);
+                } else {
+                    return Err(VMError::RuntimeError { 
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1), 
+                        msg: format!("Cannot take the remainder of {} by {}", a, b),
+                        backtrace: vec![], 
+                    });
                 }
-                Op::NEGATE => {
-                    let v = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    if v.is_number() {
-                        self.stack.push(NanBoxedValue::number(-v.as_number()));
-                    } else {
-                        return Err(VMError::RuntimeError { 
-                            line: self.call_stack.line_number_at(-1), 
-                            msg: "Can only negate numbers".to_string() 
-                        });
-                    }
+            }
+            Op::BitAnd | Op::BitOr | Op::BitXor | Op::Shl | Op::Shr => {
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+
+                let (x, y) = match (a.as_whole_number(), b.as_whole_number()) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => return Err(VMError::RuntimeError {
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                        msg: format!("Bitwise operands must be integers, got {} and {}", a, b),
+                        backtrace: vec![],
+                    }),
+                };
+                let is_shift = matches!(op, Op::Shl | Op::Shr);
+                if is_shift && !(0..64).contains(&y) {
+                    return Err(VMError::RuntimeError {
+                        line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                        msg: format!("Cannot shift by {} bits", y),
+                        backtrace: vec![],
+                    });
                 }
-                Op::ADD => {
-                    // Fast-path NaN-boxed arithmetic
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    if let Some(result) = a.fast_add(b) {
-                        self.stack.push(result);
-                    } else {
-                        // Handle string concatenation
-                        if a.is_string() && b.is_string() {
-                            let a_str = a.as_string();
-                            let b_str = b.as_string();
-                            let result = format!("{}{}", a_str, b_str);
-                            self.stack.push(NanBoxedValue::string(result));
-                        } else if a.is_string() || b.is_string() {
-                            // String + non-string = convert to string and concatenate
-                            let a_str = if a.is_string() { a.as_string().to_string() } else { format!("{}", a) };
-                            let b_str = if b.is_string() { b.as_string().to_string() } else { format!("{}", b) };
-                            let result = format!("{}{}", a_str, b_str);
-                            self.stack.push(NanBoxedValue::string(result));
-                        } else {
-                            return Err(VMError::RuntimeError { 
-                                line: self.call_stack.line_number_at(-1), 
-                                msg: format!("Cannot add {} and {}", a, b) 
-                            });
-                        }
-                    }
+                let result = match op {
+                    Op::BitAnd => x & y,
+                    Op::BitOr => x | y,
+                    Op::BitXor => x ^ y,
+                    Op::Shl => x << y,
+                    _ => x >> y,
+                };
+                self.stack.push(NanBoxedValue::integer(result));
+            }
+            Op::TRUE => {
+                self.stack.push(NanBoxedValue::boolean(true));
+            }
+            Op::FALSE => {
+                self.stack.push(NanBoxedValue::boolean(false));
+            }
+            Op::NOT => {
+                // Everything is truthy in Weave, so we just need to negate
+                // the top value's "truthiness"
+                let val = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let is_truthy = val.is_truthy();
+                self.stack.push(NanBoxedValue::boolean(!is_truthy));
+            }
+            Op::GREATER => {
+                // Fast-path NaN-boxed comparison
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_greater(b) {
+                    self.stack.push(result);
+                } else {
+                    let result = self.compare_slow(a, b, std::cmp::Ordering::is_gt)?;
+                    self.stack.push(result);
                 }
-                Op::SUB => {
-                    // Fast-path NaN-boxed arithmetic
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    if let Some(result) = a.fast_sub(b) {
-                        self.stack.push(result);
-                    } else {
-                        return Err(VMError::RuntimeError { 
-                            line: self.call_stack.line_number_at(-1), 
-                            msg: format!("Cannot subtract {} from {}", b, a) 
-                        });
-                    }
+            }
+            Op::LESS => {
+                // Fast-path NaN-boxed comparison
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                if let Some(result) = a.fast_less(b) {
+                    self.stack.push(result);
+                } else {
+                    let result = self.compare_slow(a, b, std::cmp::Ordering::is_lt)?;
+                    self.stack.push(result);
                 }
-                Op::MUL => {
-                    // Fast-path NaN-boxed arithmetic
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    if let Some(result) = a.fast_mul(b) {
-                        self.stack.push(result);
-                    } else {
-                        return Err(VMError::RuntimeError { 
-                            line: self.call_stack.line_number_at(-1), 
-                            msg: format!("Cannot multiply {} and {}", a, b) 
-                        });
-                    }
+            }
+            Op::GEqual => {
+                // Fast-path NaN-boxed comparison
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+
+                if let Some(result) = a.fast_geq(b) {
+                    self.stack.push(result);
+                } else {
+                    let result = self.compare_slow(a, b, std::cmp::Ordering::is_ge)?;
+                    self.stack.push(result);
                 }
-                Op::DIV => {
-                    // Fast-path NaN-boxed arithmetic
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    if let Some(result) = a.fast_div(b) {
-                        self.stack.push(result);
-                    } else {
-                        return Err(VMError::RuntimeError { 
-                            line: self.call_stack.line_number_at(-1), 
-                            msg: format!("Cannot divide {} by {}", a, b) 
-                        });
-                    }
+            }
+            Op::LEqual => {
+                // Fast-path NaN-boxed comparison
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+
+                if let Some(result) = a.fast_leq(b) {
+                    self.stack.push(result);
+                } else {
+                    let result = self.compare_slow(a, b, std::cmp::Ordering::is_le)?;
+                    self.stack.push(result);
                 }
-                Op::TRUE => {
-                    self.stack.push(NanBoxedValue::boolean(true));
+            }
+            Op::EQUAL => {
+                // Fast-path NaN-boxed comparison
+                let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                
+                let result = a.fast_equal(b);
+                self.stack.push(result);
+            }
+            Op::PRINT => {
+                // Don't remove the top value from the stack - printing a value evaluates
+                // to the value itself. e.g. "print(1) == 1"
+                let value = *self.stack.last().unwrap_or(&NanBoxedValue::null());
+                let text = self.colorize(&self.format_value(value));
+                let _ = writeln!(self.output, "{}", text);
+                log_debug!("VM print instruction", value = format!("{}", value).as_str(), stack_depth = self.stack.len());
+            }
+            Op::PrintN => {
+                // Prints the values space-separated, leaving only the last one on the stack
+                let count = self.call_stack.next_byte() as usize;
+                let text = self.take_output_values(count)?;
+                let _ = writeln!(self.output, "{}", text);
+            }
+            Op::WRITE => {
+                // Same as PrintN, minus the newline, so output can be built up piecemeal
+                let count = self.call_stack.next_byte() as usize;
+                let text = self.take_output_values(count)?;
+                let _ = write!(self.output, "{}", text);
+                let _ = self.output.flush();
+            }
+            Op::BuildArray => {
+                let count = self.call_stack.next_u16() as usize;
+                let first = self.stack.len().checked_sub(count).ok_or(VMError::InvalidChunk)?;
+                let items = self.stack.split_off(first);
+                let handle = self.array_arena.insert(WeaveArray::new(items));
+                self.allocations += 1;
+                self.stack.push(NanBoxedValue::array_handle(handle));
+            }
+            Op::BuildMap => {
+                let count = self.call_stack.next_u16() as usize;
+                let first = self.stack.len().checked_sub(2 * count).ok_or(VMError::InvalidChunk)?;
+                let entries = self.stack.split_off(first);
+                let mut map = WeaveMap::new();
+                for entry in entries.chunks(2) {
+                    let key = self.map_key(entry[0])?;
+                    map.set(key, entry[1]);
                 }
-                Op::FALSE => {
-                    self.stack.push(NanBoxedValue::boolean(false));
+                let handle = self.map_arena.insert(map);
+                self.allocations += 1;
+                self.stack.push(NanBoxedValue::map_handle(handle));
+            }
+            Op::Index => {
+                let index = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let target = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                if target.is_map() {
+                    // Missing keys read as null
+                    let key = self.map_key(index)?;
+                    let value = self.map_arena.get(target.as_map_handle()).and_then(|m| m.get(key));
+                    self.stack.push(value.unwrap_or(NanBoxedValue::null()));
+                } else if target.is_string() {
+                    // Strings index by character, not byte, so multi-byte characters stay whole
+                    let text = target.as_string();
+                    let idx = self.checked_index(index, text.chars().count(), "string")?;
+                    let value = self.alloc_string(text.chars().nth(idx).unwrap().to_string());
+                    self.stack.push(value);
+                } else {
+                    let (handle, idx) = self.array_index(target, index)?;
+                    let value = self.array_arena.get(handle).and_then(|a| a.get(idx)).unwrap_or(NanBoxedValue::null());
+                    self.stack.push(value);
                 }
-                Op::NOT => {
-                    // Everything is truthy in Weave, so we just need to negate
-                    // the top value's "truthiness"
-                    let val = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let is_truthy = val.is_truthy();
-                    self.stack.push(NanBoxedValue::boolean(!is_truthy));
+            }
+            Op::Slice => {
+                let end = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let start = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let target = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                if target.is_string() {
+                    let text = target.as_string();
+                    let (start, end) = self.slice_bounds(start, end, text.chars().count(), "string")?;
+                    let value = self.alloc_string(text.chars().skip(start).take(end - start).collect());
+                    self.stack.push(value);
+                } else if target.is_array() {
+                    let handle = target.as_array_handle();
+                    let items = self.array_arena.get(handle).map(|a| a.items().to_vec()).unwrap_or_default();
+                    let (start, end) = self.slice_bounds(start, end, items.len(), "array")?;
+                    let handle = self.array_arena.insert(WeaveArray::new(items[start..end].to_vec()));
+                    self.allocations += 1;
+                    self.stack.push(NanBoxedValue::array_handle(handle));
+                } else {
+                    return Err(self.index_error(format!("Can only slice strings and arrays, not {}", target)));
                 }
-                Op::GREATER => {
-                    // Fast-path NaN-boxed comparison
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    if let Some(result) = a.fast_greater(b) {
-                        self.stack.push(result);
-                    } else {
-                        // For non-numeric comparisons, return false
-                        self.stack.push(NanBoxedValue::boolean(false));
+            }
+            Op::SetIndex => {
+                let value = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let index = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                let target = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                if target.is_map() {
+                    let key = self.map_key(index)?;
+                    if let Some(map) = self.map_arena.get_mut(target.as_map_handle()) {
+                        map.set(key, value);
                     }
-                }
-                Op::LESS => {
-                    // Fast-path NaN-boxed comparison
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    if let Some(result) = a.fast_less(b) {
-                        self.stack.push(result);
-                    } else {
-                        // For non-numeric comparisons, return false
-                        self.stack.push(NanBoxedValue::boolean(false));
+                } else {
+                    let (handle, idx) = self.array_index(target, index)?;
+                    if let Some(array) = self.array_arena.get_mut(handle) {
+                        array.set(idx, value);
                     }
                 }
-                Op::EQUAL => {
-                    // Fast-path NaN-boxed comparison
-                    let b = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    let a = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    
-                    let result = a.fast_equal(b);
-                    self.stack.push(result);
+                // Value stays on stack since assignments are expressions in Weave
+                self.stack.push(value);
+            }
+            Op::Jump => {
+                let jmp_target = self.call_stack.next_u16();
+                self.call_stack.jump(jmp_target)?;
+            }
+            Op::JumpIfFalse => {
+                let jmp_offset = self.call_stack.next_u16();
+                let value = self.stack.pop().unwrap_or(NanBoxedValue::null());
+                if !value.is_truthy() {
+                    self.call_stack.jump(jmp_offset)?;
                 }
-                Op::PRINT => {
-                    // Don't remove the top value from the stack - printing a value evaluates
-                    // to the value itself. e.g. "print(1) == 1"
-                    let value = *self.stack.last().unwrap_or(&NanBoxedValue::null());
-                    println!("{}", green(&format!("{}", value)));
-                    log_debug!("VM print instruction", value = format!("{}", value).as_str(), stack_depth = self.stack.len());
+                // Value is already popped - no need to do anything else
+            }
+            Op::JumpIfTrue => {
+                let jmp_offset = self.call_stack.next_u16();
+                if self.stack.last().is_some_and(|value| value.is_truthy()) {
+                    self.call_stack.jump(jmp_offset)?;
                 }
-                Op::Jump => {
-                    let jmp_target = self.call_stack.next_u16();
-                    self.call_stack.jump(jmp_target);
+            }
+            Op::Loop => {
+                let jmp_offset = self.call_stack.next_u16();
+                self.call_stack.jump_back(jmp_offset)?;
+            }
+        }
+
+        self.record_op_time(&op, start_time);
+
+        if self.debug_mode {
+            self.debug(&format!("  - {:?}", self.stack));
+            self.debug(&format!("  - {:?}", self.call_stack.constants(&self.closure_arena)));
+        }
+
+        Ok(None)
+    }
+
+    fn debug(&self, msg: &str) {
+        if self.debug_mode {
+            log_debug!("VM debug", message = msg, stack_depth = self.stack.len());
+        }
+    }
+
+    /// Logs the failing call stack, resets the VM and returns the backtrace
+    fn runtime_error(&mut self, msg: &str) -> Vec<(String, usize)> {
+        let mut backtrace = vec![];
+        let callstack = self.call_stack.frames.iter().rev();
+        for frame in callstack {
+            let Some(closure) = self.closure_arena.get(frame.closure.clone()) else { continue };
+            let func = &closure.func;
+            let line = func.chunk.line_number_at(frame.ip.idx(-1));
+            
+            log_error!("Runtime error in function", 
+                line = line, 
+                function = func.name.as_str(), 
+                message = msg,
+                code = func.chunk.line_str(frame.ip.idx(0)).as_str()
+            );
+            backtrace.push((func.name.clone(), line));
+        }
+
+        self.reset_stack();
+        backtrace
+    }
+
+    /// The global slot for the name constant at `idx` in the running chunk. The name is only
+    /// looked up the first time; after that the chunk remembers the slot.
+    fn global_slot(&mut self, idx: usize) -> Result<usize, VMError> {
+        let chunk = &self.call_stack.closure(&self.closure_arena).func.chunk;
+        if let Some(slot) = chunk.cached_global_slot(self.id, idx) {
+            return Ok(slot);
+        }
+        #[cfg(test)]
+        { self.global_lookups += 1; }
+        let name = chunk.constants.get(idx).filter(|name| name.is_string()).ok_or(VMError::InvalidChunk)?;
+        let slot = self.globals.slot(name.as_string());
+        chunk.cache_global_slot(self.id, idx, slot);
+        Ok(slot)
+    }
+
+    fn define_native(&mut self, func: Rc<NativeFn>) {
+        let name = func.name.to_string();
+        let nan_boxed_func = NanBoxedValue::pointer(Box::into_raw(Box::new(func)) as *const (), PointerTag::NativeFn);
+        self.globals.insert(name, nan_boxed_func);
+    }
+
+    fn reset_stack(&mut self) {
+        self.close_upvalues(0);
+        self.stack.clear();
+        self.call_stack.reset();
+        self.collect_garbage();
+    }
+
+    /// Interns a string, allocating it if needed so the garbage collector can free it later
+    fn alloc_string(&mut self, value: String) -> NanBoxedValue {
+        let (string, allocated) = self.strings.intern(value);
+        if allocated {
+            self.allocations += 1;
+        }
+        string
+    }
+
+    /// Swaps the string constants of a freshly compiled chunk, and of the functions within it,
+    /// for interned strings
+    fn intern_constants(&mut self, chunk: &mut Chunk) {
+        for constant in chunk.constants.iter_mut() {
+            if constant.is_string() {
+                *constant = self.strings.adopt_constant(*constant);
+            } else if constant.is_pointer() && constant.as_pointer().1 == PointerTag::Closure {
+                // SAFETY: the compiler leaked this closure for the chunk, and nothing has run yet
+                // that could have cloned it
+                let closure = unsafe { &mut *(constant.as_pointer().0 as *mut FnClosure) };
+                if let Some(func) = Rc::get_mut(&mut closure.func) {
+                    self.intern_constants(&mut func.chunk);
                 }
-                Op::JumpIfFalse => {
-                    let jmp_offset = self.call_stack.next_u16();
-                    let value = self.stack.pop().unwrap_or(NanBoxedValue::null());
-                    if !value.is_truthy() {
-                        self.call_stack.jump(jmp_offset);
+            }
+        }
+    }
+
+    /// Mark-and-sweep collection of runtime strings, closures, arrays, maps and upvalues.
+    /// Roots are the stack, globals, the last result and the closures of active call frames.
+    pub fn collect_garbage(&mut self) {
+        let mut strings: HashSet<u64> = HashSet::new();
+        let mut closures: HashSet<u64> = HashSet::new();
+        let mut arrays: HashSet<u64> = HashSet::new();
+        let mut maps: HashSet<u64> = HashSet::new();
+        let mut upvalues: HashSet<u64> = HashSet::new();
+
+        let mut pending: Vec<NanBoxedValue> = self.stack.clone();
+        pending.extend(self.globals.values().copied());
+        pending.push(self.last_value);
+
+        let mut pending_upvalues: Vec<crate::weave::vm::types::UpvalueHandle> = vec![];
+        for frame in &self.call_stack.frames {
+            if closures.insert(frame.closure.clone().to_u64())
+                && let Some(closure) = self.closure_arena.get(frame.closure.clone())
+            {
+                pending_upvalues.extend(closure.upvalues.iter().cloned());
+            }
+        }
+
+        loop {
+            if let Some(upvalue_handle) = pending_upvalues.pop() {
+                if !upvalues.insert(upvalue_handle.clone().to_u64()) { continue; }
+                if let Some(upvalue) = self.upvalue_arena.get(upvalue_handle) {
+                    // Open upvalues point into the stack, which is already a root
+                    if !upvalue.is_open() {
+                        pending.push(upvalue.get_fast(self));
                     }
-                    // Value is already popped - no need to do anything else
                 }
-                Op::Loop => {
-                    let jmp_offset = self.call_stack.next_u16();
-                    self.call_stack.jump_back(jmp_offset);
+            } else if let Some(value) = pending.pop() {
+                if value.is_string() {
+                    strings.insert(value.bits());
+                } else if value.is_array() {
+                    let handle = value.as_array_handle();
+                    if !arrays.insert(handle.clone().to_u64()) { continue; }
+                    if let Some(array) = self.array_arena.get(handle) {
+                        pending.extend_from_slice(array.items());
+                    }
+                } else if value.is_map() {
+                    let handle = value.as_map_handle();
+                    if !maps.insert(handle.clone().to_u64()) { continue; }
+                    if let Some(map) = self.map_arena.get(handle) {
+                        pending.extend(map.values().copied());
+                    }
+                } else if value.is_closure_handle() {
+                    let handle = value.as_closure_handle();
+                    if !closures.insert(handle.clone().to_u64()) { continue; }
+                    if let Some(closure) = self.closure_arena.get(handle) {
+                        pending_upvalues.extend(closure.upvalues.iter().cloned());
+                    }
                 }
+            } else {
+                break;
+            }
+        }
+
+        // Sweep everything that wasn't reached
+        let freed_strings = self.strings.sweep(|string| strings.contains(&string.bits()));
+
+        let dead_closures: Vec<_> = self.closure_arena.iter()
+            .filter(|(handle, _)| !closures.contains(&handle.clone().to_u64()))
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in &dead_closures {
+            self.closure_arena.remove(handle.clone());
+        }
+
+        let dead_arrays: Vec<_> = self.array_arena.iter()
+            .filter(|(handle, _)| !arrays.contains(&handle.clone().to_u64()))
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in &dead_arrays {
+            self.array_arena.remove(handle.clone());
+        }
+
+        let dead_maps: Vec<_> = self.map_arena.iter()
+            .filter(|(handle, _)| !maps.contains(&handle.clone().to_u64()))
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in &dead_maps {
+            self.map_arena.remove(handle.clone());
+        }
+
+        let dead_upvalues: Vec<_> = self.upvalue_arena.iter()
+            .filter(|(handle, _)| !upvalues.contains(&handle.clone().to_u64()))
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in &dead_upvalues {
+            self.upvalue_arena.remove(handle.clone());
+        }
+        self.open_upvalues.retain(|handle| upvalues.contains(&handle.clone().to_u64()));
+
+        // Give back the tail of each arena. Live objects don't move, so stored handles stay valid.
+        self.closure_arena.compact();
+        self.array_arena.compact();
+        self.map_arena.compact();
+        self.upvalue_arena.compact();
+
+        let live = self.heap_object_count();
+        self.allocations = live;
+        self.next_gc = std::cmp::max(GC_INITIAL_THRESHOLD, live * 2);
+        log_debug!("Garbage collection complete",
+            freed_strings = freed_strings,
+            freed_closures = dead_closures.len(),
+            freed_arrays = dead_arrays.len(),
+            freed_maps = dead_maps.len(),
+            freed_upvalues = dead_upvalues.len(),
+            live_objects = live
+        );
+    }
+
+    /// Turns opcode profiling on or off. It is on by default only in builds with the
+    /// `vm-profiling` feature; timings accumulate across runs until `profile_report` is read.
+    pub fn set_profile(&mut self, profile: bool) {
+        self.profile = profile;
+    }
+
+    /// Per-opcode (name, total ns, count) gathered while profiling, slowest first
+    pub fn profile_report(&self) -> Vec<(String, u64, u64)> {
+        let mut report: Vec<_> = self.opcode_times.iter()
+            .map(|(opcode, (total_ns, count))| (opcode.clone(), *total_ns, *count))
+            .collect();
+        report.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        report
+    }
+
+    /// Writes the ten slowest opcodes from `profile_report` to stderr
+    pub fn print_profile(&self) {
+        let report = self.profile_report();
+        if report.is_empty() {
+            eprintln!("No opcodes were executed!");
+            return;
+        }
+        eprintln!("Opcode Performance Profile:");
+        for (opcode, total_ns, count) in report.iter().take(10) {
+            let avg_ns = total_ns / count;
+            eprintln!("  {:15} {:8} calls, {:10} ns total, {:6} ns avg", opcode, count, total_ns, avg_ns);
+        }
+        eprintln!();
+    }
+
+    fn record_op_time(&mut self, op: &Op, start_time: Option<std::time::Instant>) {
+        if let Some(start_time) = start_time {
+            let elapsed = start_time.elapsed().as_nanos() as u64;
+            let entry = self.opcode_times.entry(format!("{:?}", op)).or_insert((0, 0));
+            entry.0 += elapsed;
+            entry.1 += 1;
+        }
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            stack_len: self.stack.len(),
+            stack_capacity: self.stack.capacity(),
+            frames: self.call_stack.frames.len(),
+            globals: self.globals.len(),
+            strings: self.strings.len(),
+            closures: self.closure_arena.stats(),
+            upvalues: self.upvalue_arena.stats(),
+            arrays: self.array_arena.stats(),
+            maps: self.map_arena.stats(),
+        }
+    }
+
+    /// Number of runtime heap objects (strings, closures and arrays) the VM is holding on to
+    pub fn heap_object_count(&self) -> usize {
+        self.strings.len() + self.closure_arena.len() + self.array_arena.len() + self.map_arena.len()
+    }
+
+    /// Limits how many values the stack may grow to before execution fails
+    pub fn set_max_stack_size(&mut self, max_stack_size: usize) {
+        self.max_stack_size = max_stack_size;
+    }
+
+    /// Grows the stack so that `slot` exists, refusing to grow past `max_stack_size`
+    fn ensure_slot(&mut self, slot: usize) -> Result<(), VMError> {
+        if slot < self.stack.len() {
+            return Ok(());
+        }
+        if slot >= self.max_stack_size {
+            return Err(VMError::RuntimeError {
+                line: self.call_stack.line_number_at(&self.closure_arena, -1),
+                msg: "Stack size limit exceeded".to_string(),
+                backtrace: vec![],
+            });
+        }
+        // Grow exponentially to avoid O(n²) resize behavior
+        let new_size = std::cmp::max(slot + 1, self.stack.len() * 2).min(self.max_stack_size);
+        self.stack.resize(new_size, NanBoxedValue::null());
+        Ok(())
+    }
+
+    /// Sets the command-line arguments scripts can read with `args()`
+    pub fn set_script_args(&mut self, script_args: Vec<String>) {
+        self.script_args = script_args;
+    }
+
+    /// Redirects the output of `puts` and `echo`, which goes to stdout by default
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Turns the coloring of `puts` and `echo` output on or off. By default output is
+    /// colored only when stdout is a terminal and `NO_COLOR` isn't set.
+    pub fn set_color_output(&mut self, color_output: bool) {
+        self.color_output = color_output;
+    }
+
+    fn colorize(&self, text: &str) -> String {
+        if self.color_output { green(text) } else { text.to_string() }
+    }
+
+    /// Formats the top `count` values space-separated for output, popping all but the last
+    fn take_output_values(&mut self, count: usize) -> Result<String, VMError> {
+        // At least one value has to be left behind as the result
+        let first = self.stack.len().checked_sub(count).filter(|_| count > 0).ok_or(VMError::InvalidChunk)?;
+        let text = self.stack[first..].iter()
+            .map(|v| self.format_value(*v))
+            .collect::<Vec<String>>()
+            .join(" ");
+        self.stack.drain(first..self.stack.len() - 1);
+        Ok(self.colorize(&text))
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<NanBoxedValue> {
+        self.globals.get(name).copied()
+    }
+
+    /// Names of all currently defined globals, sorted
+    pub fn global_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.globals.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// The bytecode listing for the global function `name`, if it names a Weave function
+    pub fn disassemble_global(&self, name: &str) -> Option<String> {
+        let value = self.get_global(name)?;
+        if !value.is_closure_handle() {
+            return None;
+        }
+        let closure = self.closure_arena.get(value.as_closure_handle())?;
+        Some(closure.func.disassemble_all())
+    }
+    
+    
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_math() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("5 + 2 * 3");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let result = res.unwrap();
+        assert!(result.is_number());
+        assert_eq!(result.as_number(), 11.0);
+    }
+
+    #[test]
+    fn test_parenthesis() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("(5 + 2) * 3");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(21.0));
+    }
+
+    #[test]
+    fn test_modulo() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("7 % 3");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(1.0));
+
+        let res = vm.interpret("7.5 % 2.0");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(1.5));
+    }
+
+    #[test]
+    fn test_modulo_precedence() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("1 + 10 % 4 * 2");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(5.0));
+    }
+
+    #[test]
+    fn test_modulo_non_numeric() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("true % 2");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_logical_and() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("true && false");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(false));
+
+        let res = vm.interpret("true && 3");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(3.0));
+    }
+
+    #[test]
+    fn test_logical_or() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("false || true");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(true));
+
+        let res = vm.interpret("false || false");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(false));
+    }
+
+    #[test]
+    fn test_logical_short_circuit() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("false && undefined_fn()");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(false));
+
+        let res = vm.interpret("true || boom()");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(true));
+    }
+
+    #[test]
+    fn test_or_returns_the_first_truthy_operand() {
+        let cases = [
+            ("null || \"default\"", "default"),
+            ("\"name\" || \"default\"", "name"),
+            ("false || null", "null"),
+            ("null || false || 3", "3"),
+            ("x = null\nif x || true { 1 } else { 2 }", "1"),
+            // The right side only runs when the left is falsy
+            ("calls = [0]\nfn bump() { calls[0] = calls[0] + 1; true }\n1 || bump()\nfalse || bump()\ncalls[0]", "1"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_and_returns_the_first_falsy_operand() {
+        let cases = [
+            ("null && \"never\"", "null"),
+            ("\"name\" && \"greeting\"", "greeting"),
+            ("1 && false && 3", "false"),
+            ("1 && 2 && 3", "3"),
+            ("x = null\nif x && true { 1 } else { 2 }", "2"),
+            // The right side only runs when the left is truthy
+            ("calls = [0]\nfn bump() { calls[0] = calls[0] + 1; true }\nnull && bump()\n1 && bump()\ncalls[0]", "1"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_logical_keywords_match_the_symbols() {
+        let mut vm = VM::new(false);
+        let forms = [
+            ("a and b or not c", "a && b || !c"),
+            ("not a or b and c", "!a || b && c"),
+            ("a or b and not c", "a || b && !c"),
+            ("not not a and b", "!!a && b"),
+            ("not a == b", "!a == b"),
+        ];
+        for values in [[true, true, true], [true, false, false], [false, true, true], [false, false, true]] {
+            let [a, b, c] = values;
+            let setup = format!("a = {}\nb = {}\nc = {}\n", a, b, c);
+            for (words, symbols) in forms {
+                let res = vm.interpret(&format!("{}{}", setup, words));
+                assert!(res.is_ok(), "Failed to interpret {}: {:?}", words, res.unwrap_err());
+                let expected = vm.interpret(&format!("{}{}", setup, symbols)).unwrap();
+                assert_eq!(res.unwrap(), expected, "{} with {:?}", words, values);
+            }
+        }
+
+        // `or` keeps the first truthy operand, just like ||
+        let res = vm.interpret("null or \"default\"");
+        assert_eq!(vm.format_value(res.unwrap()), "default");
+    }
+
+    #[test]
+    fn test_logical_nesting() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("a = true\nb = false\nc = true\na && b || c");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(true));
+
+        let res = vm.interpret("false || true && false");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(false));
+
+        let res = vm.interpret("1 < 2 && 3 > 2");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(true));
+    }
+
+    #[test]
+    fn test_array_literal() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("[1, 2, 3]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let array = res.unwrap();
+        assert!(array.is_array());
+        assert_eq!(vm.format_value(array), "[1, 2, 3]");
+
+        let res = vm.interpret("[]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(vm.format_value(res.unwrap()), "[]");
+    }
+
+    #[test]
+    fn test_array_index() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("[1,2,3][1]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(2.0));
+
+        let res = vm.interpret("a = [[1, 2], [3, 4]]\na[1][0]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(3.0));
+    }
+
+    #[test]
+    fn test_array_set_index() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("a = [1, 2, 3]\na[0] = 9\na[0]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(9.0));
+
+        let code = "fn t() {
+            a = [1, 2, 3]
+            a[2] = a[1] * 10
+            a
+        } t()";
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(vm.format_value(res.unwrap()), "[1, 2, 20]");
+    }
+
+    #[test]
+    fn test_array_index_out_of_bounds() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("[1, 2, 3][3]");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+
+        let res = vm.interpret("a = [1]\na[-1] = 2");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+
+        let res = vm.interpret("5[0]");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_native_read_write_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("roundtrip.txt");
+        let code = format!("write(\"{0}\", \"hello\")\nread(\"{0}\")", path.to_string_lossy());
+        let mut vm = VM::new(false);
+        let res = vm.interpret(&code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(value.is_string());
+        assert_eq!(value.as_string(), "hello");
+    }
+
+    #[test]
+    fn test_native_len() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("len(\"hello\")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(5.0));
+
+        let res = vm.interpret("len([1, 2, 3, 4])");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(4.0));
+
+        let res = vm.interpret("len(true)");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+
+        let res = vm.interpret("len()");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_native_math() {
+        let cases = [
+            ("sqrt(9)", 3.0),
+            ("floor(2.7)", 2.0),
+            ("ceil(2.1)", 3.0),
+            ("abs(-4)", 4.0),
+            ("round(2.5)", 3.0),
+            ("round(-1.2)", -1.0),
+            ("pow(2, 10)", 1024.0),
+            ("pow(-2, 3)", -8.0),
+            ("pow(4, 0.5)", 2.0),
+            ("mod(10, 3)", 1.0),
+            ("mod(7.5, 2)", 1.5),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::number(expected), "{}", code);
+        }
+
+        let res = vm.interpret("sqrt(\"nine\")");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+
+        for code in ["pow(2, \"ten\")", "mod(null, 3)"] {
+            let res = vm.interpret(code);
+            assert!(matches!(res, Err(VMError::RuntimeError { .. })), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_native_type() {
+        let cases = [
+            ("type(1)", "number"),
+            ("type(1.5)", "number"),
+            ("type(\"a\")", "string"),
+            ("type(true)", "boolean"),
+            ("type([1])", "array"),
+            ("type(^(){0})", "function"),
+            ("fn f() { 1 }\ntype(f)", "function"),
+            ("type(len)", "function"),
+            ("type(type(1))", "string"),
+            ("type(null)", "null"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap().as_string(), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_native_assert() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("assert(1 == 1, \"ok\")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_null());
+
+        let res = vm.interpret("assert(1 == 2, \"nope\")");
+        match res {
+            Err(VMError::RuntimeError { msg, .. }) => assert_eq!(msg, "nope"),
+            other => panic!("Expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_native_str() {
+        let cases = [
+            ("str(42)", "42"),
+            ("str(true)", "true"),
+            ("fn nothing() { return }\nstr(nothing())", "null"),
+            ("str(6.02)", "6.02"),
+            ("str(\"already\")", "already"),
+            ("\"n=\" + str(1 + 1)", "n=2"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap().as_string(), expected, "{}", code);
+        }
+
+        // Stringified floats parse back to the same value
+        let res = vm.interpret("str(0.1 + 0.2)").unwrap();
+        assert_eq!(res.as_string().parse::<f64>().unwrap(), 0.1 + 0.2);
+    }
+
+    #[test]
+    fn test_native_num() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("num(\"3.5\")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(3.5));
+
+        let res = vm.interpret("num(\" 10 \")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(value.is_int());
+        assert_eq!(value, NanBoxedValue::number(10.0));
+
+        // Unparseable strings give null rather than stopping the script
+        let res = vm.interpret("num(\"abc\")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_null());
+
+        let res = vm.interpret("num(10)");
+        assert!(matches!(res, Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_native_string_functions() {
+        let cases = [
+            ("upper(\"abc\")", "ABC"),
+            ("lower(\"AbC\")", "abc"),
+            ("trim(\"  x  \")", "x"),
+            ("trim(\"\\tx y\\n\")", "x y"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap().as_string(), expected, "{}", code);
+        }
+
+        let cases = [
+            ("split(\"a,b,,c\", \",\")", "[a, b, , c]"),
+            ("split(\"one two\", \" \")", "[one, two]"),
+            ("split(\"abc\", \"\")", "[a, b, c]"),
+            ("split(\"abc\", \"-\")", "[abc]"),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+
+        for code in ["upper(1)", "trim(null)", "split(\"a\", 1)"] {
+            let res = vm.interpret(code);
+            assert!(matches!(res, Err(VMError::RuntimeError { .. })), "{} should fail", code);
+        }
+    }
+
+    #[test]
+    fn test_native_string_predicates() {
+        let cases = [
+            ("contains(\"hello\", \"ell\")", true),
+            ("contains(\"hello\", \"elo\")", false),
+            ("starts_with(\"hello\", \"he\")", true),
+            ("starts_with(\"hello\", \"lo\")", false),
+            ("ends_with(\"hello\", \"lo\")", true),
+            ("ends_with(\"hello\", \"he\")", false),
+            ("contains(\"hello\", \"\")", true),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+
+        for code in ["contains(1, \"1\")", "starts_with(\"a\", null)", "ends_with([\"a\"], \"a\")"] {
+            let res = vm.interpret(code);
+            assert!(matches!(res, Err(VMError::RuntimeError { .. })), "{} should fail", code);
+        }
+    }
+
+    #[test]
+    fn test_native_env() {
+        // SAFETY: no other test touches this variable
+        unsafe { std::env::set_var("WEAVE_TEST_ENV_NATIVE", "woven") };
+        let mut vm = VM::new(false);
+        let res = vm.interpret("env(\"WEAVE_TEST_ENV_NATIVE\")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(value.is_string());
+        assert_eq!(value.as_string(), "woven");
+
+        let res = vm.interpret("env(\"WEAVE_TEST_ENV_NATIVE_UNSET\")");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_null());
+
+        assert!(matches!(vm.interpret("env(1)"), Err(VMError::RuntimeError { .. })));
+    }
+
+    #[test]
+    fn test_native_args() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("len(args())");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(0));
+
+        vm.set_script_args(vec!["first".to_string(), "--flag".to_string()]);
+        let res = vm.interpret("args()");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(vm.format_value(res.unwrap()), "[first, --flag]");
+        let res = vm.interpret("args()[1]");
+        assert_eq!(res.unwrap().as_string(), "--flag");
+    }
+
+    #[test]
+    fn test_native_defined() {
+        let mut vm = VM::new(false);
+        let cases = [
+            ("defined(\"x\")", false),
+            ("x = 1\ndefined(\"x\")", true),
+            ("defined(\"len\")", true),
+            // Only globals count - a function's locals aren't visible by name
+            ("fn f() { local = 1; defined(\"local\") }\nf()", false),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+        assert!(vm.interpret("defined(1)").is_err());
+    }
+
+    #[test]
+    fn test_native_range() {
+        let mut vm = VM::new(false);
+        let cases = [
+            ("range(3)", "[0, 1, 2]"),
+            ("range(2, 5)", "[2, 3, 4]"),
+            ("range(0)", "[]"),
+            ("range(4, 4)", "[]"),
+            ("range(-2, 1)", "[-2, -1, 0]"),
+            ("range(2.0)", "[0, 1]"),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+
+        let errors = [
+            ("range(-1)", "range can't count down from 0 to -1"),
+            ("range(5, 2)", "range can't count down from 5 to 2"),
+            ("range(1.5)", "range expects a whole number, got 1.5"),
+            ("range(\"3\")", "range expects a number, got 3"),
+            ("range()", "range Expected 1 to 2 arguments but got 0"),
+            ("range(1, 2, 3)", "range Expected 1 to 2 arguments but got 3"),
+            ("range(1e10)", "range from 0 to 10000000000 would hold more than 10000000 items"),
+            ("range(-5, 10000000)", "range from -5 to 10000000 would hold more than 10000000 items"),
+        ];
+        for (code, expected) in errors {
+            match vm.interpret(code) {
+                Err(VMError::RuntimeError { msg, .. }) => assert_eq!(msg, expected, "{}", code),
+                other => panic!("Expected {} to fail, got {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stepping_through_a_program() {
+        let mut vm = VM::new(false);
+        vm.load("x = 1\n2 + 3 * 4").unwrap();
+        let ints = |vm: &VM| -> Vec<i64> {
+            // Skip the script's own closure at the bottom of the stack
+            vm.stack_snapshot()[1..].iter().map(|v| v.as_int()).collect()
+        };
+
+        // Nothing has run yet
+        assert_eq!(vm.current_line(), 1);
+        assert_eq!(vm.stack_snapshot().len(), 1);
+        assert_eq!(vm.get_global("x"), None);
+
+        // `x = 1` pushes the 1, then stores it
+        assert!(vm.step().unwrap());
+        assert_eq!(ints(&vm), [1]);
+        assert!(vm.step().unwrap());
+        assert_eq!(vm.get_global("x"), Some(NanBoxedValue::integer(1)));
+        assert!(vm.step().unwrap());
+        assert_eq!(ints(&vm), Vec::<i64>::new());
+
+        // Each step runs exactly one instruction of `2 + 3 * 4`
+        assert_eq!(vm.current_line(), 2);
+        let expected: [&[i64]; 5] = [&[2], &[2, 3], &[2, 3, 4], &[2, 12], &[14]];
+        for stack in expected {
+            assert!(vm.step().unwrap());
+            assert_eq!(ints(&vm), stack);
+        }
+
+        // The final return empties the stack and ends the program
+        assert!(!vm.step().unwrap());
+        assert!(vm.stack_snapshot().is_empty());
+        assert_eq!(vm.current_line(), 0);
+        assert!(!vm.step().unwrap());
+    }
+
+    #[test]
+    fn test_stepping_into_a_runtime_error() {
+        let mut vm = VM::new(false);
+        vm.load("1\n-\"a\"").unwrap();
+        let mut steps = 0;
+        let err = loop {
+            match vm.step() {
+                Ok(true) => steps += 1,
+                Ok(false) => panic!("Expected the negation to fail"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(steps, 3);
+        assert!(matches!(err, VMError::RuntimeError { line: 2, .. }), "{:?}", err);
+        assert!(vm.stack_snapshot().is_empty());
+        assert!(matches!(vm.load("1 +"), Err(VMError::CompilationError { .. })));
+    }
+
+    #[test]
+    fn test_natives_call_back_into_weave() {
+        let mut vm = VM::new(false);
+        let cases = [
+            ("filter([1, 2, 3, 4], fn(x) { x % 2 == 0 })", "[2, 4]"),
+            ("map([1, 2, 3], fn(x) { x * x })", "[1, 4, 9]"),
+            ("map([], fn(x) { x })", "[]"),
+            ("reduce([1, 2, 3, 4], fn(acc, x) { acc + x })", "10"),
+            ("reduce([1, 2, 3], fn(acc, x) { acc + x }, 10)", "16"),
+            ("reduce([], fn(acc, x) { acc + x }, 0)", "0"),
+            ("fn double(x) { x * 2 }\nmap(range(3), double)", "[0, 2, 4]"),
+            // Natives can be passed along, and callbacks can call back in turn
+            ("map([[1, 2], [3]], fn(xs) { reduce(map(xs, fn(x) { x + 1 }), fn(a, b) { a + b }) })", "[5, 4]"),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+
+        let errors = [
+            ("map(3, fn(x) { x })", "map expects an array, got number"),
+            ("filter([1], 2)", "filter expects a function, got number"),
+            ("reduce([], fn(acc, x) { acc + x })", "reduce needs an initial value for an empty array"),
+            ("map([1])", "map Expected 2 arguments but got 1"),
+        ];
+        for (code, expected) in errors {
+            match vm.interpret(code) {
+                Err(VMError::RuntimeError { msg, .. }) => assert_eq!(msg, expected, "{}", code),
+                other => panic!("Expected {} to fail, got {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_debug_and_quiet_vms() {
+        for debug_mode in [true, false] {
+            let mut vm = VM::new(debug_mode);
+            let res = vm.interpret("fn add(a, b) { a + b } add(1, 2)");
+            assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::number(3.0));
+        }
+    }
+
+    #[test]
+    fn test_gc_bounds_string_allocations() {
+        let code = "fn t() {
+            s = \"\"
+            for (i = 0; i < 5000; i = i + 1) {
+                s = \"x\" + i
+            }
+            s
+        } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap().as_string(), "x4999");
+        assert!(vm.heap_object_count() <= 2 * GC_INITIAL_THRESHOLD, "heap grew to {}", vm.heap_object_count());
+    }
+
+    #[test]
+    fn test_gc_keeps_reachable_values() {
+        let code = "
+            keep = [\"a\" + \"b\", [1, 2]]
+            fn make_counter() {
+              count = 0
+              fn counter() {
+                count = count + 1
+                count
+              }
+              counter
+            }
+            c = make_counter()
+            c()
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+
+        vm.collect_garbage();
+        let res = vm.interpret("for (i = 0; i < 3000; i = i + 1) { [i] } c()");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(2.0));
+
+        vm.collect_garbage();
+        assert_eq!(vm.format_value(vm.globals["keep"]), "[ab, [1, 2]]");
+    }
+
+    #[test]
+    fn test_strings_are_interned() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("a = \"abc\"\nfn f() { \"abc\" }\nb = f()\nc = \"ab\" + \"c\"\na == b");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(true));
+        // Literals, including those in other functions, and built strings all share one handle
+        let a = vm.globals["a"];
+        assert_eq!(a.bits(), vm.globals["b"].bits());
+        assert_eq!(a.bits(), vm.globals["c"].bits());
+
+        let cases = [
+            ("\"abc\" == \"abc\"", true),
+            ("a == upper(\"ABC\")", false),
+            ("lower(\"ABC\") == a", true),
+            ("str(12) == \"12\"", true),
+            ("\"abc\" != \"abd\"", true),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+
+        // A collected string is interned afresh the next time it is built
+        vm.interpret("d = \"x\" + 1; d = null").unwrap();
+        vm.collect_garbage();
+        let res = vm.interpret("\"x\" + 1 == \"x1\"");
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(true));
+    }
+
+    #[test]
+    fn test_a_chunk_runs_on_several_vms() {
+        // Both chunks name `secret`; interning one copy must not free the other for later runs
+        let chunk = crate::compile("fn f() { secret }\nsecret = 42\nf()").unwrap();
+        for _ in 0..2 {
+            let mut vm = VM::new(false);
+            let res = vm.interpret_chunk(chunk.clone());
+            assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::integer(42));
+        }
+        let res = VM::new(false).interpret_chunk(chunk);
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(42));
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let cases = [
+            ("\"apple\" < \"banana\"", true),
+            ("\"banana\" < \"apple\"", false),
+            ("\"banana\" > \"apple\"", true),
+            ("\"app\" < \"apple\"", true),
+            ("\"Zebra\" < \"apple\"", true),
+            ("\"same\" >= \"same\"", true),
+            ("\"same\" > \"same\"", false),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_chained_comparisons() {
+        let cases = [
+            ("1 < 2 < 3", true),
+            ("3 < 2 < 1", false),
+            ("1 < 3 > 2", true),
+            ("1 < 3 > 4", false),
+            ("3 > 2 > 1", true),
+            ("1 <= 1 < 2 <= 2", true),
+            ("1 < 2 < 3 < 2", false),
+            // Equality binds looser, so it compares the whole chain's result
+            ("1 < 2 < 3 == true", true),
+            ("x = 5\n0 < x <= 10", true),
+            ("fn between(n) { 0 <= n < 10 }\nbetween(9) && !between(10)", true),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+
+        // Middle operands are evaluated once, and a false link skips the rest
+        let res = vm.interpret("calls = [0]\nfn two() { calls[0] = calls[0] + 1; 2 }\nfn boom() { calls[0] = 100; 0 }\n1 < two() < 3\n3 < two() < boom()\ncalls[0]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(2));
+    }
+
+    #[test]
+    fn test_global_slots_are_cached() {
+        let mut vm = VM::new(false);
+        let code = "step = 3\ntotal = 0\ni = 0\nwhile i < 100000 { total = total + step; i = i + 1 }\ntotal";
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(300000));
+        // Each name constant is looked up once, however many times the loop reads it
+        assert!(vm.global_lookups <= 10, "looked up globals {} times", vm.global_lookups);
+
+        // Redefining a global after its slot is cached still sees the new value
+        let res = vm.interpret("fn fetch() { later }\nfetch()");
+        assert!(matches!(res, Err(VMError::RuntimeError { ref msg, .. }) if msg == "Undefined global later"), "{:?}", res);
+        let res = vm.interpret("later = 1\nfirst = fetch()\nlater = \"two\"\n[first, fetch()]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(vm.format_value(res.unwrap()), "[1, two]");
+    }
+
+    #[test]
+    fn test_global_slots_are_cached_per_vm() {
+        let chunk = crate::compile("fn f() { b }\na = 1\nb = 2\nf()").unwrap();
+        let res = VM::new(false).interpret_chunk(chunk.clone());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(2));
+
+        // f's chunk is shared with the first VM, where `b` had a different slot
+        let mut vm = VM::new(false);
+        vm.interpret("early = 5
+later = 6").unwrap();
+        let res = vm.interpret_chunk(chunk);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(2));
+    }
+
+    #[test]
+    fn test_malformed_global_access() {
+        // A global's name operand must point at a string constant
+        let mut chunk = Chunk::new();
+        let idx = chunk.add_constant_only(NanBoxedValue::integer(7)).unwrap();
+        chunk.write_op(Op::GetGlobalByIndex, 1);
+        chunk.write(&(idx as u16).to_be_bytes().to_vec(), 1);
+        chunk.write_op(Op::RETURN, 1);
+        assert!(matches!(VM::new(false).interpret_chunk(chunk), Err(VMError::InvalidChunk)));
+
+        // Assigning with nothing on the stack is rejected before it runs
+        let mut chunk = Chunk::new();
+        let idx = chunk.add_constant_only(NanBoxedValue::string("x".to_string())).unwrap();
+        chunk.write_op(Op::POP, 1);
+        chunk.write_op(Op::SetGlobalByIndex, 1);
+        chunk.write(&(idx as u16).to_be_bytes().to_vec(), 1);
+        chunk.write_op(Op::RETURN, 1);
+        assert!(matches!(VM::new(false).interpret_chunk(chunk), Err(VMError::InvalidChunk)));
+    }
+
+    #[test]
+    fn test_unverified_stack_underflow_is_an_error() {
+        // Chunks that skipped `Chunk::verify` still can't crash the VM by taking values it doesn't have
+        let cases = [
+            vec![Op::BuildArray.bytecode()[0], 0xff, 0],
+            vec![Op::BuildMap.bytecode()[0], 0x80, 0],
+            vec![Op::Call.bytecode()[0], 5],
+            vec![Op::PrintN.bytecode()[0], 0],
+            vec![Op::WRITE.bytecode()[0], 0],
+            vec![Op::SetGlobal.bytecode()[0]],
+            vec![Op::GetGlobal.bytecode()[0]],
+        ];
+        for code in cases {
+            let mut func = WeaveFn::new(String::new(), vec![]);
+            func.chunk.code = code.clone();
+            func.chunk.write_op(Op::RETURN, 1);
+            let mut vm = VM::new(false);
+            vm.load_script(func);
+            let res = vm.run();
+            assert!(matches!(res, Err(VMError::InvalidChunk)), "{:?}: {:?}", code, res);
+        }
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let cases = [
+            ("fn fib(n){ if n<2 {n} else {fib(n-1)+fib(n-2)} } fib(10)", "55"),
+            ("fn outer() {\nfn fact(n) { if n < 2 { 1 } else { n * fact(n - 1) } }\nfact(5)\n}\nouter()", "120"),
+            // Sibling functions get slots of their own
+            ("fn pair(x) {\nfn first(a) { a }\nfn second(b) { b * 2 }\n[first(x), second(x)]\n}\npair(3)", "[3, 6]"),
+            // Inside a function, mutual recursion needs the second name declared up front
+            ("fn parity(x) {\nis_odd = null\nfn is_even(n) { n == 0 ? true : is_odd(n - 1) }\nfn is_odd(n) { n == 0 ? false : is_even(n - 1) }\n[is_even(x), is_odd(x)]\n}\nparity(7)", "[false, true]"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+
+        let res = vm.interpret("fn undeclared(x) {\nfn a(n) { n == 0 ? 0 : b(n - 1) }\nfn b(n) { a(n) }\na(x)\n}\nundeclared(2)");
+        assert!(matches!(res, Err(VMError::RuntimeError { ref msg, .. }) if msg == "Undefined global b"), "{:?}", res);
+    }
+
+    #[test]
+    fn test_fn_expressions() {
+        let cases = [
+            ("g = fn(n){n+1}; g(4)", 5),
+            ("fn(a, b) { a * b }(6, 7)", 42),
+            ("fn adder(x) { fn(y) { x + y } }\nadder(2)(3)", 5),
+            // A name is bound inside the function, so the function can recurse
+            ("f = fn fact(n) { n < 2 ? 1 : n * fact(n - 1) }\nf(5)", 120),
+            ("fn outer() {\nfib = fn fib(n) { n < 2 ? n : fib(n - 1) + fib(n - 2) }\nfib(10)\n}\nouter()", 55),
+            // Partway through an expression, the name doesn't take a stack slot from its neighbours
+            ("fn apply(f, x) { f(x) }\nfn outer() { 1 + apply(fn g(n) { n * 2 }, 5) }\nouter()", 11),
+            ("fn twice() { [1, fn h(n) { n < 1 ? 0 : 1 + h(n - 1) }(4)] }\ntwice()[1]", 4),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::integer(expected), "{}", code);
+        }
+
+        // ...and nowhere else
+        let res = vm.interpret("x = fn named(n) { n }(2)\nnamed");
+        assert!(matches!(res, Err(VMError::RuntimeError { ref msg, .. }) if msg == "Undefined global named"), "{:?}", res);
+    }
+
+    #[test]
+    fn test_mixed_comparison_errors() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("\"hi\" < 3");
+        assert!(matches!(res, Err(VMError::RuntimeError { ref msg, .. }) if msg == "Cannot compare string and number: hi and 3"), "{:?}", res);
+        let res = vm.interpret("2.5 >= \"1\"");
+        assert!(matches!(res, Err(VMError::RuntimeError { ref msg, .. }) if msg == "Cannot compare number and string: 2.5 and 1"), "{:?}", res);
+
+        let res = vm.interpret("true < false");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::boolean(false));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret(r#""a\nb""#);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap().as_string(), "a\nb");
+
+        let res = vm.interpret(r#"len("tab\there")"#);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(8.0));
+
+        let res = vm.interpret(r#""\q""#);
+        assert!(matches!(res, Err(VMError::CompilationError { .. })));
+    }
+
+    #[test]
+    fn test_compound_assignment_globals() {
+        let cases = [
+            ("x = 1; x += 4; x", 5.0),
+            ("x = 10; x -= 4; x", 6.0),
+            ("x = 3; x *= 4; x", 12.0),
+            ("x = 12; x /= 4; x", 3.0),
+            ("x = 2; x *= 1 + 2; x", 6.0),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::number(expected), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_loops_keep_the_stack_balanced() {
+        // A loop that leaked (or over-popped) values each iteration would grow the stack past
+        // its initial capacity, or eat the locals beneath it
+        let cases = [
+            // Empty body - nothing to pop
+            ("fn f() { a = 5; i = 0; while (i += 1) < 3000 { } a } f()", 5),
+            ("fn f() { i = 0; while (i += 1) < 10 { } i } f()", 10),
+            // Several values per iteration
+            ("fn f() { a = 5; i = 0; while i < 3000 { i += 1; 7; 8 } a } f()", 5),
+            // Bodies that produce no values of their own
+            ("fn f() { i = 0; while i < 3000 { while false { } i += 1 } i } f()", 3000),
+            ("fn f() { n = 0; for (i = 0; i < 3000; i += 1) { if i % 2 == 0 { n += 1; 1; 2 } } n } f()", 1500),
+            // Leaving the body early
+            ("fn f() { i = 0; while i < 3000 { i += 1; 1; if i > 10 { 2; continue } 3 } i } f()", 3000),
+            ("fn f() { i = 0; while true { i += 1; 1; if i > 2990 { 2; break } } i } f()", 2991),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::integer(expected), "{}", code);
+            assert_eq!(vm.stack.len(), 0, "{}", code);
+            assert!(vm.stack.capacity() <= 255, "{} grew the stack to {}", code, vm.stack.capacity());
+        }
+
+        // An if without an else still yields a value on both paths
+        let mut vm = VM::new(false);
+        let res = vm.interpret("fn f(n) { if n > 1 { 5 } } f(0)");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_null());
+    }
+
+    #[test]
+    fn test_discarded_call_results_are_popped() {
+        // A call used as a statement must not leave its result behind on each iteration
+        let cases = [
+            ("fn f() { 1 }\ni = 0\nwhile i < 1000 { f(); i = i + 1 }\ni", 1000),
+            ("fn f() { 1 }\nfn g() { i = 0; while i < 1000 { f(); f(); i = i + 1 } i }\ng()", 1000),
+            ("fn f() { 1 }\nfn g() { f(); f(); 2 }\ng()", 2),
+        ];
+        // A long straight-line body would otherwise grow the stack by one value per call
+        let long_body = format!("fn f() {{ 1 }}\nfn g() {{ {} 3 }}\ng()", "f(); ".repeat(1000));
+        let cases = cases.iter().map(|(code, n)| (code.to_string(), *n)).chain([(long_body, 3)]);
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(&code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::integer(expected), "{}", code);
+            assert_eq!(vm.stack.len(), 0, "{}", code);
+            assert!(vm.stack.capacity() <= 255, "{} grew the stack to {}", code, vm.stack.capacity());
+        }
+    }
+
+    #[test]
+    fn test_profile_report() {
+        let code = "i = 0\nwhile i < 100 { i = i + 1 }\ni";
+
+        let mut vm = VM::new(false);
+        vm.set_profile(false);
+        vm.interpret(code).unwrap();
+        assert!(vm.profile_report().is_empty(), "nothing is timed unless profiling is on");
+
+        vm.set_profile(true);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(100));
+        let report = vm.profile_report();
+        let add = report.iter().find(|(opcode, _, _)| opcode == "ADD");
+        assert!(matches!(add, Some((_, _, 100))), "{:?}", report);
+        assert!(report.iter().any(|(opcode, _, count)| opcode == "RETURN" && *count == 1), "{:?}", report);
+        assert!(report.windows(2).all(|w| w[0].1 >= w[1].1), "the slowest opcodes come first");
+    }
+
+    #[test]
+    fn test_frames_survive_closure_arena_growth() {
+        // The running closures must stay usable while the arena reallocates underneath them
+        let code = "
+            fn outer() {
+              base = 40
+              grow = ^() {
+                i = 0
+                while i < 500 {
+                  f = ^() { i }
+                  i += 1
+                }
+                base + 1
+              }
+              result = grow()
+              result + 1
+            }
+            outer()
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(42));
+        assert!(vm.closure_arena.len() > 64, "the arena should have outgrown its initial capacity");
+    }
+
+    /// Runs a hand-assembled chunk as the top-level function. The chunk has no RETURN, so
+    /// whatever it leaves on the stack stays there for inspection.
+    fn run_chunk(mut vm: VM, chunk: Chunk) -> (VM, VMResult) {
+        let mut func = WeaveFn::new("test".to_string(), vec![]);
+        func.chunk = chunk;
+        let handle = vm.closure_arena.insert(FnClosure::new(Rc::new(func)));
+        vm.stack.push(NanBoxedValue::closure_handle(handle.clone()));
+        vm.call_stack.push(&vm.closure_arena, handle, 0);
+        let res = vm.run();
+        (vm, res)
+    }
+
+    #[test]
+    fn test_stack_size_limit() {
+        // A slot far beyond anything on the stack, in a VM that only allows a few values
+        let mut chunk = Chunk::new();
+        chunk.emit_constant(NanBoxedValue::integer(1), 1).unwrap();
+        chunk.write_op(Op::SetLocal, 1);
+        chunk.write_byte(250, 1);
+        let mut vm = VM::new(false);
+        vm.set_max_stack_size(16);
+        let (vm, res) = run_chunk(vm, chunk);
+        match res {
+            Err(VMError::RuntimeError { msg, .. }) => assert_eq!(msg, "Stack size limit exceeded"),
+            other => panic!("Expected a stack limit error, got {:?}", other),
+        }
+        assert!(vm.stack.len() <= 16, "the stack grew to {}", vm.stack.len());
+
+        // The same slot is fine under the default limit
+        let mut chunk = Chunk::new();
+        chunk.emit_constant(NanBoxedValue::integer(1), 1).unwrap();
+        chunk.write_op(Op::SetLocal, 1);
+        chunk.write_byte(250, 1);
+        let (vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(res.is_ok(), "Failed to run: {:?}", res.unwrap_err());
+        assert_eq!(vm.stack[250], NanBoxedValue::integer(1));
+    }
+
+    #[test]
+    fn test_local_slots_out_of_range() {
+        fn expect_stack_limit(res: VMResult) {
+            match res {
+                Err(VMError::RuntimeError { msg, .. }) => assert_eq!(msg, "Stack size limit exceeded"),
+                other => panic!("Expected a stack limit error, got {:?}", other),
+            }
+        }
+
+        // Reading a slot far past the top of a small stack
+        let mut chunk = Chunk::new();
+        chunk.write_op(Op::GetLocal, 1);
+        chunk.write_byte(255, 1);
+        let mut vm = VM::new(false);
+        vm.set_max_stack_size(16);
+        let (vm, res) = run_chunk(vm, chunk);
+        expect_stack_limit(res);
+        assert!(vm.stack.len() <= 16, "the stack grew to {}", vm.stack.len());
+
+        // A frame base so large that adding the slot would overflow
+        let mut chunk = Chunk::new();
+        chunk.write_op(Op::GetLocal, 1);
+        chunk.write_byte(200, 1);
+        let mut vm = VM::new(false);
+        let mut func = WeaveFn::new("test".to_string(), vec![]);
+        func.chunk = chunk;
+        let handle = vm.closure_arena.insert(FnClosure::new(Rc::new(func)));
+        vm.call_stack.push(&vm.closure_arena, handle, usize::MAX - 100);
+        expect_stack_limit(vm.run());
+    }
+
+    #[test]
+    fn test_dup_and_swap() {
+        let mut chunk = Chunk::new();
+        chunk.emit_constant(NanBoxedValue::integer(1), 1).unwrap();
+        chunk.emit_constant(NanBoxedValue::integer(2), 1).unwrap();
+        chunk.write_op(Op::DUP, 1);
+        chunk.write_op(Op::SWAP, 1);
+        chunk.write_op(Op::ADD, 1);
+        chunk.write_op(Op::SWAP, 1);
+        chunk.write_op(Op::DUP, 1);
+        let (vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(res.is_ok(), "Failed to run: {:?}", res.unwrap_err());
+        // [fn, 1, 2] -> DUP [fn, 1, 2, 2] -> SWAP [fn, 1, 2, 2] -> ADD [fn, 1, 4]
+        //            -> SWAP [fn, 4, 1] -> DUP [fn, 4, 1, 1]
+        let values: Vec<i64> = vm.stack[1..].iter().map(|v| v.as_int()).collect();
+        assert_eq!(values, vec![4, 1, 1]);
+
+        let mut chunk = Chunk::new();
+        chunk.emit_constant(NanBoxedValue::integer(1), 1).unwrap();
+        chunk.emit_constant(NanBoxedValue::integer(2), 1).unwrap();
+        chunk.write_op(Op::SWAP, 1);
+        chunk.write_op(Op::SUB, 1);
+        let (vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(res.is_ok(), "Failed to run: {:?}", res.unwrap_err());
+        assert_eq!(vm.stack[1..], [NanBoxedValue::integer(1)]);
+
+        // Only the function itself is on the stack, so SWAP has nothing to trade places with
+        let mut chunk = Chunk::new();
+        chunk.write_op(Op::SWAP, 1);
+        let (_vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(res.is_err(), "SWAP with one value should fail");
+    }
+
+    #[test]
+    fn test_string_indexing_and_slicing() {
+        let cases = [
+            ("\"hello\"[1]", "e"),
+            ("\"hello\"[1:3]", "el"),
+            ("\"hello\"[0:2]", "he"),
+            ("\"hello\"[3:]", "lo"),
+            ("\"hello\"[2:2]", ""),
+            // Indices count characters, not bytes
+            ("\"naïve\"[2]", "ï"),
+            ("\"naïve\"[2:4]", "ïv"),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap().as_string(), expected, "{}", code);
+        }
+
+        let res = vm.interpret("[1, 2, 3, 4][1:3]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(vm.format_value(res.unwrap()), "[2, 3]");
+
+        for code in ["\"hello\"[5]", "\"hello\"[-1]", "\"hello\"[1.5]", "\"hello\"[2:9]", "\"hello\"[3:1]", "5[0:1]"] {
+            let res = vm.interpret(code);
+            assert!(matches!(res, Err(VMError::RuntimeError { .. })), "{} should fail: {:?}", code, res);
+        }
+    }
+
+    #[test]
+    fn test_newline_separated_statements() {
+        let cases = [
+            // Each line is its own statement, no semicolons needed
+            ("x = 1\ny = 2\nx + y", "3"),
+            ("x = 5\n-3", "-3"),
+            ("x = 1\n[x, x]", "[1, 1]"),
+            ("x = 2\n(x + 1) * 2", "6"),
+            ("f = ^(n) { n }\n^(n) { n * 2 }(4)", "8"),
+            // ...but a line ending mid-expression carries on to the next
+            ("x = 1 +\n  2\nx", "3"),
+            ("x = [1,\n  2]\nx[1]", "2"),
+            ("add = ^(a, b) { a + b }\nadd(1,\n  2)", "3"),
+            ("1; 2\n3", "3"),
+            // `;` ends the whole statement, even when the expression before it is nested
+            ("x = 1; [x, 2]", "[1, 2]"),
+            ("g = 1; (g)", "1"),
+            ("x = 3; -x", "-3"),
+            ("a = [1]; a[0] = 2; a", "[2]"),
+            ("fn f() { y = 4; [y] }\nf()", "[4]"),
+            ("t = 0\nfor (i = 0; i < 3; i = i + 1) { t = t + i; }\nt", "3"),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_top_level_statements_keep_the_stack_balanced() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("1; 2; 3");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(3));
+        assert_eq!(vm.stack.len(), 0);
+
+        // Without the final RETURN we can see what the statements leave behind: only the last value
+        let source = "x = 1\ny = [x, x]\nif x > 0 { x + 1 } else { 0 }\nfn f() { 5 }\nx * 10; \"done\"";
+        let mut chunk = Compiler::new(source, false).compile().unwrap().chunk;
+        assert_eq!(chunk.code.pop(), Some(Op::RETURN.bytecode()[0]));
+        let (vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(res.is_ok(), "Failed to run: {:?}", res.unwrap_err());
+        assert_eq!(vm.stack.len(), 2, "Expected just the script function and one value: {:?}", vm.stack);
+        assert_eq!(vm.stack[1].as_string(), "done");
+    }
+
+    #[test]
+    fn test_out_of_range_jumps_are_invalid() {
+        // A loop back past the start of the chunk
+        let mut chunk = Chunk::new();
+        chunk.write_op(Op::Loop, 1);
+        chunk.write(&vec![0xFF, 0xFF], 1);
+        let (_vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(matches!(res, Err(VMError::InvalidChunk)), "{:?}", res);
+
+        // A jump forward past the end of it
+        let mut chunk = Chunk::new();
+        chunk.write_op(Op::Jump, 1);
+        chunk.write(&vec![0, 10], 1);
+        let (_vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(matches!(res, Err(VMError::InvalidChunk)), "{:?}", res);
+
+        // Jumping to exactly the end just finishes the chunk
+        let mut chunk = Chunk::new();
+        chunk.write_op(Op::Jump, 1);
+        chunk.write(&vec![0, 0], 1);
+        let (_vm, res) = run_chunk(VM::new(false), chunk);
+        assert!(res.is_ok(), "Failed to run: {:?}", res.unwrap_err());
+    }
+
+    #[test]
+    fn test_default_params() {
+        let cases = [
+            ("fn g(a, b=10){a+b} g(5)", 15),
+            ("fn g(a, b=10){a+b} g(5, 1)", 6),
+            // Defaults can build on earlier params, and only the missing ones are evaluated
+            ("fn h(a, b = a * 2, c = b + 1) { a + b + c } h(1)", 6),
+            ("fn h(a, b = a * 2, c = b + 1) { a + b + c } h(1, 5)", 12),
+            ("fn h(a, b = a * 2, c = b + 1) { a + b + c } h(1, 5, 0)", 6),
+            ("n = 0\nfn bump() { n += 1 }\nfn k(a = bump()) { a } k(7) + n", 7),
+            ("add = ^(a = 1, b = 2) { a * 10 + b }\nadd() + add(3)", 44),
+            // Locals declared in the body sit after the defaulted params
+            ("fn m(a, b = 3) { c = a + b\nc * 2 } m(1)", 8),
+            ("base = 100\nfn outer() { off = 5\n^(x, y = off) { base + x + y } } outer()(1)", 106),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::integer(expected), "{}", code);
+        }
+
+        for code in ["fn g(a, b=10){a+b} g()", "fn g(a, b=10){a+b} g(1, 2, 3)"] {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_err(), "{} should fail", code);
+            let msg = format!("{}", res.unwrap_err());
+            assert!(msg.contains("Expected 1 to 2 arguments"), "{}: {}", code, msg);
+        }
+    }
+
+    #[test]
+    fn test_ternary() {
+        let cases = [
+            ("true ? 1 : 2", 1),
+            ("false ? 1 : 2", 2),
+            ("a = false\nb = 1\nc = true\nd = 2\ne = 3\na ? b : c ? d : e", 2),
+            ("a = true\na ? 1 : false ? 2 : 3", 1),
+            ("false ? 1 : false ? 2 : 3", 3),
+            // The condition binds looser than comparison and logic operators
+            ("x = 3 > 2 && 1 > 2 ? 10 : 20\nx", 20),
+            ("true ? false ? 1 : 2 : 3", 2),
+            ("fn max(a, b) { a > b ? a : b } max(4, 9) + max(7, 2)", 16),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::integer(expected), "{}", code);
+            // The untaken branch must not leave anything behind
+            assert_eq!(vm.stack.len(), 0, "{}", code);
+        }
+
+        let mut vm = VM::new(false);
+        assert!(vm.interpret("true ? 1").is_err(), "a missing ':' should fail to compile");
+    }
+
+    #[test]
+    fn test_calling_a_non_function() {
+        let cases = [
+            ("5()", "Cannot call number '5' — only functions are callable"),
+            ("\"x\"()", "Cannot call string 'x' — only functions are callable"),
+            ("a = [1, 2]\na(0)", "Cannot call array '[1, 2]' — only functions are callable"),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            match vm.interpret(code) {
+                Err(VMError::RuntimeError { msg, .. }) => assert_eq!(msg, expected, "{}", code),
+                other => panic!("{} should fail to call, got {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_functions_compare_by_identity() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("f = ^(){1}\nfn g() { 2 }\nh = f");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+
+        let cases = [
+            ("f == f", true),
+            ("^(){1} == ^(){1}", false),
+            ("h == f", true),
+            ("g == g", true),
+            ("f == g", false),
+            ("len == len", true),
+            ("len == f", false),
+            ("f == 1", false),
+            ("f == null", false),
+            ("f != ^(){1}", true),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_maps() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("m = {\"a\": 1}\nm[\"a\"]");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(1));
+
+        let cases = [
+            ("m[\"b\"] = 2", "2"),
+            ("m[\"b\"]", "2"),
+            ("m[\"a\"] = \"one\"; m", "{a: one, b: 2}"),
+            ("m[\"missing\"]", "null"),
+            ("len(m)", "2"),
+            ("type(m)", "map"),
+            ("{}", "{}"),
+            ("{\"nested\": {\"k\": [1, 2]}, \"a\" + \"b\": 3}", "{ab: 3, nested: {k: [1, 2]}}"),
+            ("k = \"key\"\nfn f() {\n  {\n    k: 1,\n  }\n}\nf()[\"key\"]", "1"),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(vm.format_value(res.unwrap()), expected, "{}", code);
+        }
+
+        // Maps are shared, not copied
+        let res = vm.interpret("alias = m; alias[\"c\"] = 3; m[\"c\"]");
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(3));
+
+        for code in ["m[1]", "m[null] = 1", "{1: 2}", "{\"a\" 1}"] {
+            assert!(vm.interpret(code).is_err(), "{} should fail", code);
+        }
+    }
+
+    #[test]
+    fn test_gc_collects_maps() {
+        let mut vm = VM::new(false);
+        vm.interpret("keep = {\"inner\": [\"a\" + \"b\"]}\ndrop = {\"x\": 1}").unwrap();
+        assert_eq!(vm.memory_report().maps.live, 2);
+
+        vm.interpret("drop = null").unwrap();
+        vm.collect_garbage();
+        assert_eq!(vm.memory_report().maps.live, 1);
+        assert_eq!(vm.format_value(vm.globals["keep"]), "{inner: [ab]}");
+    }
+
+    #[test]
+    fn test_memory_report() {
+        let mut vm = VM::new(false);
+        let before = vm.memory_report();
+        assert_eq!(before.closures.live, 0);
+        assert_eq!(before.arrays.live, 0);
+
+        let res = vm.interpret("fn make(n) { ^() { n } }\nkeep = make(1)\nlist = [1, 2, 3]\nkeep()");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let after = vm.memory_report();
+        assert_eq!(after.globals, before.globals + 3);
+        assert_eq!(after.arrays.live, 1);
+        assert!(after.closures.live >= 2, "make and keep should both be live: {}", after.closures);
+        assert_eq!(after.closures.live + after.closures.freed_slots, after.closures.total_slots);
+        assert!(after.upvalues.live >= 1, "keep captures n: {}", after.upvalues);
+
+        // Dropping the only reference lets a collection free the array and trim its slot
+        vm.interpret("list = 0").unwrap();
+        vm.collect_garbage();
+        let collected = vm.memory_report();
+        assert_eq!(collected.arrays.live, 0);
+        assert_eq!(collected.arrays.total_slots, 0);
+        assert!(format!("{}", collected).contains("arrays:   0 live, 0 freed, 0 slots"));
+    }
+
+    #[test]
+    fn test_nan_stays_a_number() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("0.0 / 0.0");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let nan = res.unwrap();
+        assert!(nan.is_number());
+        assert!(nan.as_number().is_nan());
+        assert!(!nan.is_null() && !nan.is_boolean() && !nan.is_pointer());
+
+        for (code, expected) in [("type(0.0 / 0.0)", "number"), ("x = 0.0 / 0.0\nx == x", "false")] {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(format!("{}", res.unwrap()), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_comparisons_with_nan() {
+        let cases = [
+            ("3 >= 3", true),
+            ("3 <= 3", true),
+            ("2 >= 3", false),
+            ("4 <= 3", false),
+            ("(0.0 / 0.0) >= 1", false),
+            ("(0.0 / 0.0) <= 1", false),
+            ("1 >= (0.0 / 0.0)", false),
+        ];
+        let mut vm = VM::new(false);
+        for (code, expected) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_null_literal() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("null");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_null());
+
+        let cases = [
+            ("null == null", true),
+            ("x = null\nx == null", true),
+            ("null == 0", false),
+            ("null == false", false),
+            ("fn f() { return null } f() == null", true),
+            ("!null", true),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::boolean(expected), "{}", code);
+        }
+
+        let mut vm = VM::new(false);
+        assert!(vm.interpret("null = 1").is_err(), "null is a keyword, not a variable");
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let cases = [
+            ("6 & 3", 2),
+            ("6 | 3", 7),
+            ("5 ^ 1", 4),
+            ("1 << 4", 16),
+            ("256 >> 4", 16),
+            ("-8 >> 1", -4),
+            ("8.0 & 12", 8),
+            // Shifts bind tighter than &, which binds tighter than ^ and |
+            ("1 | 2 ^ 3 & 1 << 1", 1),
+            ("1 << 2 + 1", 8),
+        ];
+        for (code, expected) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            let value = res.unwrap();
+            assert!(value.is_int(), "{} should be an integer", code);
+            assert_eq!(value.as_int(), expected, "{}", code);
+        }
+
+        for code in ["1.5 & 1", "\"a\" | 1", "1 << 64", "1 >> -1"] {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            assert!(matches!(res, Err(VMError::RuntimeError { .. })), "{} should fail", code);
+        }
+
+        // ^ still introduces a lambda in prefix position...
+        let mut vm = VM::new(false);
+        let res = vm.interpret("f = ^(a) { a ^ 3 }; f(1)");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(2));
+
+        // ...and at the start of a line, rather than xor-ing with the line above
+        let code = "
+            fn counter() {
+              count = 0
+              ^() { count += 1; count }
             }
+            c = counter(); c(); c()
+        ";
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(2));
+    }
 
-            #[cfg(feature = "vm-profiling")]
-            {
-                let elapsed = start_time.elapsed().as_nanos() as u64;
-                let opcode_name = format!("{:?}", op);
-                let entry = opcode_times.entry(opcode_name).or_insert((0, 0));
-                entry.0 += elapsed;
-                entry.1 += 1;
+    #[test]
+    fn test_const_bindings() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("const RATE = 2.5; RATE * 2");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(5.0));
+
+        let code = "
+            fn scale(x) {
+              const factor = 3
+              ^(y) { y * factor }(x)
             }
+            scale(4)
+        ";
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(12));
 
-            self.debug(&format!("  - {:?}", self.stack));
-            self.debug(&format!("  - {:?}", self.call_stack.constants()));
-        }
+        let res = vm.interpret("const LIMIT = 1; LIMIT = 2");
+        assert!(matches!(res, Err(VMError::CompilationError { .. })));
 
-        #[cfg(feature = "vm-profiling")]
-        {
-            eprintln!("VM execution completed. Opcodes tracked: {}", opcode_times.len());
-            if !opcode_times.is_empty() {
-                eprintln!("Opcode Performance Profile:");
-                let mut sorted_opcodes: Vec<_> = opcode_times.iter().collect();
-                sorted_opcodes.sort_by(|a, b| b.1.0.cmp(&a.1.0)); // Sort by total time desc
-                for (opcode, (total_ns, count)) in sorted_opcodes.iter().take(10) {
-                    let avg_ns = *total_ns / *count;
-                    eprintln!("  {:15} {:8} calls, {:10} ns total, {:6} ns avg", 
-                             opcode, count, total_ns, avg_ns);
-                }
-                eprintln!();
-            } else {
-                eprintln!("No opcodes were executed!");
+        // Constants stay constant in later scripts run on the same VM
+        let res = vm.interpret("RATE = 1");
+        assert!(matches!(res, Err(VMError::CompilationError { .. })), "Reassigned a constant from an earlier script");
+        let res = vm.interpret("const RATE = 1");
+        assert!(matches!(res, Err(VMError::CompilationError { .. })), "Redeclared a constant from an earlier script");
+        assert_eq!(vm.interpret("RATE").unwrap(), NanBoxedValue::number(2.5));
+    }
+
+    #[test]
+    fn test_compound_assignment_locals_and_upvalues() {
+        let code = "
+            fn f() {
+              total = 1
+              total += 2
+              total *= 5
+              total -= 3
+              total /= 2
+              total
             }
-            
-            // Memory usage analysis
-            if !memory_samples.is_empty() {
-                eprintln!("Memory Usage Analysis:");
-                eprintln!("  Total samples: {}", memory_samples.len());
-                
-                if memory_samples.len() > 1 {
-                    let first = &memory_samples[0];
-                    let last = &memory_samples[memory_samples.len() - 1];
-                    
-                    eprintln!("  Growth from iteration {} to {}:", first.0, last.0);
-                    eprintln!("    Stack:      {} -> {} (+{})", first.1, last.1, last.1 as i32 - first.1 as i32);
-                    eprintln!("    Upvalues:   {} -> {} (+{})", first.2, last.2, last.2 as i32 - first.2 as i32);
-                    eprintln!("    Arena:      {} -> {} (+{})", first.3, last.3, last.3 as i32 - first.3 as i32);
-                    eprintln!("    Globals:    {} -> {} (+{})", first.4, last.4, last.4 as i32 - first.4 as i32);
-                    eprintln!("    Frames:     {} -> {} (+{})", first.5, last.5, last.5 as i32 - first.5 as i32);
-                    
-                    // Find the component with the highest growth
-                    let stack_growth = last.1 as i32 - first.1 as i32;
-                    let upvalues_growth = last.2 as i32 - first.2 as i32;
-                    let arena_growth = last.3 as i32 - first.3 as i32;
-                    let globals_growth = last.4 as i32 - first.4 as i32;
-                    let frames_growth = last.5 as i32 - first.5 as i32;
-                    
-                    let growths = [stack_growth, upvalues_growth, arena_growth, globals_growth, frames_growth];
-                    let max_growth = *growths.iter().max().unwrap();
-                    
-                    if max_growth > 0 {
-                        eprintln!("  Largest growth component: {}", 
-                            if stack_growth == max_growth { "Stack" }
-                            else if upvalues_growth == max_growth { "Upvalues" }
-                            else if arena_growth == max_growth { "Arena" }
-                            else if globals_growth == max_growth { "Globals" }
-                            else { "Frames" }
-                        );
-                    }
-                }
-                eprintln!();
+            f()
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(6.0));
+
+        let code = "
+            fn make_counter() {
+              count = 0
+              fn counter() {
+                count += 2
+                count
+              }
+              counter
             }
-        }
+            c = make_counter()
+            c()
+            c()
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(4.0));
+    }
 
-        // Return the top value on the stack as the result
-        Ok(self.stack.last().copied().unwrap_or(NanBoxedValue::null()))
+    #[test]
+    fn test_integer_division() {
+        // Integer division floors; any float operand makes the result a float
+        let mut vm = VM::new(false);
+        let res = vm.interpret("5 / 2");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(value.is_int());
+        assert_eq!(value.as_int(), 2);
+
+        let res = vm.interpret("-5 / 2");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap().as_int(), -3);
+
+        let res = vm.interpret("5.0 / 2.0");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(value.is_float());
+        assert_eq!(value.as_number(), 2.5);
+
+        let res = vm.interpret("5 / 2.0");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_float());
     }
 
-    fn debug(&self, msg: &str) {
-        #[cfg(debug_assertions)]
-        log_debug!("VM debug", message = msg, stack_depth = self.stack.len());
+    #[test]
+    fn test_integer_arithmetic_stays_integral() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("x = 3 * 4 - 2 + 7 % 3; x");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(value.is_int());
+        assert_eq!(value.as_int(), 11);
+        assert_eq!(vm.format_value(value), "11");
+
+        let res = vm.interpret("1.5 + 1.5");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_float());
     }
 
-    fn runtime_error(&mut self, line: usize, msg: &String) {
-        let callstack = self.call_stack.frames.iter().rev();
-        for frame in callstack {
-            let closure = unsafe { &*frame.closure };
-            let func = &closure.func;
-            let line = func.chunk.line_number_at(frame.ip.idx(-1));
-            
-            log_error!("Runtime error in function", 
-                line = line, 
-                function = func.name.as_str(), 
-                message = msg.as_str(),
-                code = func.chunk.line_str(frame.ip.idx(0)).as_str()
-            );
+    #[test]
+    fn test_integer_overflow_becomes_a_float() {
+        let mut vm = VM::new(false);
+        let max = (1i64 << 47) - 1;
+        let cases = [
+            (format!("{} * {}", max, max), max as f64 * max as f64),
+            (format!("{} + 1", max), (max + 1) as f64),
+            (format!("-{} - 2", max), (-max - 2) as f64),
+            (format!("x = -{} - 1\n-x", max), (max + 1) as f64),
+            (format!("{} * {}", i64::MAX, i64::MAX), i64::MAX as f64 * i64::MAX as f64),
+        ];
+        for (code, expected) in cases {
+            let res = vm.interpret(&code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            let value = res.unwrap();
+            assert!(value.is_float(), "{} should be a float", code);
+            assert_eq!(value.as_number(), expected, "{}", code);
         }
 
-        self.reset_stack();
+        let res = vm.interpret(&format!("{} * 1 - 0", max));
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_int());
     }
 
-    fn define_native(&mut self, func: Rc<NativeFn>) {
-        let name = func.name.to_string();
-        let nan_boxed_func = NanBoxedValue::pointer(Box::into_raw(Box::new(func)) as *const (), PointerTag::NativeFn);
-        self.globals.insert(name, nan_boxed_func);
+    #[test]
+    fn test_script_level_return() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("x = 40; return x + 2; x = 0");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(42.0));
+
+        let res = vm.interpret("1 + 1\nreturn");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert!(res.unwrap().is_null());
+
+        let res = vm.interpret("if (true) { return 7 }\n 8");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(7.0));
     }
 
-    fn reset_stack(&mut self) {
-        self.stack.clear();
-        self.call_stack.reset();
+    /// A writer that tests can read back after handing a clone to the VM
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
     }
-    
-    
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    impl SharedOutput {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
 
     #[test]
-    fn test_basic_math() {
-        let mut vm = VM::new();
-        let res = vm.interpret("5 + 2 * 3");
+    fn test_puts_multiple_values() {
+        let output = SharedOutput::default();
+        let mut vm = VM::new(false);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_color_output(true);
+
+        let res = vm.interpret("puts 1, \"two\", [3]");
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
-        let result = res.unwrap();
-        assert!(result.is_number());
-        assert_eq!(result.as_number(), 11.0);
+        // The statement evaluates to the last printed value
+        assert_eq!(vm.format_value(res.unwrap()), "[3]");
+
+        let res = vm.interpret("puts 4");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::number(4.0));
+
+        let expected = format!("{}\n{}\n", green("1 two [3]"), green("4"));
+        assert_eq!(output.contents(), expected);
     }
 
     #[test]
-    fn test_parenthesis() {
-        let mut vm = VM::new();
-        let res = vm.interpret("(5 + 2) * 3");
+    fn test_uncolored_output() {
+        let output = SharedOutput::default();
+        let mut vm = VM::new(false);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_color_output(false);
+
+        let res = vm.interpret("puts 1\nputs \"a\", \"b\"\necho 2");
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
-        assert_eq!(res.unwrap(), NanBoxedValue::number(21.0));
+        assert!(!output.contents().contains('\x1b'), "{:?}", output.contents());
+        assert_eq!(output.contents(), "1\na b\n2");
+    }
+
+    #[test]
+    fn test_echo_has_no_newline() {
+        let output = SharedOutput::default();
+        let mut vm = VM::new(false);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_color_output(false);
+
+        let res = vm.interpret("echo \"a\"\necho 1, 2\necho 3\necho 4\necho \"!\"");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        // Like puts, echo evaluates to the last value echoed
+        assert_eq!(res.unwrap().as_string(), "!");
+        assert_eq!(output.contents(), "a1 234!");
+    }
+
+    #[test]
+    fn test_runtime_error_backtrace() {
+        let code = "
+            fn inner() {
+              missing_global
+            }
+            fn middle() {
+              inner()
+            }
+            fn outer() {
+              middle()
+            }
+            outer()
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        let Err(err @ VMError::RuntimeError { .. }) = res else {
+            panic!("Expected a runtime error, got {:?}", res);
+        };
+        let VMError::RuntimeError { msg, backtrace, .. } = &err else { unreachable!() };
+        assert_eq!(msg, "Undefined global missing_global");
+
+        let names: Vec<&str> = backtrace.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["inner", "middle", "outer", ""]);
+        assert_eq!(backtrace[0].1, 3);
+
+        let printed = err.to_string();
+        assert!(printed.contains("at inner (line 3)"), "{}", printed);
+        assert!(printed.contains("at outer"), "{}", printed);
+        assert!(printed.ends_with("at <script> (line 11)"), "{}", printed);
+    }
+
+    #[test]
+    fn test_error_json() {
+        let mut vm = VM::new(false);
+        let err = vm.interpret("fn f() {\n  missing\n}\nf()").unwrap_err();
+        let json = err.to_json();
+        assert_eq!(json["kind"], "runtime");
+        assert_eq!(json["line"], 2);
+        assert_eq!(json["message"], "Undefined global missing");
+        assert_eq!(json["backtrace"][0], serde_json::json!({ "function": "f", "line": 2 }));
+        assert_eq!(json["backtrace"][1], serde_json::json!({ "function": "<script>", "line": 4 }));
+
+        assert!(json["column"].is_null());
+
+        let json = vm.interpret("x = 1\ny = )").unwrap_err().to_json();
+        assert_eq!(json["kind"], "compile");
+        assert_eq!(json["line"], 2);
+        assert_eq!(json["column"], 5);
+        assert!(json["message"].as_str().unwrap().starts_with("Expected prefix expression"), "{}", json);
+    }
+
+    #[test]
+    fn test_native_errors_report_the_call_line() {
+        let cases = [
+            ("x = 1\n\nread_file(\"/no/such/file\")", 3, vec![3]),
+            ("fn check(n) {\n  assert(n > 1, \"too small\")\n}\n\ncheck(0)", 2, vec![2, 5]),
+        ];
+        for (code, expected_line, backtrace_lines) in cases {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(code);
+            let Err(VMError::RuntimeError { line, backtrace, .. }) = res else {
+                panic!("Expected a runtime error from {}, got {:?}", code, res);
+            };
+            assert_eq!(line, expected_line, "{}", code);
+            let lines: Vec<usize> = backtrace.iter().map(|(_, line)| *line).collect();
+            assert_eq!(lines, backtrace_lines, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_exit_stops_the_script() {
+        let output = SharedOutput::default();
+        let mut vm = VM::new(false);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_color_output(false);
+
+        let res = vm.interpret("echo \"bye\"\nfn quit() { exit(3) }\nquit()\necho \"unreachable\"");
+        assert!(matches!(res, Err(VMError::Exit(3))), "{:?}", res);
+        assert_eq!(res.unwrap_err().exit_code(), 3);
+        assert_eq!(output.contents(), "bye");
+
+        // Exiting from a callback stops the native that called it too
+        let res = vm.interpret("map([1, 2], ^(n) { exit(n) })");
+        assert!(matches!(res, Err(VMError::Exit(1))), "{:?}", res);
+
+        // The VM is left ready for more code
+        assert_eq!(vm.interpret("1 + 1").unwrap(), NanBoxedValue::integer(2));
+    }
+
+    #[test]
+    fn test_panic() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("fn fail() {\n  panic(\"boom\")\n}\nfail()\n1");
+        let Err(VMError::RuntimeError { line, msg, .. }) = res else {
+            panic!("Expected a runtime error, got {:?}", res);
+        };
+        assert_eq!(msg, "boom");
+        assert_eq!(line, 2);
+
+        // The VM is still usable afterwards
+        assert_eq!(vm.interpret("1 + 1").unwrap(), NanBoxedValue::integer(2));
+        let res = vm.interpret("panic([1, 2])");
+        assert!(matches!(res, Err(VMError::RuntimeError { msg, .. }) if msg == "[1, 2]"));
     }
 
     #[test]
     fn test_negate() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("-5");
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::number(-5.0));
     }
 
+    #[test]
+    fn test_negate_preserves_number_type() {
+        // (code, value, whether it's an integer)
+        let cases = [("-5", -5.0, true), ("-5.0", -5.0, false), ("-(-5)", 5.0, true), ("-2.5", -2.5, false), ("x = 7\n-x", -7.0, true)];
+        let mut vm = VM::new(false);
+        for (code, expected, is_int) in cases {
+            let res = vm.interpret(code);
+            assert!(res.is_ok(), "Failed to interpret {}: {:?}", code, res.unwrap_err());
+            let value = res.unwrap();
+            assert_eq!(value.as_number(), expected, "{}", code);
+            assert_eq!(value.is_int(), is_int, "{}", code);
+        }
+
+        // The smallest integer has no integer negation, so it becomes a float instead
+        let res = vm.interpret("min = -140737488355327 - 1; min");
+        assert!(res.unwrap().is_int());
+        let res = vm.interpret("-min");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        let value = res.unwrap();
+        assert!(!value.is_int());
+        assert_eq!(value.as_number(), 140737488355328.0);
+    }
+
     #[test]
     fn test_string_literal() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("\"hello\"");
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         // String values are stored as pointers in NanBoxedValue
@@ -1013,7 +3693,7 @@ mod tests {
     
     #[test]
     fn test_var_addition() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("x = 5\nx + 2");
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(7.0));
@@ -1021,7 +3701,7 @@ mod tests {
 
     #[test]
     fn test_puts_statement() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("puts \"hello\";");
         assert_eq!(vm.stack.len(), 0);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
@@ -1029,7 +3709,7 @@ mod tests {
 
     #[test]
     fn test_using_var() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("x = 5; puts x;");
         assert_eq!(vm.stack.len(), 0);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
@@ -1037,7 +3717,7 @@ mod tests {
 
     #[test]
     fn test_declaring_var() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("x = 5");
         assert_eq!(vm.stack.len(), 0);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
@@ -1051,14 +3731,14 @@ mod tests {
     
     #[test]
     fn test_invalid_assignment_doesnt_parse() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("a= 1; a + b = 5");
         assert!(res.is_err());
     }
 
     #[test]
     fn test_shadowing_self() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("
         a = 1;  # Global var
         fn foo() {
@@ -1074,14 +3754,14 @@ mod tests {
 
     #[test]
     fn test_bad_initializer() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("a = a");
         assert!(res.is_err());
     }
 
     #[test]
     fn test_local_variables() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("fn test() { x = 1; x + 3 } test()");
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(4.0));
@@ -1089,7 +3769,7 @@ mod tests {
 
     #[test]
     fn test_nested_scopes() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         // Note: Updated to use functions instead of bare blocks 
         // This test now verifies closure variable capture instead of nested blocks
         let res = vm.interpret("fn outer() { x = 2; fn inner() { x = x + 3; x } inner() } outer()");
@@ -1099,7 +3779,7 @@ mod tests {
 
     #[test]
     fn test_if_true_condition() {
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret("fn test() {
         a = 1;
         if (true) { a = a + 1 }
@@ -1115,7 +3795,7 @@ mod tests {
         if false { a = a + 1 }
         a
         } test()";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(1.0));
@@ -1132,7 +3812,7 @@ mod tests {
         }
         a
         } test()";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(3.0));
@@ -1148,11 +3828,30 @@ mod tests {
                 puts 2
             }
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
     }
     
+    #[test]
+    fn test_else_if_chain() {
+        let code = "fn classify(n) {
+            if n < 0 {
+                -1
+            } else if n == 0 {
+                0
+            } else {
+                1
+            }
+        }";
+        for (arg, expected) in [("-5", -1.0), ("0", 0.0), ("7", 1.0)] {
+            let mut vm = VM::new(false);
+            let res = vm.interpret(&format!("{} classify({})", code, arg));
+            assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+            assert_eq!(res.unwrap(), NanBoxedValue::from(expected), "classify({})", arg);
+        }
+    }
+
     #[test]
     fn test_while_syntax() {
         let code = "fn test() {
@@ -1162,12 +3861,127 @@ mod tests {
             }
             a
         } test()";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(3.0));
     }
     
+    #[test]
+    fn test_while_accumulates_locals() {
+        let code = "fn t() { s = 0; i = 0; while i < 5 { s = s + i; i = i + 1 } s } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(10.0));
+    }
+
+    #[test]
+    fn test_while_at_start_of_script() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("while false { 1 } 2");
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(2.0));
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let code = "fn t(){ s=0; for(i=0;i<5;i=i+1){ s=s+i } s } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(10.0));
+    }
+
+    #[test]
+    fn test_for_loop_globals() {
+        let code = "
+            s = 0
+            for (i = 0; i < 4; i = i + 1) {
+                s = s + i
+            }
+            s
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(6.0));
+    }
+
+    #[test]
+    fn test_for_loop_empty_clauses() {
+        let code = "fn t() {
+            i = 0
+            for (; i < 3;) { i = i + 1 }
+            i
+        } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(3.0));
+    }
+
+    #[test]
+    fn test_while_break() {
+        let code = "fn t() {
+            i = 0
+            while true {
+                i = i + 1
+                if i == 3 { break }
+            }
+            i
+        } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(3.0));
+    }
+
+    #[test]
+    fn test_while_continue() {
+        let code = "fn t() {
+            i = 0
+            s = 0
+            while i < 6 {
+                i = i + 1
+                if i % 2 == 0 { continue }
+                s = s + i
+            }
+            s
+        } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(9.0));
+    }
+
+    #[test]
+    fn test_for_break_and_continue() {
+        let code = "fn t() {
+            s = 0
+            for (i = 0; ; i = i + 1) {
+                if i == 5 { break }
+                if i % 2 == 0 { continue }
+                s = s + i
+            }
+            s
+        } t()";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(4.0));
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let mut vm = VM::new(false);
+        let res = vm.interpret("break");
+        assert!(matches!(res, Err(VMError::CompilationError { .. })));
+
+        let res = vm.interpret("continue");
+        assert!(matches!(res, Err(VMError::CompilationError { .. })));
+    }
+
     #[test]
     fn test_fn_definition() {
         let code = "
@@ -1176,7 +3990,7 @@ mod tests {
             }
             add(-1, 4)
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(3.0));
@@ -1196,7 +4010,7 @@ mod tests {
             c = make_counter()
             c()
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(1.0));
@@ -1220,19 +4034,89 @@ mod tests {
             }
             outer()
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(10.0));
     }
 
+    #[test]
+    fn test_closures_share_captured_variable() {
+        let code = "
+            fn make_pair() {
+              count = 0
+              fn inc() {
+                count = count + 1
+                count
+              }
+              fn get() {
+                count
+              }
+              inc()
+              inc();
+              [inc, get]
+            }
+            pair = make_pair()
+            pair[0]()
+            pair[1]()
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::from(3.0));
+    }
+
+    #[test]
+    fn test_closing_upvalues_skips_closed_ones() {
+        let code = "
+            fn make_adder(x) { ^(y) { x + y } }
+            adders = {}
+            i = 0
+            while i < 500 {
+              adders[str(i)] = make_adder(i)
+              i = i + 1
+            }
+            adders[\"7\"](1)
+        ";
+        let mut vm = VM::new(false);
+        let res = vm.interpret(code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(8));
+        assert!(vm.upvalue_arena.stats().live >= 500);
+        assert!(vm.open_upvalues.is_empty());
+
+        // Another return only looks at its own upvalue, however many were closed before
+        vm.upvalues_visited = 0;
+        let res = vm.interpret("make_adder(1)(2)");
+        assert_eq!(res.unwrap(), NanBoxedValue::integer(3));
+        assert!(vm.upvalues_visited <= 2, "visited {} upvalues", vm.upvalues_visited);
+    }
+
+    #[test]
+    fn test_one_and_two_byte_constants() {
+        // Enough distinct literals that later ones need the two-byte CONSTANT form
+        let mut code = "x = 0\n".to_string();
+        for n in 1000..1300 {
+            code.push_str(&format!("x = x + {}\n", n));
+        }
+        code.push('x');
+        let chunk = Compiler::new(&code, false).compile().unwrap().chunk;
+        let listing = chunk.disassemble_to_string("script");
+        assert!(listing.contains("ConstantByte") && listing.contains("CONSTANT"), "{}", listing);
+
+        let mut vm = VM::new(false);
+        let res = vm.interpret(&code);
+        assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
+        assert_eq!(res.unwrap(), NanBoxedValue::integer((1000..1300).sum()));
+    }
+
     #[test]
     fn test_basic_lambda() {
         let code = "
             add = ^(a, b) { a + b }
             add(3, 4)
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(7.0));
@@ -1247,7 +4131,7 @@ mod tests {
             add5 = make_adder(5)
             add5(10)
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(15.0));
@@ -1259,7 +4143,7 @@ mod tests {
             getValue = ^() { 42 }
             getValue()
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(42.0));
@@ -1271,7 +4155,7 @@ mod tests {
             square = ^(x) { x * x }
             square(6)
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(36.0));
@@ -1288,7 +4172,7 @@ mod tests {
             
             result1 + result2
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(32.0)); // 7 + 25 = 32
@@ -1301,7 +4185,7 @@ mod tests {
             mul = ^(x, y) { x * y }
             add(3, 4) + mul(5, 6)
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(37.0)); // 7 + 30 = 37
@@ -1317,7 +4201,7 @@ mod tests {
             msg = getMessage()
             result
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(7.0));
@@ -1334,7 +4218,7 @@ mod tests {
             result2 = lambda2(temp)
             result1 + result2
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(16.0)); // 6 + 10 = 16
@@ -1351,7 +4235,7 @@ mod tests {
             result2 = func2(temp)
             result1 + result2
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(16.0)); // 6 + 10 = 16
@@ -1365,7 +4249,7 @@ mod tests {
             
             add(mul(2, 3), mul(4, 5))
         ";
-        let mut vm = VM::new();
+        let mut vm = VM::new(false);
         let res = vm.interpret(code);
         assert!(res.is_ok(), "Failed to interpret: {:?}", res.unwrap_err());
         assert_eq!(res.unwrap(), NanBoxedValue::from(26.0)); // add(6, 20) = 26
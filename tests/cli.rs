@@ -0,0 +1,218 @@
+use std::process::Command;
+
+fn weaver(args: &[&str], dir: &std::path::Path) -> std::process::Output {
+    // Run from a scratch directory so the interpreter's log files stay out of the repo
+    Command::new(env!("CARGO_BIN_EXE_weaver"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("failed to run weaver")
+}
+
+#[test]
+fn dump_bytecode_prints_the_listing_without_running() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "fn double(n) { n * 2 }\nputs double(21)\n").unwrap();
+
+    let output = weaver(&["--dump-bytecode", script.to_str().unwrap()], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("ConstantByte"), "{}", stdout);
+    assert!(stdout.contains("RETURN"), "{}", stdout);
+    // Nested functions are listed too
+    assert!(stdout.contains("== <fn double(1)> =="), "{}", stdout);
+    assert!(stdout.contains("MUL"), "{}", stdout);
+    // ...but nothing is executed
+    assert!(!stdout.contains("42"), "{}", stdout);
+}
+
+#[test]
+fn dump_bytecode_reports_compile_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("broken.wv");
+    std::fs::write(&script, "1 +").unwrap();
+
+    let output = weaver(&["--dump-bytecode", script.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Error compiling"));
+}
+
+#[test]
+fn check_compiles_without_running() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "puts \"ran\"\nexit(3)\n").unwrap();
+
+    let output = weaver(&["--check", script.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+
+    let broken = dir.path().join("broken.wv");
+    std::fs::write(&broken, "x = 1\nputs (x +\n").unwrap();
+    let output = weaver(&["--check", broken.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(70));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error compiling"), "{}", stderr);
+
+    let output = weaver(&["--check", "-e", "1 +"], dir.path());
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn echo_output_has_no_newlines_or_color_with_no_color() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("echo.wv");
+    std::fs::write(&script, "echo \"a\"\necho \"b\", 1\necho 2\n").unwrap();
+
+    let output = weaver(&["--no-color", script.to_str().unwrap()], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "ab 12");
+}
+
+#[test]
+fn compile_writes_bytecode_that_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "x = 20\nputs \"answer\", x * 2 + 2\n").unwrap();
+
+    let output = weaver(&["--compile", script.to_str().unwrap()], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    // Compiling doesn't run anything
+    assert!(output.stdout.is_empty(), "{:?}", output);
+
+    let compiled = dir.path().join("script.weavec");
+    assert!(compiled.exists());
+    let output = weaver(&["--no-color", compiled.to_str().unwrap()], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "answer 42\n");
+}
+
+#[test]
+fn malformed_bytecode_is_rejected_before_running() {
+    let dir = tempfile::tempdir().unwrap();
+    let compiled = dir.path().join("bad.weavec");
+    // GetGlobalByIndex naming constant 0, which is the integer 7 rather than a string
+    let mut bytes = b"WVBC\x03".to_vec();
+    bytes.extend_from_slice(&4u32.to_be_bytes());
+    bytes.extend_from_slice(&[47, 0, 0, 0]);
+    bytes.extend_from_slice(&0u32.to_be_bytes());
+    bytes.extend_from_slice(&1u32.to_be_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(&weaver::NanBoxedValue::integer(7).bits().to_be_bytes());
+    std::fs::write(&compiled, bytes).unwrap();
+
+    let output = weaver(&["--no-color", compiled.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(60), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Global name at 0000 is not a string"), "{}", stderr);
+
+    // --check verifies bytecode rather than reading it as source
+    let output = weaver(&["--check", compiled.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(60), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Global name at 0000 is not a string"), "{}", stderr);
+}
+
+#[test]
+fn check_accepts_compiled_bytecode() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "puts \"ran\"\n").unwrap();
+    let output = weaver(&["--compile", script.to_str().unwrap()], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = weaver(&["--check", dir.path().join("script.weavec").to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+
+    // Anything that isn't text is reported, not a panic
+    let binary = dir.path().join("binary.wv");
+    std::fs::write(&binary, [0xff, 0xfe, 0x00]).unwrap();
+    let output = weaver(&["--check", binary.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(66), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Error reading"), "{}", stderr);
+}
+
+#[test]
+fn profile_prints_opcode_timings_to_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "i = 0\nwhile i < 10 { i = i + 1 }\necho i\n").unwrap();
+
+    let output = weaver(&["--no-color", "--profile", script.to_str().unwrap()], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "10");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Opcode Performance Profile"), "{}", stderr);
+    assert!(stderr.contains("ADD"), "{}", stderr);
+}
+
+#[test]
+fn arguments_after_double_dash_reach_the_script() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "echo len(args()), args()[0], args()[1]\n").unwrap();
+
+    let output = weaver(&["--no-color", script.to_str().unwrap(), "--", "one", "--two"], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2 one --two");
+}
+
+#[test]
+fn json_error_format_prints_parseable_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "x = 1\n\nmissing\n").unwrap();
+
+    let output = weaver(&["--no-color", "--error-format", "json", script.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(80));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let json: serde_json::Value = serde_json::from_str(stderr.trim()).expect(&stderr);
+    assert_eq!(json["kind"], "runtime");
+    assert_eq!(json["line"], 3);
+    assert_eq!(json["message"], "Undefined global missing");
+    assert_eq!(json["file"], script.to_str().unwrap());
+}
+
+#[test]
+fn json_compile_errors_have_a_line_and_column() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "x = 1\ny = )\n").unwrap();
+
+    let output = weaver(&["--check", "--error-format", "json", script.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(70));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let json: serde_json::Value = serde_json::from_str(stderr.trim()).expect(&stderr);
+    assert_eq!(json["kind"], "compile");
+    assert_eq!(json["line"], 2);
+    assert_eq!(json["column"], 5);
+}
+
+#[test]
+fn eval_runs_code_from_the_command_line() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = weaver(&["--no-color", "-e", "puts 2+3"], dir.path());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "5\n");
+
+    let output = weaver(&["--eval", "missing"], dir.path());
+    assert_eq!(output.status.code(), Some(80));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error executing <eval>"), "{}", stderr);
+}
+
+#[test]
+fn exit_ends_the_script_with_its_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script.wv");
+    std::fs::write(&script, "echo \"bye\"\nexit(3)\nputs \"unreachable\"\n").unwrap();
+
+    let output = weaver(&["--no-color", script.to_str().unwrap()], dir.path());
+    assert_eq!(output.status.code(), Some(3));
+    // Output written before exiting isn't lost
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "bye");
+}
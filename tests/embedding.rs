@@ -0,0 +1,70 @@
+use weaver::{eval, VMError, Value, Weave};
+
+#[test]
+fn eval_returns_the_result() {
+    let result = eval("2 + 3").unwrap();
+    assert_eq!(result, Value::Integer(5));
+    assert_eq!(result.as_number(), Some(5.0));
+}
+
+#[test]
+fn negation_keeps_integers_and_floats_apart() {
+    assert_eq!(eval("-5").unwrap(), Value::Integer(-5));
+    assert_eq!(eval("-5.0").unwrap(), Value::Float(-5.0));
+}
+
+#[test]
+fn eval_reports_errors() {
+    assert!(matches!(eval("1 +"), Err(VMError::CompilationError { .. })));
+    assert!(matches!(eval("undefined_thing"), Err(VMError::RuntimeError { .. })));
+}
+
+#[test]
+fn weave_keeps_globals_between_runs() {
+    let mut weave = Weave::new();
+    weave.run("greeting = \"hello\"").unwrap();
+    weave.run("count = len(greeting) * 2").unwrap();
+
+    assert_eq!(weave.get_global("count"), Some(Value::Integer(10)));
+    assert_eq!(weave.get_global("greeting").unwrap().as_str(), Some("hello"));
+    assert_eq!(weave.get_global("missing"), None);
+
+    let list = weave.run("[count, greeting]").unwrap();
+    assert_eq!(list, Value::Array(vec![Value::Integer(10), Value::String("hello".to_string())]));
+    assert_eq!(list.to_string(), "[10, hello]");
+}
+
+#[test]
+fn exit_returns_to_the_embedder() {
+    let mut weave = Weave::new();
+    assert!(matches!(weave.run("exit(4)"), Err(VMError::Exit(4))));
+    assert_eq!(weave.run("2 * 3").unwrap(), Value::Integer(6));
+}
+
+#[test]
+fn constants_stay_constant_between_runs() {
+    let mut weave = Weave::new();
+    weave.run("const LIMIT = 10").unwrap();
+
+    assert!(matches!(weave.run("LIMIT = 20"), Err(VMError::CompilationError { .. })));
+    assert_eq!(weave.get_global("LIMIT"), Some(Value::Integer(10)));
+}
+
+#[test]
+fn results_outlive_later_runs() {
+    let mut weave = Weave::new();
+    let name = weave.run("\"na\" + \"me\"").unwrap();
+    let map = weave.run("m = {\"a\": [1, 2.5], \"b\": null}\nm").unwrap();
+    let function = weave.run("fn sq(n) { n * n }\nsq").unwrap();
+
+    // Enough garbage to make the collector run, freeing everything the results came from
+    weave.run("m = null\ni = 0\nwhile i < 5000 { x = \"s\" + i; i = i + 1 }").unwrap();
+
+    assert_eq!(name.as_str(), Some("name"));
+    assert_eq!(map.to_string(), "{a: [1, 2.5], b: null}");
+    assert_eq!(function, Value::Function("<fn sq(1)>".to_string()));
+
+    // An array that contains itself stops where it repeats
+    let nested = weave.run("a = [1]\na[0] = a\na").unwrap();
+    assert_eq!(nested, Value::Array(vec![Value::Null]));
+}